@@ -0,0 +1,2 @@
+drop index if exists uniq_accounts_account;
+alter table accounts drop column if exists disabled;
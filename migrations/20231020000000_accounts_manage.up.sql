@@ -0,0 +1,3 @@
+-- 账号管理: 禁用账号 + 账号名唯一
+alter table accounts add column disabled bool not null default false;
+create unique index uniq_accounts_account on accounts (account);
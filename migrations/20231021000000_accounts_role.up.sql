@@ -0,0 +1,4 @@
+-- 角色: admin(管理员) / sales(业务部) / warehouse(仓库) / workshop(车间)
+alter table accounts add column role text not null default 'sales';
+-- 已有账号保持原来的全部权限
+update accounts set role = 'admin';
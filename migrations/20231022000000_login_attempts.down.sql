@@ -0,0 +1,3 @@
+alter table accounts drop column if exists unlocked_at;
+alter table accounts drop column if exists locked_at;
+drop table if exists login_attempts;
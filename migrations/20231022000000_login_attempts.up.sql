@@ -0,0 +1,16 @@
+-- 登录尝试记录, 用于限制登录失败次数
+create table login_attempts
+(
+    id          serial PRIMARY KEY,
+    account     text        not null default '', -- 登录时填写的账号
+    account_id  integer,                         -- 账号存在时记录账号id
+    ip          text        not null default '', -- 客户端ip
+    success     bool        not null default false,
+    create_time TIMESTAMPTZ not null default now()
+);
+create index idx_login_attempts_account_id on login_attempts (account_id, create_time);
+create index idx_login_attempts_ip on login_attempts (ip, create_time);
+
+-- 连续登录失败后锁定账号, 由管理员解锁
+alter table accounts add column locked_at TIMESTAMPTZ;
+alter table accounts add column unlocked_at TIMESTAMPTZ;
@@ -0,0 +1,15 @@
+-- 操作日志: 记录每次修改数据的操作人和修改前后的数据
+create table audit_log
+(
+    id          serial PRIMARY KEY,
+    account_id  integer     not null default 0,  -- 操作人
+    entity      text        not null default '', -- 数据类型(表名)
+    entity_id   integer     not null default 0,  -- 数据id
+    action      text        not null default '', -- 操作: create/update/delete/...
+    before      jsonb,                           -- 修改前
+    after       jsonb,                           -- 修改后
+    create_time TIMESTAMPTZ not null default now()
+);
+create index idx_audit_log_account_id on audit_log (account_id, create_time);
+create index idx_audit_log_entity on audit_log (entity, entity_id, create_time);
+create index idx_audit_log_create_time on audit_log (create_time);
@@ -0,0 +1,2 @@
+drop index if exists idx_orders_status;
+alter table orders drop column if exists status;
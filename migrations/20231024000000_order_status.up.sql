@@ -0,0 +1,8 @@
+-- 订单状态: 0 草稿 / 1 已确认 / 2 已发货 / 3 已完成 / 4 已取消
+alter table orders add column status integer not null default 0;
+-- 已有的正常订单视为已确认, 导入订单只是历史记录, 视为已完成
+update orders set status = 1 where tp = 0;
+update orders set status = 3 where tp = 1;
+create index idx_orders_status on orders (status);
+
+-- item_inout_bucket.via 增加 order: 订单发货出库 / 已发货订单取消后的退回入库
@@ -0,0 +1,2 @@
+drop index if exists idx_orders_order_no;
+drop table if exists order_no_sequences;
@@ -0,0 +1,24 @@
+-- 订单号: 前缀 + 日期 + 当天序号, 例: SO202310250001
+create table order_no_sequences
+(
+    day date    PRIMARY KEY,
+    seq integer not null default 0 -- 当天已经分配的最大序号
+);
+
+-- 给已有的订单补上订单号
+-- 前缀要和 .env 的 ORDER_NO_PREFIX 一致(默认SO), 配置了别的前缀时执行前把下面的 'SO' 改掉
+update orders o
+set order_no = 'SO' || to_char(t.day, 'YYYYMMDD') || lpad(t.seq::text, greatest(4, length(t.seq::text)), '0')
+from (select id,
+             create_time::date                                               as day,
+             row_number() over (partition by create_time::date order by id) as seq
+      from orders) t
+where o.id = t.id
+  and o.order_no = '';
+
+insert into order_no_sequences (day, seq)
+select create_time::date, count(1)
+from orders
+group by create_time::date;
+
+create unique index idx_orders_order_no on orders (order_no);
@@ -0,0 +1,2 @@
+drop table if exists import_job_entities;
+drop table if exists import_jobs;
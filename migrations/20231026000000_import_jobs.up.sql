@@ -0,0 +1,29 @@
+-- excel导入记录: 每次上传一条, 保存原文件, 方便追查数据来源
+create table import_jobs
+(
+    id            serial PRIMARY KEY,
+    account_id    integer     not null default 0,  -- 上传人
+    tp            integer     not null default 0,  -- 导入类型: 0商品 1胚 3订单 4旧订单
+    customer_id   integer     not null default 0,
+    filename      text        not null default '', -- 上传时的文件名
+    file_path     text        not null default '', -- 保存的原文件
+    row_count     integer     not null default 0,  -- excel数据行数
+    created_count integer     not null default 0,  -- 新增数
+    updated_count integer     not null default 0,  -- 更新数
+    status        integer     not null default 0,  -- 0导入中 1成功 2失败 3已撤销
+    errors        jsonb,                           -- 失败原因
+    create_time   TIMESTAMPTZ not null default now(),
+    finish_time   TIMESTAMPTZ
+);
+create index idx_import_jobs_account_id on import_jobs (account_id, create_time);
+
+-- 导入创建的数据: entity是表名
+create table import_job_entities
+(
+    id        serial PRIMARY KEY,
+    job_id    integer not null default 0,
+    entity    text    not null default '',
+    entity_id integer not null default 0
+);
+create index idx_import_job_entities_job_id on import_job_entities (job_id);
+create index idx_import_job_entities_entity on import_job_entities (entity, entity_id);
@@ -0,0 +1,2 @@
+drop table if exists customer_excel_template;
+drop table if exists excel_templates;
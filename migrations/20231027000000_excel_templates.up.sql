@@ -0,0 +1,21 @@
+-- excel导入模版: 不同客户的订单表格, 表头文字对应到字段
+create table excel_templates
+(
+    id                  serial PRIMARY KEY,
+    name                text        not null default '',
+    header_row          integer     not null default 3,    -- 表头在第几行
+    data_start_row      integer     not null default 4,    -- 数据从第几行开始
+    columns             jsonb       not null default '{}', -- 表头文字 -> 字段, 例: {"电镀&颜色": "color"}
+    order_date_regex    text        not null default '',   -- 表头上面的行里找订单日期, 第一个分组是日期, 空的用默认的
+    delivery_date_regex text        not null default '',   -- 出货日期, 同上
+    create_time         TIMESTAMPTZ not null default now()
+);
+
+-- 客户用哪个导入模版
+create table customer_excel_template
+(
+    id          serial PRIMARY KEY,
+    customer_id integer not null default 0,
+    template_id integer not null default 0
+);
+create unique index idx_customer_excel_template_customer_id on customer_excel_template (customer_id);
@@ -0,0 +1,2 @@
+drop table if exists embryo_stock;
+drop table if exists item_stock;
@@ -0,0 +1,25 @@
+-- 库存余额, 和出入库记录在同一个事务里更新, 不用每次都 sum(item_inout)
+create table item_stock
+(
+    item_id     integer PRIMARY KEY,
+    count       integer     not null default 0,
+    update_time TIMESTAMPTZ not null default now()
+);
+
+create table embryo_stock
+(
+    embryo_id   integer PRIMARY KEY,
+    count       integer     not null default 0,
+    update_time TIMESTAMPTZ not null default now()
+);
+
+-- 按已有的出入库记录初始化
+insert into item_stock (item_id, count)
+select item_id, sum(count)
+from item_inout
+group by item_id;
+
+insert into embryo_stock (embryo_id, count)
+select embryo_id, sum(count)
+from embryo_inout
+group by embryo_id;
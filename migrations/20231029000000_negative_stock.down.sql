@@ -0,0 +1,2 @@
+alter table global_settings drop column if exists negative_stock_account_ids;
+alter table global_settings drop column if exists negative_stock_policy;
@@ -0,0 +1,3 @@
+-- 出库后库存不够时怎么处理: 0不允许 1允许但返回提示 2只允许下面的账号(返回提示)
+alter table global_settings add column negative_stock_policy integer not null default 0;
+alter table global_settings add column negative_stock_account_ids integer[] not null default '{}';
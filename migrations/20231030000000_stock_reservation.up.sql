@@ -0,0 +1,18 @@
+-- 已确认未发货的订单占用的库存, 发货或取消时删掉
+create table item_reservations
+(
+    id          SERIAL PRIMARY KEY,
+    order_id    integer     not null,
+    item_id     integer     not null,
+    count       integer     not null,
+    create_time TIMESTAMPTZ not null default now()
+);
+create unique index uniq_item_reservations_order_item on item_reservations (order_id, item_id);
+create index idx_item_reservations_item on item_reservations (item_id);
+
+-- 按已确认的订单初始化
+insert into item_reservations (order_id, item_id, count)
+select oi.order_id, oi.item_id, sum(oi.count)
+from order_items oi, orders o
+where oi.order_id = o.id and o.tp = 0 and o.status = 1
+group by oi.order_id, oi.item_id;
@@ -0,0 +1,22 @@
+-- 各仓库的库存合并回一个
+insert into item_stock (item_id, warehouse_id) select distinct item_id, 1 from item_stock on conflict do nothing;
+update item_stock s set count = t.count
+from (select item_id, sum(count)::int as count from item_stock group by item_id) t
+where s.item_id = t.item_id and s.warehouse_id = 1;
+delete from item_stock where warehouse_id <> 1;
+alter table item_stock drop constraint item_stock_pkey;
+alter table item_stock drop column if exists warehouse_id;
+alter table item_stock add primary key (item_id);
+
+insert into embryo_stock (embryo_id, warehouse_id) select distinct embryo_id, 1 from embryo_stock on conflict do nothing;
+update embryo_stock s set count = t.count
+from (select embryo_id, sum(count)::int as count from embryo_stock group by embryo_id) t
+where s.embryo_id = t.embryo_id and s.warehouse_id = 1;
+delete from embryo_stock where warehouse_id <> 1;
+alter table embryo_stock drop constraint embryo_stock_pkey;
+alter table embryo_stock drop column if exists warehouse_id;
+alter table embryo_stock add primary key (embryo_id);
+
+alter table embryo_inout_bucket drop column if exists warehouse_id;
+alter table item_inout_bucket drop column if exists warehouse_id;
+drop table if exists warehouses;
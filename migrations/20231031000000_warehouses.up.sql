@@ -0,0 +1,22 @@
+-- 仓库/存放位置: 门店, 后仓, 车间...
+create table warehouses
+(
+    id          SERIAL PRIMARY KEY,
+    name        text        not null default '',
+    notes       text        not null default '',
+    create_time TIMESTAMPTZ not null default now()
+);
+create unique index uniq_warehouses_name on warehouses (name);
+-- 已有的出入库记录都算在默认仓库
+insert into warehouses (name) values ('默认仓库');
+
+alter table item_inout_bucket add column warehouse_id integer not null default 1;
+alter table embryo_inout_bucket add column warehouse_id integer not null default 1;
+
+-- 库存按仓库分开记
+alter table item_stock add column warehouse_id integer not null default 1;
+alter table item_stock drop constraint item_stock_pkey;
+alter table item_stock add primary key (item_id, warehouse_id);
+alter table embryo_stock add column warehouse_id integer not null default 1;
+alter table embryo_stock drop constraint embryo_stock_pkey;
+alter table embryo_stock add primary key (embryo_id, warehouse_id);
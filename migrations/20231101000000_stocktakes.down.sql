@@ -0,0 +1,2 @@
+drop table if exists stocktake_items;
+drop table if exists stocktakes;
@@ -0,0 +1,28 @@
+-- 盘点: 进行中时录入盘点数量, 过账时按差异生成一次调整的出入库记录
+create table stocktakes
+(
+    id           SERIAL PRIMARY KEY,
+    warehouse_id integer     not null default 1,
+    account_id   integer     not null,
+    status       integer     not null default 0, -- 0 盘点中, 1 已过账, 2 已取消
+    notes        text        not null default '',
+    bucket_id    integer     not null default 0, -- 过账生成的出入库记录(via=stocktake)
+    create_time  TIMESTAMPTZ not null default now(),
+    close_time   TIMESTAMPTZ null
+);
+create index idx_stocktakes_bucket on stocktakes (bucket_id);
+-- 同一个仓库同时只能有一个盘点中的
+create unique index uniq_stocktakes_counting on stocktakes (warehouse_id) where status = 0;
+
+-- 盘点数量, 同一个商品录多次以最后一次为准; system_count 是录入时的账面库存
+create table stocktake_items
+(
+    id           SERIAL PRIMARY KEY,
+    stocktake_id integer     not null,
+    item_id      integer     not null,
+    counted      integer     not null,
+    system_count integer     not null default 0,
+    account_id   integer     not null,
+    update_time  TIMESTAMPTZ not null default now()
+);
+create unique index uniq_stocktake_items_item on stocktake_items (stocktake_id, item_id);
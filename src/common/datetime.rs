@@ -50,3 +50,30 @@ pub fn parse_date_with_regex(date: &str) -> Option<NaiveDate> {
 
     None
 }
+
+/// 年月日用 . - / 或者 年月日 隔开都可以, 例: 2023.12.11, 2023年12月11日
+pub fn parse_ymd(date: &str) -> Option<NaiveDate> {
+    let re = Regex::new(r"(\d{4})\s*[./\-年]\s*(\d{1,2})\s*[./\-月]\s*(\d{1,2})").unwrap();
+    let captures = re.captures(date)?;
+    let year = captures[1].parse::<i32>().ok()?;
+    let month = captures[2].parse::<u32>().ok()?;
+    let day = captures[3].parse::<u32>().ok()?;
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::datetime::parse_ymd;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_ymd() {
+        let date = NaiveDate::from_ymd_opt(2023, 12, 11);
+        assert_eq!(parse_ymd("2023.12.11"), date);
+        assert_eq!(parse_ymd("2023-12-11"), date);
+        assert_eq!(parse_ymd("出货日期: 2023/12/11"), date);
+        assert_eq!(parse_ymd("2023年12月11日"), date);
+        assert_eq!(parse_ymd("2023.13.11"), None);
+        assert_eq!(parse_ymd("12.11"), None);
+    }
+}
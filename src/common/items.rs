@@ -1,3 +1,12 @@
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+
 pub fn calculate_barcode(number: &str, color: i32, price: i32) -> String {
     format!("{}{:02}{:04}", number, color, price)
 }
+
+/// 图片url转成本地文件路径(上传和excel导入的图片都存在本地)
+pub fn image_url_to_path(url: &str) -> String {
+    url.strip_prefix(STORAGE_URL_PREFIX)
+        .map(|rest| format!("{}{}", STORAGE_FILE_PATH, rest))
+        .unwrap_or(url.to_string())
+}
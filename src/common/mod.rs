@@ -4,4 +4,6 @@ pub mod hashmap;
 pub mod items;
 pub mod list;
 pub mod log;
+pub mod money;
+pub mod password;
 pub mod string;
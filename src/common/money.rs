@@ -0,0 +1,88 @@
+const CN_DIGITS: [&str; 10] = ["零", "壹", "贰", "叁", "肆", "伍", "陆", "柒", "捌", "玖"];
+const CN_UNITS: [&str; 4] = ["", "拾", "佰", "仟"];
+const CN_GROUP_UNITS: [&str; 4] = ["", "万", "亿", "万亿"];
+
+/// 金额(分)转成人民币大写, 例: 123456 => 壹仟贰佰叁拾肆元伍角陆分
+pub fn money_to_chinese_uppercase(cents: i64) -> String {
+    if cents == 0 {
+        return "零元整".to_string();
+    }
+
+    let mut res = String::new();
+    if cents < 0 {
+        res.push('负');
+    }
+    let cents = cents.unsigned_abs();
+    let yuan = cents / 100;
+    let jiao = (cents / 10 % 10) as usize;
+    let fen = (cents % 10) as usize;
+
+    if yuan > 0 {
+        let digits = yuan
+            .to_string()
+            .bytes()
+            .map(|b| (b - b'0') as usize)
+            .collect::<Vec<_>>();
+        let len = digits.len();
+        let mut zero = false;
+        let mut group_non_zero = false;
+        for (i, digit) in digits.into_iter().enumerate() {
+            let pos = len - 1 - i;
+            if digit == 0 {
+                zero = true;
+            } else {
+                if zero {
+                    res.push_str(CN_DIGITS[0]);
+                }
+                res.push_str(CN_DIGITS[digit]);
+                res.push_str(CN_UNITS[pos % 4]);
+                zero = false;
+                group_non_zero = true;
+            }
+            if pos % 4 == 0 {
+                if group_non_zero {
+                    res.push_str(CN_GROUP_UNITS[pos / 4]);
+                }
+                group_non_zero = false;
+            }
+        }
+        res.push('元');
+    }
+
+    if jiao == 0 && fen == 0 {
+        res.push('整');
+        return res;
+    }
+    if jiao > 0 {
+        res.push_str(CN_DIGITS[jiao]);
+        res.push('角');
+    } else if yuan > 0 {
+        res.push_str(CN_DIGITS[0]);
+    }
+    if fen > 0 {
+        res.push_str(CN_DIGITS[fen]);
+        res.push('分');
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::money::money_to_chinese_uppercase;
+
+    #[test]
+    fn test_money_to_chinese_uppercase() {
+        assert_eq!(money_to_chinese_uppercase(0), "零元整");
+        assert_eq!(money_to_chinese_uppercase(5), "伍分");
+        assert_eq!(money_to_chinese_uppercase(1000), "壹拾元整");
+        assert_eq!(money_to_chinese_uppercase(1050), "壹拾元伍角");
+        assert_eq!(money_to_chinese_uppercase(100005), "壹仟元零伍分");
+        assert_eq!(
+            money_to_chinese_uppercase(123456),
+            "壹仟贰佰叁拾肆元伍角陆分"
+        );
+        assert_eq!(money_to_chinese_uppercase(10_001_000_000), "壹亿零壹万元整");
+        assert_eq!(money_to_chinese_uppercase(10_000_000_100), "壹亿零壹元整");
+        assert_eq!(money_to_chinese_uppercase(-2000), "负贰拾元整");
+    }
+}
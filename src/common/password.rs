@@ -0,0 +1,48 @@
+use crate::{ERPError, ERPResult};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+/// 已经hash过的密码都是 PHC 格式: $argon2id$v=19$...
+pub fn is_hashed(password: &str) -> bool {
+    password.starts_with("$argon2")
+}
+
+pub fn hash_password(password: &str) -> ERPResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| ERPError::Failed("密码加密失败".to_string()))
+}
+
+/// 兼容还未迁移的明文密码
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    if !is_hashed(stored) {
+        return password == stored;
+    }
+
+    match PasswordHash::new(stored) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::password::{hash_password, is_hashed, verify_password};
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hashed = hash_password("test").unwrap();
+        assert!(is_hashed(&hashed));
+        assert!(verify_password("test", &hashed));
+        assert!(!verify_password("wrong", &hashed));
+
+        // 明文
+        assert!(verify_password("test", "test"));
+        assert!(!verify_password("wrong", "test"));
+    }
+}
@@ -14,6 +14,18 @@ pub trait DatabaseTrait {
     fn get_pool(&self) -> &Pool<Postgres>;
 }
 
+impl Database {
+    /// 不立即建立连接, 供不访问数据库的测试使用
+    #[cfg(test)]
+    pub fn connect_lazy(database_url: &str) -> Result<Self, Error> {
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect_lazy(database_url)?;
+
+        Ok(Self { pool })
+    }
+}
+
 #[async_trait]
 impl DatabaseTrait for Database {
     async fn init() -> Result<Self, Error> {
@@ -8,11 +8,78 @@ pub const STORAGE_FILE_PATH: &str = "/home/debian/lkx-data/file";
 pub const STORAGE_URL_PREFIX: &str = "https://lkx-api.ligulfzhou.com/file";
 // pub const STORAGE_URL_PREFIX: &str = "http://localhost/file";
 
+/// 时间窗口(分钟)内同一账号登录失败达到次数后锁定账号
+pub const LOGIN_FAILURE_WINDOW_MINUTES: i32 = 15;
+pub const LOGIN_MAX_FAILURES_PER_ACCOUNT: i64 = 5;
+/// 时间窗口内同一ip登录失败达到次数后暂时拒绝该ip登录
+pub const LOGIN_MAX_FAILURES_PER_IP: i64 = 20;
+
+/// 操作日志的通用操作类型
+pub const AUDIT_CREATE: &str = "create";
+pub const AUDIT_UPDATE: &str = "update";
+pub const AUDIT_DELETE: &str = "delete";
+
+/// 订单状态: 草稿 -> 已确认 -> 已发货 -> 已完成, 未完成前都可以取消
+pub const ORDER_STATUS_DRAFT: i32 = 0;
+pub const ORDER_STATUS_CONFIRMED: i32 = 1;
+pub const ORDER_STATUS_SHIPPED: i32 = 2;
+pub const ORDER_STATUS_COMPLETED: i32 = 3;
+pub const ORDER_STATUS_CANCELLED: i32 = 4;
+
+/// 出库后库存不够时: 不允许 / 允许但返回提示 / 只允许指定的账号
+pub const NEGATIVE_STOCK_REJECT: i32 = 0;
+pub const NEGATIVE_STOCK_WARN: i32 = 1;
+pub const NEGATIVE_STOCK_ACCOUNTS: i32 = 2;
+
+/// 默认仓库, 没指定仓库的出入库都算在这里
+pub const DEFAULT_WAREHOUSE_ID: i32 = 1;
+
+/// 盘点状态: 盘点中 -> 已过账, 盘点中可以取消; 过账和取消后不能再改
+pub const STOCKTAKE_STATUS_COUNTING: i32 = 0;
+pub const STOCKTAKE_STATUS_POSTED: i32 = 1;
+pub const STOCKTAKE_STATUS_CANCELLED: i32 = 2;
+
+pub const IMPORT_JOB_STATUS_RUNNING: i32 = 0;
+pub const IMPORT_JOB_STATUS_SUCCEEDED: i32 = 1;
+pub const IMPORT_JOB_STATUS_FAILED: i32 = 2;
+pub const IMPORT_JOB_STATUS_ROLLED_BACK: i32 = 3;
+
+// 后台导入的阶段
+pub const IMPORT_PHASE_QUEUED: &str = "排队中";
+pub const IMPORT_PHASE_PARSING: &str = "解析中";
+pub const IMPORT_PHASE_IMAGES: &str = "保存图片";
+pub const IMPORT_PHASE_SAVING: &str = "写入数据库";
+pub const IMPORT_PHASE_DONE: &str = "完成";
+pub const IMPORT_PHASE_FAILED: &str = "失败";
+pub const IMPORT_PHASE_ROLLED_BACK: &str = "已撤销";
+
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_SALES: &str = "sales";
+pub const ROLE_WAREHOUSE: &str = "warehouse";
+pub const ROLE_WORKSHOP: &str = "workshop";
+
 lazy_static! {
     pub static ref STEP_TO_DEPARTMENT: HashMap<i32, &'static str> =
         vec![(1, "业务部"), (2, "仓库"), (3, "车间"),]
             .into_iter()
             .collect();
+    pub static ref ROLE_TO_DEPARTMENT: HashMap<&'static str, &'static str> = vec![
+        (ROLE_ADMIN, "管理员"),
+        (ROLE_SALES, "业务部"),
+        (ROLE_WAREHOUSE, "仓库"),
+        (ROLE_WORKSHOP, "车间"),
+    ]
+    .into_iter()
+    .collect();
+    pub static ref ORDER_STATUS_TO_NAME: HashMap<i32, &'static str> = vec![
+        (ORDER_STATUS_DRAFT, "草稿"),
+        (ORDER_STATUS_CONFIRMED, "已确认"),
+        (ORDER_STATUS_SHIPPED, "已发货"),
+        (ORDER_STATUS_COMPLETED, "已完成"),
+        (ORDER_STATUS_CANCELLED, "已取消"),
+    ]
+    .into_iter()
+    .collect();
     pub static ref SORTER_ORDER_TO_DB_SORTER_ORDER: HashMap<&'static str, &'static str> =
         vec![("descend", "desc"), ("ascend", "asc"),]
             .into_iter()
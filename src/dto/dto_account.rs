@@ -1,10 +1,17 @@
+use crate::constants::{ROLE_ADMIN, ROLE_TO_DEPARTMENT};
 use crate::model::account::AccountModel;
+use chrono::{DateTime, Utc};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AccountDto {
     pub id: i32,
     pub name: String,
     pub account: String,
+    pub disabled: bool,
+    pub role: String,
+    pub department: String,
+    pub locked: bool,
+    pub create_time: DateTime<Utc>,
 }
 
 impl AccountDto {
@@ -13,6 +20,81 @@ impl AccountDto {
             id: account.id,
             name: account.name,
             account: account.account,
+            disabled: account.disabled,
+            department: ROLE_TO_DEPARTMENT
+                .get(account.role.as_str())
+                .unwrap_or(&"")
+                .to_string(),
+            role: account.role,
+            locked: account.locked_at.is_some(),
+            create_time: account.create_time,
         }
     }
+
+    /// 管理员拥有所有权限
+    pub fn has_role(&self, roles: &[&str]) -> bool {
+        self.role == ROLE_ADMIN || roles.contains(&self.role.as_str())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginDto {
+    #[serde(flatten)]
+    pub account: AccountDto,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryParams {
+    pub name: Option<String>,
+
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAccountParams {
+    pub name: String,
+    pub account: String,
+    pub password: String,
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordParams {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordParams {
+    pub id: i32,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableAccountParams {
+    pub id: i32,
+    pub disabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoleParams {
+    pub id: i32,
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockAccountParams {
+    pub id: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginAttemptQueryParams {
+    pub account: Option<String>,
+    pub ip: Option<String>,
+    pub success: Option<bool>,
+
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
 }
@@ -0,0 +1,44 @@
+use crate::model::audit::AuditLogModel;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogDto {
+    pub id: i32,
+    pub account_id: i32,
+    pub account_name: String,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub create_time: DateTime<Utc>,
+}
+
+impl AuditLogDto {
+    pub fn from(audit_log: AuditLogModel, account_name: &str) -> AuditLogDto {
+        Self {
+            id: audit_log.id,
+            account_id: audit_log.account_id,
+            account_name: account_name.to_string(),
+            entity: audit_log.entity,
+            entity_id: audit_log.entity_id,
+            action: audit_log.action,
+            before: audit_log.before,
+            after: audit_log.after,
+            create_time: audit_log.create_time,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryParams {
+    pub account_id: Option<i32>,
+    pub entity: Option<String>,
+    pub entity_id: Option<i32>,
+    pub create_time_st: Option<String>,
+    pub create_time_ed: Option<String>,
+
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
@@ -1,3 +1,4 @@
+use crate::dto::dto_stock::WarehouseStockDto;
 use crate::model::embryo::{EmbryoInOutBucketModal, EmbryoModel};
 use chrono::{DateTime, Utc};
 use sqlx::FromRow;
@@ -14,10 +15,11 @@ pub struct EmbryoDto {
     pub number: String,
     pub create_time: DateTime<Utc>,
     pub count: i32,
+    pub warehouse_stocks: Vec<WarehouseStockDto>, // 各仓库的库存
 }
 
 impl EmbryoDto {
-    pub fn from(embryo: EmbryoModel, count: i32) -> Self {
+    pub fn from(embryo: EmbryoModel, count: i32, warehouse_stocks: Vec<WarehouseStockDto>) -> Self {
         Self {
             id: embryo.id,
             images: embryo.images,
@@ -28,6 +30,7 @@ impl EmbryoDto {
             number: embryo.number,
             create_time: embryo.create_time,
             count,
+            warehouse_stocks,
         }
     }
 }
@@ -58,6 +61,7 @@ pub struct EmbryoInOutBucketDto {
     pub account: String,            // 经手账号 名
     pub in_true_out_false: bool,    // 增加还是减少
     pub via: String,                // 规格
+    pub warehouse_id: i32,          // 仓库id
     pub create_time: DateTime<Utc>, // 创建时间
 
     pub total_count: i32,
@@ -80,6 +84,7 @@ impl EmbryoInOutBucketDto {
             account: account_name.to_string(),
             in_true_out_false: item_inout_bucket.in_true_out_false,
             via: item_inout_bucket.via,
+            warehouse_id: item_inout_bucket.warehouse_id,
             create_time: item_inout_bucket.create_time,
             total_count,
             total_sum,
@@ -130,6 +135,7 @@ pub struct InoutParams {
     pub in_out: bool,
     // pub via: String, todo: 应该是不需要，肯定是form
     pub count: i32,
+    pub warehouse_id: Option<i32>, // 仓库, 不传是默认仓库
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use umya_spreadsheet::Image;
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct ItemExcelDto<'a> {
+    pub row: u32, // excel里的行号
     pub index: i32,
     pub images: Vec<String>, // 图片
     #[serde(skip_serializing, skip_deserializing)]
@@ -23,6 +25,7 @@ pub struct ItemExcelDto<'a> {
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct EmbryoExcelDto {
+    pub row: u32,            // excel里的行号
     pub images: Vec<String>, // 图片
     pub name: String,        // 名称
     pub color: String,       // 颜色
@@ -35,6 +38,7 @@ pub struct EmbryoExcelDto {
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct OrderExcelDto {
+    pub row: u32,            // excel里的行号
     pub index: i32,          // 序号
     pub number: String,      // 货号
     pub images: Vec<String>, // 图片
@@ -47,3 +51,76 @@ pub struct OrderExcelDto {
     pub total: i32,          // 金额
     pub notes: String,       // 备注
 }
+
+/// 盘点表里的一行, 有条码按条码找商品, 没有按编号+颜色
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct StocktakeExcelDto {
+    pub row: u32,        // excel里的行号
+    pub number: String,  // 编号
+    pub color: String,   // 颜色
+    pub barcode: String, // 条码
+    pub counted: i32,    // 盘点数量
+}
+
+/// 导入预览里的一行
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct ImportPreviewRowDto {
+    pub row: u32,
+    pub number: String,
+    pub name: String,
+    pub color: String,
+    pub barcode: String,
+    pub count: i32,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct ImportErrorDto {
+    pub sheet: String,
+    pub row: u32,       // 0: 不是某一行的问题
+    pub column: String, // 表头
+    pub value: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ImportErrorDto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.row {
+            0 => write!(f, "{}", self.message),
+            row => write!(f, "第{}行 {} {}", row, self.column, self.message),
+        }
+    }
+}
+
+/// dry_run导入的结果, 不写数据库
+#[derive(Debug, Serialize, Default)]
+pub struct ImportPreviewDto {
+    pub tp: i32,
+    pub create: Vec<ImportPreviewRowDto>,
+    pub update: Vec<ImportPreviewRowDto>,
+    pub skip: Vec<ImportPreviewRowDto>,
+    pub new_cates: Vec<String>, // 大类 或 大类/小类
+    pub new_colors: Vec<String>,
+    pub errors: Vec<ImportErrorDto>,
+    pub template: String, // 用到的导入模版, 空的是默认格式
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExcelTemplateEditParams {
+    pub id: i32,
+    pub name: String,
+    pub header_row: i32,
+    pub data_start_row: i32,
+    pub columns: HashMap<String, String>, // 表头文字 -> 字段
+    #[serde(default)]
+    pub order_date_regex: String,
+    #[serde(default)]
+    pub delivery_date_regex: String,
+}
+
+/// template_id 为0时客户不再用模版
+#[derive(Debug, Deserialize)]
+pub struct CustomerExcelTemplateParams {
+    pub customer_id: i32,
+    pub template_id: i32,
+}
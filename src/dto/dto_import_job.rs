@@ -0,0 +1,87 @@
+use crate::dto::dto_stock::InsufficientStockDto;
+use crate::model::import_job::ImportJobModel;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub struct ImportJobDto {
+    pub id: i32,
+    pub account_id: i32,
+    pub account_name: String,
+    pub tp: i32,
+    pub customer_id: i32,
+    pub filename: String,
+    pub row_count: i32,
+    pub created_count: i32,
+    pub updated_count: i32,
+    pub status: i32,
+    pub errors: Option<Value>,
+    pub create_time: DateTime<Utc>,
+    pub finish_time: Option<DateTime<Utc>>,
+}
+
+impl ImportJobDto {
+    pub fn from(job: ImportJobModel, account_name: &str) -> ImportJobDto {
+        Self {
+            id: job.id,
+            account_id: job.account_id,
+            account_name: account_name.to_string(),
+            tp: job.tp,
+            customer_id: job.customer_id,
+            filename: job.filename,
+            row_count: job.row_count,
+            created_count: job.created_count,
+            updated_count: job.updated_count,
+            status: job.status,
+            errors: job.errors,
+            create_time: job.create_time,
+            finish_time: job.finish_time,
+        }
+    }
+}
+
+/// 撤销导入的结果
+#[derive(Debug, Serialize)]
+pub struct ImportRollbackDto {
+    pub deleted: HashMap<String, i32>, // 删掉的条数, 按表名
+    pub updated_count: i32,            // 导入时修改的已有商品数, 撤销不会还原
+    pub msg: String,
+}
+
+/// 导入完成后的统计
+#[derive(Debug, Default)]
+pub struct ImportCounts {
+    pub row_count: i32,
+    pub created_count: i32,
+    pub updated_count: i32,
+    pub warnings: Vec<InsufficientStockDto>, // 库存不足但允许出库的商品
+}
+
+/// 导入进度: 当前阶段和这个阶段处理到第几行
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportProgressDto {
+    pub id: i32,
+    pub status: i32,
+    pub phase: String,
+    pub processed: i32,
+    pub total: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryParams {
+    pub account_id: Option<i32>,
+    pub tp: Option<i32>,
+    pub customer_id: Option<i32>,
+    pub status: Option<i32>,
+    pub create_time_st: Option<String>,
+    pub create_time_ed: Option<String>,
+
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportJobParams {
+    pub id: i32,
+}
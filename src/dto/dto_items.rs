@@ -1,4 +1,5 @@
 use crate::dto::dto_embryo::EmbryoDto;
+use crate::dto::dto_stock::WarehouseStockDto;
 use crate::model::embryo::EmbryoModel;
 use crate::model::items::{ItemInOutBucketModal, ItemsModel};
 use chrono::{DateTime, Utc};
@@ -6,22 +7,25 @@ use chrono::{DateTime, Utc};
 #[derive(Debug, Serialize, Clone, sqlx::FromRow)]
 pub struct ItemsDto {
     pub id: i32,
-    pub images: Vec<String>,        // 商品图片
-    pub name: String,               // 产品名称
-    pub size: String,               // 规格
-    pub color: String,              // 颜色
-    pub cate1_id: i32,              // 大类ID
-    pub cate1: String,              // 大类名
-    pub cate2_id: i32,              // 小类ID
-    pub cate2: String,              // 小类名
-    pub unit: String,               // 单位
-    pub price: i32,                 // 标准售价
-    pub cost: i32,                  // 成本
-    pub notes: String,              // 备注
-    pub number: String,             // 货号
-    pub barcode: String,            // 条码
-    pub count: i32,                 // 库存数
-    pub create_time: DateTime<Utc>, // 创建时间
+    pub images: Vec<String>,                      // 商品图片
+    pub name: String,                             // 产品名称
+    pub size: String,                             // 规格
+    pub color: String,                            // 颜色
+    pub cate1_id: i32,                            // 大类ID
+    pub cate1: String,                            // 大类名
+    pub cate2_id: i32,                            // 小类ID
+    pub cate2: String,                            // 小类名
+    pub unit: String,                             // 单位
+    pub price: i32,                               // 标准售价
+    pub cost: i32,                                // 成本
+    pub notes: String,                            // 备注
+    pub number: String,                           // 货号
+    pub barcode: String,                          // 条码
+    pub count: i32,                               // 库存数(在库)
+    pub reserved: i32,                            // 已确认未发货的订单占用
+    pub available: i32,                           // 可用 = 在库 - 占用
+    pub warehouse_stocks: Vec<WarehouseStockDto>, // 各仓库的在库数
+    pub create_time: DateTime<Utc>,               // 创建时间
     pub embryo: Option<EmbryoDto>,
 }
 
@@ -29,6 +33,8 @@ impl ItemsDto {
     pub fn from(
         item: ItemsModel,
         count: i32,
+        reserved: i32,
+        warehouse_stocks: Vec<WarehouseStockDto>,
         cate1: &str,
         cate2: &str,
         embryo: Option<EmbryoDto>,
@@ -50,6 +56,9 @@ impl ItemsDto {
             number: item.number,
             barcode: item.barcode,
             count,
+            reserved,
+            available: count - reserved,
+            warehouse_stocks,
             create_time: item.create_time,
             embryo,
         }
@@ -63,6 +72,7 @@ pub struct ItemInOutBucketDto {
     pub account: String,            // 经手账号 名
     pub in_true_out_false: bool,    // 增加还是减少
     pub via: String,                // 规格
+    pub warehouse_id: i32,          // 仓库id
     pub create_time: DateTime<Utc>, // 创建时间
 
     pub total_count: i32,
@@ -85,6 +95,7 @@ impl ItemInOutBucketDto {
             account: account_name.to_string(),
             in_true_out_false: item_inout_bucket.in_true_out_false,
             via: item_inout_bucket.via,
+            warehouse_id: item_inout_bucket.warehouse_id,
             create_time: item_inout_bucket.create_time,
             total_count,
             total_sum,
@@ -264,6 +275,7 @@ pub struct InoutParams {
     pub id: i32,
     pub in_out: bool,
     pub count: i32,
+    pub warehouse_id: Option<i32>, // 仓库, 不传是默认仓库
 }
 
 #[derive(Deserialize, Debug)]
@@ -288,4 +300,13 @@ pub struct ItemStockOutItem {
 #[derive(Debug, Deserialize)]
 pub struct ItemStockOutMultiParams {
     pub items: Vec<ItemStockOutItem>,
+    pub warehouse_id: Option<i32>, // 仓库, 不传是默认仓库
+}
+
+/// 调拨: 从一个仓库出库, 另一个仓库入库
+#[derive(Debug, Deserialize)]
+pub struct ItemTransferParams {
+    pub from_warehouse_id: i32,
+    pub to_warehouse_id: i32,
+    pub items: Vec<ItemStockOutItem>,
 }
@@ -7,6 +7,7 @@ pub struct OrderDto {
     pub id: i32,
     pub order_no: String,
     pub tp: i32,
+    pub status: i32,
     pub account_id: i32,
     pub account: String,
     pub customer_id: i32,
@@ -27,6 +28,7 @@ pub struct OrderItemDto {
     pub color: String,
     pub name: String,
     pub number: String,
+    pub unit: String,
     pub count: i32,
     pub origin_price: i32,
     pub price: i32,
@@ -50,6 +52,8 @@ pub struct ImportedOrderDetailDto {
 #[derive(Debug, Serialize, FromRow)]
 pub struct OrderInListDto {
     pub id: i32,
+    pub order_no: String,
+    pub status: i32,
     pub account_id: i32,
     pub account: String,
     pub customer_id: i32,
@@ -71,6 +75,8 @@ pub struct QueryParams {
     pub account_id: i32,
     pub create_time_st: String,
     pub create_time_ed: String,
+    pub status: Option<i32>,
+    pub order_no: Option<String>,
 
     pub page: Option<i32>,
     pub page_size: Option<i32>,
@@ -87,6 +93,12 @@ impl QueryParams {
         if !self.create_time_ed.is_empty() && !self.create_time_st.is_empty() {
             return false;
         }
+        if self.status.is_some() {
+            return false;
+        }
+        if self.order_no.as_deref().is_some_and(|s| !s.is_empty()) {
+            return false;
+        }
         true
     }
 }
@@ -110,7 +122,22 @@ pub struct CreateOrderParams {
     // pub delivery_date: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct EditOrderParams {
+    pub id: i32,
+    pub customer_id: i32,
+    pub order_date: NaiveDate,
+    pub delivery_date: NaiveDate,
+    pub items: Vec<OrderItemsParams>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DeleteOrderParams {
     pub id: i32,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateOrderStatusParams {
+    pub id: i32,
+    pub warehouse_id: Option<i32>, // 发货的仓库, 不传是默认仓库
+}
@@ -9,6 +9,8 @@ pub struct ColorEditParams {
 pub struct GlobalSettingsUpdateParams {
     pub units: Option<Vec<String>>,
     pub accounts: Option<Vec<String>>,
+    pub negative_stock_policy: Option<i32>,
+    pub negative_stock_account_ids: Option<Vec<i32>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -16,3 +18,10 @@ pub struct CustomerTypeEditParams {
     pub id: i32,
     pub ty_pe: String,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct WarehouseEditParams {
+    pub id: i32,
+    pub name: String,
+    pub notes: String,
+}
@@ -0,0 +1,68 @@
+use crate::dto::dto_items::ItemsDto;
+
+/// 商品库存: 在库, 订单占用, 可用
+#[derive(Debug, Serialize, Clone)]
+pub struct ItemStockDto {
+    pub item_id: i32,
+    pub number: String,
+    pub name: String,
+    pub color: String,
+    pub count: i32,                               // 在库
+    pub reserved: i32,                            // 已确认未发货的订单占用
+    pub available: i32,                           // 可用
+    pub warehouse_stocks: Vec<WarehouseStockDto>, // 各仓库的在库数
+}
+
+impl ItemStockDto {
+    pub fn from(item: &ItemsDto) -> Self {
+        Self {
+            item_id: item.id,
+            number: item.number.clone(),
+            name: item.name.clone(),
+            color: item.color.clone(),
+            count: item.count,
+            reserved: item.reserved,
+            available: item.available,
+            warehouse_stocks: item.warehouse_stocks.clone(),
+        }
+    }
+}
+
+/// 某个仓库的库存
+#[derive(Debug, Serialize, Clone)]
+pub struct WarehouseStockDto {
+    pub warehouse_id: i32,
+    pub warehouse: String, // 仓库名
+    pub count: i32,
+}
+
+/// 出库时库存不够的商品
+#[derive(Debug, Serialize, Clone)]
+pub struct InsufficientStockDto {
+    pub item_id: i32,
+    pub number: String,
+    pub color: String,
+    pub requested: i32, // 要出库的数量
+    pub available: i32, // 出库前的可用库存: 库存 - 其他订单占用
+}
+
+impl std::fmt::Display for InsufficientStockDto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({}) 出库{} 可用{}",
+            self.number, self.color, self.requested, self.available
+        )
+    }
+}
+
+/// 库存余额和出入库记录对不上的
+#[derive(Debug, Serialize, Clone)]
+pub struct StockDriftDto {
+    pub id: i32, // 商品/库存胚id
+    pub number: String,
+    pub color: String,
+    pub warehouse_id: i32,
+    pub expected: i32, // 按出入库记录算出来的
+    pub actual: i32,   // 库存表里的
+}
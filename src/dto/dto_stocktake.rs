@@ -0,0 +1,106 @@
+use crate::model::stocktake::StocktakeModel;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize)]
+pub struct StocktakeDto {
+    pub id: i32,
+    pub warehouse_id: i32,
+    pub warehouse: String, // 仓库名
+    pub account_id: i32,
+    pub account_name: String,
+    pub status: i32,
+    pub notes: String,
+    pub bucket_id: i32,
+    pub item_count: i32, // 已盘点的商品数
+    pub create_time: DateTime<Utc>,
+    pub close_time: Option<DateTime<Utc>>,
+}
+
+impl StocktakeDto {
+    pub fn from(
+        stocktake: StocktakeModel,
+        warehouse: &str,
+        account_name: &str,
+        item_count: i32,
+    ) -> StocktakeDto {
+        Self {
+            id: stocktake.id,
+            warehouse_id: stocktake.warehouse_id,
+            warehouse: warehouse.to_string(),
+            account_id: stocktake.account_id,
+            account_name: account_name.to_string(),
+            status: stocktake.status,
+            notes: stocktake.notes,
+            bucket_id: stocktake.bucket_id,
+            item_count,
+            create_time: stocktake.create_time,
+            close_time: stocktake.close_time,
+        }
+    }
+}
+
+/// 盘点的商品和差异
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct StocktakeItemDto {
+    pub item_id: i32,
+    pub number: String,
+    pub name: String,
+    pub color: String,
+    pub barcode: String,
+    pub counted: i32,      // 盘点数量
+    pub system_count: i32, // 账面库存: 录入盘点数量时这个仓库的库存
+    pub variance: i32,     // 差异 = 盘点数量 - 账面库存, 正数盘盈, 负数盘亏
+}
+
+/// 盘点表的一行: 仓库里有库存的和已经盘过的商品
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct StocktakeSheetRowDto {
+    pub number: String,
+    pub name: String,
+    pub color: String,
+    pub barcode: String,
+    pub system_count: i32,
+    pub counted: Option<i32>, // 没录过的为空
+}
+
+/// 盘点详情
+#[derive(Debug, Serialize)]
+pub struct StocktakeDetailDto {
+    pub stocktake: StocktakeDto,
+    pub items: Vec<StocktakeItemDto>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryParams {
+    pub warehouse_id: Option<i32>,
+    pub status: Option<i32>,
+
+    pub page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StocktakeParams {
+    pub id: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StocktakeStartParams {
+    pub warehouse_id: Option<i32>, // 仓库, 不传是默认仓库
+    pub notes: Option<String>,
+}
+
+/// 录入盘点数量, 同一个商品再录会覆盖之前的
+#[derive(Debug, Deserialize)]
+pub struct StocktakeCountParams {
+    pub id: i32,
+    pub items: Vec<StocktakeCountItem>,
+}
+
+/// item_id 和 barcode(扫码) 传一个
+#[derive(Debug, Deserialize)]
+pub struct StocktakeCountItem {
+    pub item_id: Option<i32>,
+    pub barcode: Option<String>,
+    pub counted: i32,
+}
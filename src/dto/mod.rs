@@ -1,11 +1,15 @@
 pub mod dto_account;
+pub mod dto_audit;
 pub mod dto_cates;
 pub mod dto_customer;
 pub mod dto_embryo;
 pub mod dto_excel;
+pub mod dto_import_job;
 pub mod dto_items;
 pub mod dto_orders;
 pub mod dto_settings;
+pub mod dto_stock;
+pub mod dto_stocktake;
 
 #[derive(Deserialize, Debug)]
 pub struct GenericDeleteParams {
@@ -1,8 +1,11 @@
+use crate::dto::dto_excel::ImportErrorDto;
+use crate::dto::dto_stock::InsufficientStockDto;
 use axum::extract::rejection::{JsonRejection, QueryRejection};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use itertools::Itertools;
 use sqlx::error::Error as SqlxError;
 use std::io::Error as IOError;
 use thiserror::Error;
@@ -24,6 +27,15 @@ pub enum ERPError {
     #[error("账号不存在")]
     AccountNotFound,
 
+    #[error("账号已被禁用")]
+    AccountDisabled,
+
+    #[error("登录失败次数过多, 账号已被锁定, 请联系管理员解锁")]
+    AccountLocked,
+
+    #[error("登录失败次数过多, 请{}分钟后再试", .0)]
+    TooManyLoginAttempts(i32),
+
     #[error("IOError: {:?}", .0)]
     IOError(#[from] IOError),
 
@@ -48,6 +60,9 @@ pub enum ERPError {
     #[error("Excel数据有误: {:?}", .0)]
     ExcelError(String),
 
+    #[error("Excel数据有误: {}", .0.iter().join("; "))]
+    ExcelRowErrors(Vec<ImportErrorDto>),
+
     #[error("json参数错误: {:?}", .0)]
     JsonExtractorRejection(#[from] JsonRejection),
 
@@ -65,6 +80,9 @@ pub enum ERPError {
 
     #[error("数据冲突: {}", .0)]
     Collision(String),
+
+    #[error("库存不足: {}", .0.iter().join("; "))]
+    InsufficientStock(Vec<InsufficientStockDto>),
 }
 
 impl IntoResponse for ERPError {
@@ -74,18 +92,22 @@ impl IntoResponse for ERPError {
         let msg = self.to_string();
 
         let code = match self {
-            ERPError::NotAuthorized => 401,
+            ERPError::NotAuthorized | ERPError::AccountDisabled => 401,
             _ => 1,
         };
 
-        (
-            StatusCode::OK,
-            serde_json::json!({
-                "code": code, // failed code is always 1
-                "msg": msg
-            })
-            .to_string(),
-        )
-            .into_response()
+        let mut body = serde_json::json!({
+            "code": code, // failed code is always 1
+            "msg": msg
+        });
+        // 表格每一行的错误单独返回, 方便前端定位
+        match self {
+            ERPError::ExcelRowErrors(errors) => body["errors"] = serde_json::json!(errors),
+            // 库存不够的商品单独返回
+            ERPError::InsufficientStock(items) => body["items"] = serde_json::json!(items),
+            _ => {}
+        }
+
+        (StatusCode::OK, body.to_string()).into_response()
     }
 }
@@ -0,0 +1,159 @@
+use crate::dto::dto_excel::ImportErrorDto;
+use crate::{ERPError, ERPResult};
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use umya_spreadsheet::*;
+
+/// 读取xlsx, 不是xlsx或者文件损坏时返回错误(umya碰到有些坏文件会直接panic)
+pub fn read_xlsx(file_path: &str) -> ERPResult<Spreadsheet> {
+    tracing::info!("file_path: {file_path}");
+    let path = std::path::Path::new(file_path);
+    match catch_unwind(AssertUnwindSafe(|| reader::xlsx::read(path))) {
+        Ok(Ok(book)) => Ok(book),
+        Ok(Err(e)) => Err(ERPError::ExcelError(format!("不是有效的xlsx文件: {:?}", e))),
+        Err(_) => Err(ERPError::ExcelError("xlsx文件已损坏".to_string())),
+    }
+}
+
+/// 第一个sheet的名字, 只读目录不解析内容
+pub fn get_first_sheet_name(file_path: &str) -> String {
+    let path = std::path::Path::new(file_path);
+    catch_unwind(AssertUnwindSafe(|| reader::xlsx::lazy_read(path)))
+        .ok()
+        .and_then(|book| book.ok())
+        .and_then(|book| {
+            book.get_sheet_collection_no_check()
+                .first()
+                .map(|sheet| sheet.get_name().to_string())
+        })
+        .unwrap_or_default()
+}
+
+/// 第row行 1..=cols 列的值, 去掉首尾空格, 下标是 列号-1
+pub fn get_row_values(sheet: &Worksheet, row: u32, cols: u32) -> Vec<String> {
+    (1..cols + 1)
+        .map(|j| {
+            sheet
+                .get_cell((j, row))
+                .map(|cell| cell.get_raw_value().to_string().trim().to_string())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// 订单表格: 空行, 第一列不是序号(合计等), 或者数量和单价都为空的行, 表示商品结束了
+pub fn is_end_of_items(values: &[String], count_j: usize, price_j: usize) -> bool {
+    !values[0].is_empty() && values[0].parse::<i32>().is_err()
+        || values[count_j - 1].is_empty() && values[price_j - 1].is_empty()
+}
+
+/// 收集每一行的问题, 解析完一起返回
+pub struct RowErrors<'a> {
+    sheet: String,
+    j_to_name: &'a HashMap<i32, &'static str>,
+    errors: Vec<ImportErrorDto>,
+}
+
+impl<'a> RowErrors<'a> {
+    pub fn new(sheet: &str, j_to_name: &'a HashMap<i32, &'static str>) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            j_to_name,
+            errors: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: u32, j: u32, value: &str, message: &str) {
+        self.errors.push(ImportErrorDto {
+            sheet: self.sheet.clone(),
+            row,
+            column: self.j_to_name.get(&(j as i32)).unwrap_or(&"").to_string(),
+            value: value.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// NONE_NULLABLE_JS 里的列不能为空
+    pub fn check_required(&mut self, row: u32, j: u32, value: &str, none_nullable_js: &[i32]) {
+        if value.is_empty() && none_nullable_js.contains(&(j as i32)) {
+            self.push(row, j, value, "不能为空");
+        }
+    }
+
+    /// 解析不了的记一条错误, 返回0
+    pub fn parse_f32(&mut self, row: u32, j: u32, value: &str) -> f32 {
+        if value.is_empty() {
+            return 0.0;
+        }
+        value.parse::<f32>().unwrap_or_else(|_| {
+            self.push(row, j, value, "必须是数字");
+            0.0
+        })
+    }
+
+    /// 解析不了的记一条错误, 返回0
+    pub fn parse_i32(&mut self, row: u32, j: u32, value: &str) -> i32 {
+        if value.is_empty() {
+            return 0;
+        }
+        value.parse::<i32>().unwrap_or_else(|_| {
+            self.push(row, j, value, "必须是整数");
+            0
+        })
+    }
+
+    pub fn into_result(self) -> ERPResult<()> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(ERPError::ExcelRowErrors(self.errors)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::common::{is_end_of_items, RowErrors};
+    use crate::excel::parse_orders::{J_TO_NAME, NONE_NULLABLE_JS};
+    use crate::ERPError;
+
+    #[test]
+    fn test_row_errors() {
+        let mut errors = RowErrors::new("Sheet1", &J_TO_NAME);
+        assert!(errors.parse_f32(4, 9, "1.5") == 1.5);
+        errors.check_required(4, 5, "", &NONE_NULLABLE_JS);
+        errors.check_required(4, 11, "", &NONE_NULLABLE_JS);
+        assert_eq!(errors.parse_i32(5, 1, "a"), 0);
+
+        match errors.into_result() {
+            Err(ERPError::ExcelRowErrors(errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert_eq!(errors[0].row, 4);
+                assert_eq!(errors[0].column, "名称");
+                assert_eq!(errors[1].column, "序号");
+                assert_eq!(errors[1].value, "a");
+            }
+            _ => panic!("should have row errors"),
+        }
+    }
+
+    #[test]
+    fn test_is_end_of_items() {
+        let row = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert!(!is_end_of_items(
+            &row(&["1", "A1", "", "", "", "", "2", "", "3.5", "", ""]),
+            7,
+            9
+        ));
+        assert!(!is_end_of_items(
+            &row(&["", "", "", "", "", "", "2", "", "", "", ""]),
+            7,
+            9
+        ));
+        assert!(is_end_of_items(
+            &row(&["合计", "", "", "", "", "", "2", "", "", "", ""]),
+            7,
+            9
+        ));
+        assert!(is_end_of_items(&row(&[""; 11]), 7, 9));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::dto::dto_items::ItemsDto;
+use crate::excel::images::add_image;
+use crate::excel::parse_items::J_TO_NAME;
+use crate::{ERPError, ERPResult};
+use umya_spreadsheet::*;
+
+/// 库存放在导入格式后面的一列, 导入时不读
+const STOCK_COL: u32 = 16;
+
+/// 按导入商品的格式导出: 第6行表头, 第7行开始是商品
+/// 数量是导入时的入库数, 导出为0, 改完直接导回去只会更新商品信息, 不会重复入库
+pub fn export_items(items: &[ItemsDto]) -> ERPResult<Vec<u8>> {
+    let mut book = new_file();
+    let sheet = book
+        .get_sheet_mut(&0)
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
+
+    sheet.get_cell_mut((1, 1)).set_value("商品目录");
+    for (j, name) in J_TO_NAME.iter() {
+        sheet.get_cell_mut((*j as u32, 6)).set_value(*name);
+    }
+    sheet.get_cell_mut((STOCK_COL, 6)).set_value("库存");
+    sheet.get_column_dimension_mut("C").set_width(12.0);
+
+    for (row, item) in (7..).zip(items.iter()) {
+        sheet.get_row_dimension_mut(&row).set_height(62.0);
+        sheet.get_cell_mut((1, row)).set_value_number(row - 6);
+        sheet.get_cell_mut((2, row)).set_value(&item.number);
+        sheet.get_cell_mut((4, row)).set_value(&item.size);
+        sheet.get_cell_mut((5, row)).set_value(&item.name);
+        sheet.get_cell_mut((6, row)).set_value(&item.cate1);
+        sheet.get_cell_mut((7, row)).set_value(&item.cate2);
+        sheet.get_cell_mut((8, row)).set_value(&item.color);
+        // 条码按文本写, 避免被excel当成数字(丢掉前导0)
+        sheet.get_cell_mut((9, row)).set_value_string(&item.barcode);
+        sheet.get_cell_mut((10, row)).set_value_number(0);
+        sheet.get_cell_mut((11, row)).set_value(&item.unit);
+        sheet
+            .get_cell_mut((12, row))
+            .set_value_number(item.cost as f64 / 100.0);
+        sheet
+            .get_cell_mut((13, row))
+            .set_value_number(item.price as f64 / 100.0);
+        // 金额按库存算
+        sheet
+            .get_cell_mut((14, row))
+            .set_value_number(item.count as f64 * item.price as f64 / 100.0);
+        sheet.get_cell_mut((15, row)).set_value(&item.notes);
+        sheet
+            .get_cell_mut((STOCK_COL, row))
+            .set_value_number(item.count);
+        if let Some(url) = item.images.first() {
+            add_image(sheet, url, 3, row);
+        }
+    }
+
+    let mut bytes = vec![];
+    writer::xlsx::write_writer(&book, &mut bytes)
+        .map_err(|e| ERPError::ExcelError(format!("生成excel失败: {:?}", e)))?;
+    Ok(bytes)
+}
@@ -0,0 +1,71 @@
+use crate::common::datetime::format_date;
+use crate::dto::dto_excel::OrderExcelDto;
+use crate::dto::dto_orders::OrderDto;
+use crate::excel::images::add_image;
+use crate::excel::parse_orders::J_TO_NAME;
+use crate::{ERPError, ERPResult};
+use umya_spreadsheet::*;
+
+/// 按导入订单的格式导出: 第2行订单日期/出货日期, 第3行表头, 第4行开始是商品
+pub fn export_order(order: &OrderDto, items: &[OrderExcelDto]) -> ERPResult<Vec<u8>> {
+    let mut book = new_file();
+    let sheet = book
+        .get_sheet_mut(&0)
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
+
+    sheet
+        .get_cell_mut((1, 1))
+        .set_value(format!("{} {}", order.customer, order.order_no));
+    sheet
+        .get_cell_mut((1, 2))
+        .set_value(format!("订单日期: {}", format_date(order.order_date)));
+    sheet
+        .get_cell_mut((6, 2))
+        .set_value(format!("出货日期: {}", format_date(order.delivery_date)));
+
+    for (j, name) in J_TO_NAME.iter() {
+        sheet.get_cell_mut((*j as u32, 3)).set_value(*name);
+    }
+    sheet.get_column_dimension_mut("C").set_width(12.0);
+
+    let mut row = 4;
+    for item in items.iter() {
+        sheet.get_row_dimension_mut(&row).set_height(62.0);
+        sheet.get_cell_mut((1, row)).set_value_number(item.index);
+        sheet.get_cell_mut((2, row)).set_value(&item.number);
+        sheet.get_cell_mut((4, row)).set_value(&item.size);
+        sheet.get_cell_mut((5, row)).set_value(&item.name);
+        sheet.get_cell_mut((6, row)).set_value(&item.color);
+        sheet
+            .get_cell_mut((7, row))
+            .set_value_number(item.count as f64 / 10.0);
+        sheet.get_cell_mut((8, row)).set_value(&item.unit);
+        sheet
+            .get_cell_mut((9, row))
+            .set_value_number(item.price as f64 / 100.0);
+        sheet
+            .get_cell_mut((10, row))
+            .set_value_number(item.total as f64 / 100.0);
+        sheet.get_cell_mut((11, row)).set_value(&item.notes);
+        if let Some(url) = item.images.first() {
+            add_image(sheet, url, 3, row);
+        }
+        row += 1;
+    }
+
+    // 合计行没有单价, 导入时读到这里就结束了
+    let count = items.iter().map(|item| item.count).sum::<i32>();
+    let total = items.iter().map(|item| item.total).sum::<i32>();
+    sheet.get_cell_mut((1, row)).set_value("合计");
+    sheet
+        .get_cell_mut((7, row))
+        .set_value_number(count as f64 / 10.0);
+    sheet
+        .get_cell_mut((10, row))
+        .set_value_number(total as f64 / 100.0);
+
+    let mut bytes = vec![];
+    writer::xlsx::write_writer(&book, &mut bytes)
+        .map_err(|e| ERPError::ExcelError(format!("生成excel失败: {:?}", e)))?;
+    Ok(bytes)
+}
@@ -0,0 +1,35 @@
+use crate::dto::dto_stocktake::StocktakeSheetRowDto;
+use crate::excel::parse_stocktake::J_TO_NAME;
+use crate::{ERPError, ERPResult};
+use umya_spreadsheet::*;
+
+/// 盘点表: 按 parse_stocktake 的格式导出, 填好盘点数量后直接上传
+pub fn export_stocktake(rows: &[StocktakeSheetRowDto]) -> ERPResult<Vec<u8>> {
+    let mut book = new_file();
+    let sheet = book
+        .get_sheet_mut(&0)
+        .ok_or(ERPError::ExcelError("盘点sheet未找到".to_string()))?;
+
+    for (j, name) in J_TO_NAME.iter() {
+        sheet.get_cell_mut((*j as u32, 1)).set_value(*name);
+    }
+    for (row, item) in (2..).zip(rows.iter()) {
+        sheet.get_cell_mut((1, row)).set_value(&item.number);
+        sheet.get_cell_mut((2, row)).set_value(&item.name);
+        sheet.get_cell_mut((3, row)).set_value(&item.color);
+        // 条码按文本写, 避免被excel当成数字(丢掉前导0)
+        sheet.get_cell_mut((4, row)).set_value_string(&item.barcode);
+        sheet
+            .get_cell_mut((5, row))
+            .set_value_number(item.system_count);
+        // 已经录过的带上, 没录的留空
+        if let Some(counted) = item.counted {
+            sheet.get_cell_mut((6, row)).set_value_number(counted);
+        }
+    }
+
+    let mut bytes = vec![];
+    writer::xlsx::write_writer(&book, &mut bytes)
+        .map_err(|e| ERPError::ExcelError(format!("生成excel失败: {:?}", e)))?;
+    Ok(bytes)
+}
@@ -0,0 +1,38 @@
+use crate::common::items::image_url_to_path;
+use umya_spreadsheet::helper::coordinate::coordinate_from_index;
+use umya_spreadsheet::*;
+
+/// 图片在单元格里显示的最大像素
+const IMAGE_SIZE_PX: i64 = 80;
+/// 1像素 = 9525 EMU
+const EMU_PER_PX: i64 = 9525;
+
+/// 把图片放到(col, row)单元格, 读取失败只打日志
+pub fn add_image(sheet: &mut Worksheet, url: &str, col: u32, row: u32) {
+    let path = image_url_to_path(url);
+    // new_image 里图片读取失败会panic, 先检查一遍
+    let (width, height) = match image::image_dimensions(&path) {
+        Ok(dimensions) => dimensions,
+        Err(e) => {
+            tracing::warn!("load image {} failed: {:?}", path, e);
+            return;
+        }
+    };
+
+    let mut marker = structs::drawing::spreadsheet::MarkerType::default();
+    marker.set_coordinate(coordinate_from_index(&col, &row));
+    let mut image = structs::Image::default();
+    image.new_image(&path, marker);
+
+    // 按比例缩小到单元格大小
+    let scale = (IMAGE_SIZE_PX as f64 / width.max(height) as f64).min(1.0);
+    if let Some(anchor) = image.get_one_cell_anchor_mut() {
+        anchor
+            .get_extent_mut()
+            .set_cx((width as f64 * scale) as i64 * EMU_PER_PX);
+        anchor
+            .get_extent_mut()
+            .set_cy((height as f64 * scale) as i64 * EMU_PER_PX);
+    }
+    sheet.add_image(image);
+}
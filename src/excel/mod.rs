@@ -1,4 +1,12 @@
+pub mod common;
+pub mod export_items;
+pub mod export_orders;
+pub mod export_stocktake;
+pub mod images;
 pub mod parse_embryo;
 pub mod parse_items;
 pub mod parse_legacy_orders;
 pub mod parse_orders;
+pub mod parse_stocktake;
+pub mod parse_template_orders;
+pub mod progress;
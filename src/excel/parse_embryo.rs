@@ -1,5 +1,6 @@
 use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
 use crate::dto::dto_excel::EmbryoExcelDto;
+use crate::excel::common::{get_row_values, read_xlsx, RowErrors};
 use crate::{ERPError, ERPResult};
 use std::collections::HashMap;
 use umya_spreadsheet::*;
@@ -23,111 +24,83 @@ lazy_static! {
     pub static ref NONE_NULLABLE_JS: Vec<i32> = vec![2, 4, 5, 6, 7];
 }
 
-pub fn parse_embryos(file_path: &str) -> ERPResult<Vec<EmbryoExcelDto>> {
-    tracing::info!("fila_path: {file_path}");
-    let path = std::path::Path::new(file_path);
-    let sheets = reader::xlsx::read(path).unwrap();
+pub fn parse_embryos(file_path: &str, dry_run: bool) -> ERPResult<Vec<EmbryoExcelDto>> {
+    let sheets = read_xlsx(file_path)?;
     let items_sheet = sheets
         .get_sheet(&0)
-        .map_err(|_| ERPError::ExcelError("库存胚sheet未找到".to_string()))?;
+        .ok_or(ERPError::ExcelError("库存胚sheet未找到".to_string()))?;
 
     let (cols, rows) = items_sheet.get_highest_column_and_row();
 
     let mut items = vec![];
-    let mut pre: Option<EmbryoExcelDto> = None;
+    let mut errors = RowErrors::new(items_sheet.get_name(), &J_TO_NAME);
+    // 每一行自己的图片, 为空的沿用上一行
+    let mut items_raw_images: Vec<Vec<&Image>> = vec![];
 
     // 从第2行开始
     for i in 2..rows + 1 {
-        print!("row: {}", i);
-
-        let mut cur = EmbryoExcelDto::default();
-        if let Some(previous) = pre {
-            cur.images = previous.images;
+        let values = get_row_values(items_sheet, i, cols.max(10));
+        let images = items_sheet.get_images((3, i));
+        if images.is_empty() && values[1].is_empty() && values[4].is_empty() {
+            break;
         }
 
-        let mut images: Vec<&Image> = vec![];
-        for j in 1..cols + 1 {
-            // 图片在第3列
-            if j == 3 {
-                images = items_sheet.get_images((j, i));
-                print!("images count: {}", images.len());
-            }
-
-            let cell = items_sheet.get_cell((j, i));
-            if cell.is_none() {
-                if NONE_NULLABLE_JS.contains(&(j as i32)) {
-                    if j == 3 && !cur.images.is_empty() {
-                        continue;
-                    }
-                    return Err(ERPError::ExcelError(format!(
-                        "第{}行的 {} 为空",
-                        i,
-                        J_TO_NAME.get(&(j as i32)).unwrap_or(&"")
-                    )));
-                } else {
-                    continue;
-                }
-            }
+        let mut cur = EmbryoExcelDto {
+            row: i,
+            ..Default::default()
+        };
 
-            let cell_value = cell.unwrap().get_raw_value().to_string();
-            if cell_value.is_empty() {
-                if NONE_NULLABLE_JS.contains(&(j as i32)) {
-                    if j == 3 && !cur.images.is_empty() {
-                        continue;
-                    }
-                    return Err(ERPError::ExcelError(format!(
-                        "第{}行的 {} 为空",
-                        i,
-                        J_TO_NAME.get(&(j as i32)).unwrap_or(&"")
-                    )));
-                }
+        for (j, value) in (1..).zip(values.iter()) {
+            if value.is_empty() {
+                errors.check_required(i, j, value, &NONE_NULLABLE_JS);
                 continue;
             }
 
             match j {
-                2 => cur.number = cell_value.trim().to_string(),
-                4 => cur.name = cell_value.trim().to_string(),
-                5 => cur.color = cell_value.trim().to_string(),
-                6 => cur.count = cell_value.parse::<i32>().unwrap_or(0),
-                7 => cur.unit = cell_value.trim().to_string(),
-                8 => cur.cost = (cell_value.parse::<f32>().unwrap_or(0.0) * 100.0) as i32,
-                10 => cur.notes = cell_value.trim().to_string(),
+                2 => cur.number = value.to_string(),
+                4 => cur.name = value.to_string(),
+                5 => cur.color = value.to_string(),
+                6 => cur.count = errors.parse_i32(i, j, value),
+                7 => cur.unit = value.to_string(),
+                8 => cur.cost = (errors.parse_f32(i, j, value) * 100.0).round() as i32,
+                10 => cur.notes = value.to_string(),
                 _ => {}
             }
         }
 
-        if images.is_empty() && cur.color.is_empty() && cur.number.is_empty() {
-            break;
-        }
-
-        if cur.images.is_empty() && images.is_empty() {
-            return Err(ERPError::ExcelError(format!("第{}行的 图片 为空", i,)));
-        }
-
-        if cur.images.is_empty() {
-            cur.images = match images.is_empty() {
-                true => vec![],
-                false => {
-                    let mut image_urls = vec![];
-                    for (index, real_goods_image) in images.into_iter().enumerate() {
-                        let sku_image_name = format!("{}-{}.png", cur.number, index);
-                        let goods_image_path =
-                            format!("{}/embryo/{}", STORAGE_FILE_PATH, sku_image_name);
-                        real_goods_image.download_image(&goods_image_path);
-                        image_urls
-                            .push(format!("{}/embryo/{}", STORAGE_URL_PREFIX, sku_image_name));
-                    }
-                    image_urls
-                }
-            };
+        let has_images = items_raw_images.iter().any(|images| !images.is_empty());
+        if images.is_empty() && !has_images {
+            errors.push(i, 3, "", "不能为空");
         }
 
         tracing::info!("rows#{:?}: {:?}", i, cur);
-        pre = Some(cur.clone());
+        items_raw_images.push(images);
         items.push(cur);
     }
 
     print!("rows: {rows:}: cols: {cols:}");
+    errors.into_result()?;
+
+    // 检查都通过了再保存图片
+    let mut pre_image_urls = vec![];
+    for (cur, images) in items.iter_mut().zip(items_raw_images) {
+        if images.is_empty() {
+            cur.images = pre_image_urls.clone();
+            continue;
+        }
+
+        let mut image_urls = vec![];
+        for (index, real_goods_image) in images.into_iter().enumerate() {
+            let sku_image_name = format!("{}-{}.png", cur.number, index);
+            let goods_image_path = format!("{}/embryo/{}", STORAGE_FILE_PATH, sku_image_name);
+            if !dry_run {
+                real_goods_image.download_image(&goods_image_path);
+            }
+            image_urls.push(format!("{}/embryo/{}", STORAGE_URL_PREFIX, sku_image_name));
+        }
+        cur.images = image_urls.clone();
+        pre_image_urls = image_urls;
+    }
 
     Ok(items)
 }
@@ -1,13 +1,13 @@
 use crate::common::items::calculate_barcode;
 use crate::common::list::pickup_most_common_string;
-use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::constants::{IMPORT_PHASE_IMAGES, STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
 use crate::dto::dto_excel::ItemExcelDto;
-use crate::service::settings_service::SettingsServiceTrait;
-use crate::state::excel_state::ExcelState;
+use crate::excel::common::{get_row_values, read_xlsx, RowErrors};
+use crate::excel::progress::ImportProgress;
 use crate::{ERPError, ERPResult};
 use itertools::Itertools;
 use std::collections::HashMap;
-use umya_spreadsheet::*;
+use std::fs;
 
 /* 图片(可多张) 名称	颜色	产品大类	产品小类(可空) 编号	条码(可空) 规格	单位	售价	成本	备注(可空) 数量(6位数字，688001，688002...)*/
 /* 名称	颜色	产品大类 编号 单位	售价	成本 数量 */
@@ -36,184 +36,92 @@ lazy_static! {
     pub static ref NONE_NULLABLE_JS: Vec<i32> = vec![2, 5, 8, 10, 11, 13];
 }
 
+/// 商品图片先放在内存里, 导入成功后再写到sku目录
+pub type ItemImages = Vec<(String, Vec<u8>)>;
+
+/// 只解析和校验, 不写库; 新颜色按 add_multiple_color_to_value 的规则推算值
 pub async fn parse_items<'a>(
-    state: &ExcelState,
     file_path: &str,
     color_to_value: HashMap<String, i32>,
-) -> ERPResult<Vec<ItemExcelDto<'a>>> {
+    dry_run: bool,
+    progress: Option<&ImportProgress>,
+) -> ERPResult<(Vec<ItemExcelDto<'a>>, ItemImages)> {
     let mut new_color_to_value = color_to_value.clone();
 
-    tracing::info!("file_path: {file_path}");
-    let path = std::path::Path::new(file_path);
-    let sheets = reader::xlsx::read(path).unwrap();
+    let sheets = read_xlsx(file_path)?;
     let items_sheet = sheets
         .get_sheet(&0)
-        .map_err(|_| ERPError::ExcelError("商品sheet未找到".to_string()))?;
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
 
     let (cols, rows) = items_sheet.get_highest_column_and_row();
 
     let mut items = vec![];
     let mut pre: Option<ItemExcelDto> = None;
+    let mut errors = RowErrors::new(items_sheet.get_name(), &J_TO_NAME);
 
-    // get colors first
-    let mut new_colors_to_empty_value = HashMap::new();
+    // 从第7行开始, 先把所有行的问题找出来, 没问题了再写颜色/图片
     for i in 7..rows + 1 {
-        let cell = items_sheet.get_cell((8, i));
-        if cell.is_none() {
-            continue;
-        }
-        let cell_value = cell.unwrap().get_raw_value().to_string();
-        if cell_value.is_empty() {
-            continue;
-        }
-
-        let color = cell_value.trim().to_string().to_ascii_uppercase();
-
-        // tracing::info!(
-        //     "color: {:?}, contains: {:?}",
-        //     color,
-        //     new_color_to_value.contains_key(&color)
-        // );
-        if !new_color_to_value.contains_key(&color) {
-            new_colors_to_empty_value.entry(color).or_insert("");
-        }
-    }
-    // tracing::info!("new_colors_to_empty_value: {:?}", new_colors_to_empty_value);
-
-    if !new_colors_to_empty_value.is_empty() {
-        let new_colors = new_colors_to_empty_value
-            .into_iter()
-            .map(|item| item.0)
-            .collect::<Vec<_>>();
-
-        if !new_colors.is_empty() {
-            let c_to_v = state
-                .settings_service
-                .add_multiple_color_to_value(new_colors)
-                .await?;
-
-            c_to_v.into_iter().for_each(|(c, v)| {
-                new_color_to_value.insert(c, v);
-            });
+        let values = get_row_values(items_sheet, i, cols.max(15));
+        if values.iter().all(|value| value.is_empty()) {
+            break;
         }
-    }
 
-    // 从第2行开始
-    for i in 7..rows + 1 {
-        print!("row: {}", i);
-
-        let mut cur = ItemExcelDto::default();
-        if let Some(previous) = pre {
-            // cur.images = previous.images;
+        let mut cur = ItemExcelDto {
+            row: i,
+            ..Default::default()
+        };
+        // 序号为空(合并单元格)的行才沿用上一行的编号/规格/名称
+        if let Some(previous) = pre.filter(|_| values[0].is_empty()) {
             cur.index = previous.index;
             cur.number = previous.number;
             cur.size = previous.size;
             cur.name = previous.name;
         }
 
-        // let mut images: Vec<&Image> = vec![];
-        for j in 1..cols + 1 {
-            // 图片在第3列
-            if j == 3 {
-                cur.raw_excel_images = items_sheet.get_images((j, i)).clone();
-                print!("images count: {}", cur.raw_excel_images.len());
-            }
-
-            let cell = items_sheet.get_cell((j, i));
-            if cell.is_none() {
-                if NONE_NULLABLE_JS.contains(&(j as i32)) {
-                    if j == 2 && !cur.number.is_empty() || j == 5 && !cur.name.is_empty() {
-                        continue;
-                    }
+        // 图片在第3列
+        cur.raw_excel_images = items_sheet.get_images((3, i)).clone();
 
-                    return Err(ERPError::ExcelError(format!(
-                        "第{}行的 {} 为空/0",
-                        i,
-                        J_TO_NAME.get(&(j as i32)).unwrap_or(&"")
-                    )));
-                } else {
+        for (j, value) in (1..).zip(values.iter()) {
+            if value.is_empty() {
+                // 编号/名称可以沿用上一行
+                if j == 2 && !cur.number.is_empty() || j == 5 && !cur.name.is_empty() {
                     continue;
                 }
-            }
-
-            let cell_value = cell.unwrap().get_raw_value().to_string();
-            if cell_value.is_empty() {
-                if NONE_NULLABLE_JS.contains(&(j as i32)) {
-                    if j == 2 && !cur.number.is_empty() || j == 5 && !cur.name.is_empty() {
-                        continue;
-                    }
-
-                    return Err(ERPError::ExcelError(format!(
-                        "第{}行的 {} 为空",
-                        i,
-                        J_TO_NAME.get(&(j as i32)).unwrap_or(&"")
-                    )));
-                }
+                errors.check_required(i, j, value, &NONE_NULLABLE_JS);
                 continue;
             }
 
             match j {
-                1 => cur.index = cell_value.trim().parse::<i32>().unwrap_or(0),
-                2 => cur.number = cell_value.trim().to_string(),
-                4 => cur.size = cell_value.trim().to_string(),
-                5 => cur.name = cell_value.trim().to_string(),
-                6 => cur.cates1 = cell_value.trim().to_string(),
-                7 => cur.cates2 = cell_value.trim().to_string(),
-                8 => cur.color = cell_value.trim().to_string().to_ascii_uppercase(),
-                9 => cur.barcode = cell_value.trim().to_string(),
+                1 => cur.index = errors.parse_i32(i, j, value),
+                2 => cur.number = value.to_string(),
+                4 => cur.size = value.to_string(),
+                5 => cur.name = value.to_string(),
+                6 => cur.cates1 = value.to_string(),
+                7 => cur.cates2 = value.to_string(),
+                8 => cur.color = value.to_ascii_uppercase(),
+                9 => cur.barcode = value.to_string(),
                 10 => {
-                    cur.count = cell_value
-                        .parse::<i32>()
-                        .map_err(|_| ERPError::ConvertFailed("数量必须是整数".to_string()))?
+                    // 数量是入库数, 出库走出库接口
+                    cur.count = errors.parse_i32(i, j, value);
+                    if cur.count < 0 {
+                        errors.push(i, j, value, "不能小于0");
+                    }
                 }
-                11 => cur.unit = cell_value.trim().to_string(),
-                12 => cur.cost = (cell_value.parse::<f32>().unwrap_or(0.0) * 100.0) as i32,
-                13 => cur.price = (cell_value.parse::<f32>().unwrap_or(0.0) * 100.0) as i32,
-                15 => cur.notes = cell_value.trim().to_string(),
+                11 => cur.unit = value.to_string(),
+                12 => cur.cost = (errors.parse_f32(i, j, value) * 100.0).round() as i32,
+                13 => cur.price = (errors.parse_f32(i, j, value) * 100.0).round() as i32,
+                15 => cur.notes = value.to_string(),
                 _ => {}
             }
         }
 
-        if cur.color.is_empty() && cur.number.is_empty() && cur.cost == 0 && cur.price == 0 {
-            break;
-        }
-
-        if cur.barcode.is_empty() {
-            let color_value = new_color_to_value.get(&cur.color).unwrap_or(&0);
-            cur.barcode = calculate_barcode(&cur.number, *color_value, cur.price / 5);
-        }
-
-        // if cur.images.is_empty() && images.is_empty() {
-        //     return Err(ERPError::ExcelError(format!("第{}行的 图片 为空", i)));
-        // }
-
-        // if !images.is_empty() {
-        //     cur.images = match images.is_empty() {
-        //         true => vec![],
-        //         _ => {
-        //             let mut tmp = vec![];
-        //             for (index, real_goods_image) in images.into_iter().enumerate() {
-        //                 let sku_image_name = format!("{}-{}.png", cur.barcode, index);
-        //                 let goods_image_path =
-        //                     format!("{}/sku/{}", STORAGE_FILE_PATH, sku_image_name);
-        //                 real_goods_image.download_image(&goods_image_path);
-        //                 tmp.push(format!("{}/sku/{}", STORAGE_URL_PREFIX, sku_image_name));
-        //             }
-        //
-        //             tmp
-        //         }
-        //     };
-        // }
-
         tracing::info!(
-            "index: {}, rows#{:?}: {:?}, {:?}, {:?}，{:?},{:?}",
+            "index: {}, rows#{:?}: {:?}, {:?}, {:?}",
             cur.index,
             i,
             cur.number,
             cur.color,
             cur.price,
-            new_color_to_value.get(&cur.color).unwrap_or(&0),
-            cur.barcode
         );
         pre = Some(cur.clone());
         items.push(cur);
@@ -222,21 +130,66 @@ pub async fn parse_items<'a>(
     print!("rows: {rows:}: cols: {cols:}");
 
     let mut index_to_items = HashMap::new();
-    items.clone().into_iter().for_each(|item| {
+    items.into_iter().for_each(|item| {
         index_to_items
             .entry(item.index)
             .or_insert(vec![])
             .push(item);
     });
+    let index_to_items = index_to_items
+        .into_iter()
+        .sorted_by_key(|x| x.0)
+        .collect::<Vec<_>>();
+
+    // 同一个序号用同一个大小类
+    let mut index_to_cates = HashMap::new();
+    for (index, index_items) in index_to_items.iter() {
+        let (cate1, cate2) = get_cate1_and_cate2_from_items(index_items);
+        let row = index_items[0].row;
+        if cate1.is_empty() {
+            errors.push(row, 6, "", &format!("序号{}内未找到大类", index));
+        }
+        if cate2.is_empty() {
+            errors.push(row, 7, "", &format!("序号{}内未找到小类", index));
+        }
+        index_to_cates.insert(*index, (cate1, cate2));
+    }
+
+    errors.into_result()?;
+
+    let new_colors = index_to_items
+        .iter()
+        .flat_map(|(_, index_items)| index_items.iter())
+        .filter(|item| !new_color_to_value.contains_key(&item.color))
+        .map(|item| item.color.clone())
+        .unique()
+        .sorted()
+        .collect::<Vec<_>>();
+
+    // 新颜色在导入的事务里再入库, 值不一致时导入失败
+    let max = new_color_to_value.values().max().copied().unwrap_or(0);
+    for (index, color) in new_colors.into_iter().enumerate() {
+        new_color_to_value.insert(color, max + index as i32 + 1);
+    }
 
     let mut fixed_items = vec![];
+    let mut item_images = vec![];
 
-    for (index, index_items) in index_to_items.into_iter().sorted_by_key(|x| x.0) {
-        let (cate1, cate2) = get_cate1_and_cate2_from_items(index, &index_items)?;
-        let images = get_images_from_items(index, &index_items)?;
+    if let Some(progress) = progress {
+        progress.set_phase(IMPORT_PHASE_IMAGES, index_to_items.len() as i32);
+    }
+    for (processed, (index, mut index_items)) in (1..).zip(index_to_items) {
+        for item in index_items.iter_mut() {
+            if item.barcode.is_empty() {
+                let color_value = new_color_to_value.get(&item.color).unwrap_or(&0);
+                item.barcode = calculate_barcode(&item.number, *color_value, item.price / 5);
+            }
+        }
+
+        let (cate1, cate2) = index_to_cates.remove(&index).unwrap_or_default();
+        let images = get_images_from_items(&index_items, dry_run, &mut item_images);
 
         let index_items_clone = index_items
-            .clone()
             .into_iter()
             .map(|item| ItemExcelDto {
                 cates1: cate1.clone(),
@@ -248,42 +201,80 @@ pub async fn parse_items<'a>(
             .collect::<Vec<_>>();
 
         fixed_items.extend(index_items_clone);
+        if let Some(progress) = progress {
+            progress.set_processed(processed);
+        }
     }
 
-    Ok(fixed_items)
+    Ok((fixed_items, item_images))
 }
 
-fn get_cate1_and_cate2_from_items(
-    index: i32,
-    items: &[ItemExcelDto<'_>],
-) -> ERPResult<(String, String)> {
+fn get_cate1_and_cate2_from_items(items: &[ItemExcelDto<'_>]) -> (String, String) {
     let cate1s = items.iter().map(|item| &item.cates1).collect::<Vec<_>>();
     let cate2s = items.iter().map(|item| &item.cates2).collect::<Vec<_>>();
 
     let cate1 = pickup_most_common_string(&cate1s);
     let cate2 = pickup_most_common_string(&cate2s);
 
-    if cate1.is_empty() || cate2.is_empty() {
-        return Err(ERPError::NotFound(format!("序号{}内未找到大小类", index)));
-    }
-
-    Ok((cate1, cate2))
+    (cate1, cate2)
 }
 
-fn get_images_from_items(index: i32, items: &[ItemExcelDto<'_>]) -> ERPResult<Vec<String>> {
+fn get_images_from_items(
+    items: &[ItemExcelDto<'_>],
+    dry_run: bool,
+    item_images: &mut ItemImages,
+) -> Vec<String> {
     for item in items.iter() {
         if !item.raw_excel_images.is_empty() {
             let mut images = vec![];
             for (image_index, real_goods_image) in item.raw_excel_images.iter().enumerate() {
                 let sku_image_name = format!("{}-{}.png", item.barcode, image_index);
-                let goods_image_path = format!("{}/sku/{}", STORAGE_FILE_PATH, sku_image_name);
-                real_goods_image.download_image(&goods_image_path);
+                if !dry_run {
+                    item_images.push((
+                        sku_image_name.clone(),
+                        real_goods_image.get_image_data().clone(),
+                    ));
+                }
                 images.push(format!("{}/sku/{}", STORAGE_URL_PREFIX, sku_image_name));
             }
 
-            return Ok(images);
+            return images;
         }
     }
 
-    Err(ERPError::NotFound(format!("序号 {} 没找到图片", index)))
+    // 没有图片的, 新商品在入库时报错, 已有商品沿用原来的图片
+    vec![]
+}
+
+fn get_staging_path(job_id: i32) -> String {
+    format!("{}/sku/.import-{}", STORAGE_FILE_PATH, job_id)
+}
+
+/// 导入前先把图片写到暂存目录, 写失败时导入也失败
+pub fn stage_item_images(job_id: i32, item_images: &ItemImages) -> ERPResult<String> {
+    let staging_path = get_staging_path(job_id);
+    fs::create_dir_all(&staging_path)?;
+    for (name, data) in item_images {
+        fs::write(format!("{}/{}", staging_path, name), data)?;
+    }
+
+    Ok(staging_path)
+}
+
+/// 事务提交后再覆盖sku目录里的图片
+pub fn publish_item_images(staging_path: &str, item_images: &ItemImages) -> ERPResult<()> {
+    for (name, _) in item_images {
+        fs::rename(
+            format!("{}/{}", staging_path, name),
+            format!("{}/sku/{}", STORAGE_FILE_PATH, name),
+        )?;
+    }
+    fs::remove_dir_all(staging_path)?;
+
+    Ok(())
+}
+
+/// 导入失败时删掉暂存的图片, 已发布的不受影响
+pub fn discard_item_images(job_id: i32) {
+    fs::remove_dir_all(get_staging_path(job_id)).ok();
 }
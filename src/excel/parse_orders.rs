@@ -1,6 +1,7 @@
 use crate::common::datetime::{get_cur_date_str, parse_date_with_regex};
 use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
 use crate::dto::dto_excel::OrderExcelDto;
+use crate::excel::common::{get_row_values, is_end_of_items, read_xlsx, RowErrors};
 use crate::{ERPError, ERPResult};
 use chrono::NaiveDate;
 use std::collections::HashMap;
@@ -36,117 +37,99 @@ pub struct OrderInfo {
 pub async fn parse_order_info(file_path: &str) -> ERPResult<OrderInfo> {
     let mut order_info = OrderInfo::default();
 
-    tracing::info!("file_path: {file_path}");
-    let path = std::path::Path::new(file_path);
-    let sheets = reader::xlsx::read(path).unwrap();
+    let sheets = read_xlsx(file_path)?;
     let items_sheet = sheets
         .get_sheet(&0)
-        .map_err(|_| ERPError::ExcelError("商品sheet未找到".to_string()))?;
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
 
     let (cols, _) = items_sheet.get_highest_column_and_row();
+    let mut errors = RowErrors::new(items_sheet.get_name(), &J_TO_NAME);
 
     let row = 2;
-    for j in 1..cols + 1 {
-        let cell = items_sheet.get_cell((j, row));
-        if cell.is_none() {
-            continue;
-        }
-
-        let cell_value = cell.unwrap().get_raw_value().to_string();
-        if cell_value.is_empty() {
-            continue;
-        }
-
+    for (j, cell_value) in (1..).zip(get_row_values(items_sheet, row, cols)) {
         if cell_value.contains("订单") {
-            let naive_order_date = parse_date_with_regex(&cell_value)
-                .ok_or(ERPError::ExcelError("出货日期未找到".to_string()))?;
-            order_info.order_date = naive_order_date;
+            match parse_date_with_regex(&cell_value) {
+                Some(order_date) => order_info.order_date = order_date,
+                None => errors.push(row, j, &cell_value, "订单日期格式不对"),
+            }
         }
 
         if cell_value.contains("出货") {
-            let naive_order_date = parse_date_with_regex(&cell_value)
-                .ok_or(ERPError::ExcelError("出货日期未找到".to_string()))?;
-            order_info.delivery_date = naive_order_date;
+            match parse_date_with_regex(&cell_value) {
+                Some(delivery_date) => order_info.delivery_date = delivery_date,
+                None => errors.push(row, j, &cell_value, "出货日期格式不对"),
+            }
         }
     }
+    errors.into_result()?;
 
     Ok(order_info)
 }
 
-pub async fn parse_order(file_path: &str) -> ERPResult<Vec<OrderExcelDto>> {
-    tracing::info!("file_path: {file_path}");
-    let path = std::path::Path::new(file_path);
-    let sheets = reader::xlsx::read(path).unwrap();
+pub async fn parse_order(file_path: &str, dry_run: bool) -> ERPResult<Vec<OrderExcelDto>> {
+    let sheets = read_xlsx(file_path)?;
     let items_sheet = sheets
         .get_sheet(&0)
-        .map_err(|_| ERPError::ExcelError("商品sheet未找到".to_string()))?;
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
 
     let (cols, rows) = items_sheet.get_highest_column_and_row();
 
     let mut items = vec![];
     let mut pre: Option<OrderExcelDto> = None;
+    let mut errors = RowErrors::new(items_sheet.get_name(), &J_TO_NAME);
 
-    let now_str = get_cur_date_str();
-
-    let mut index_to_images: HashMap<i32, Vec<String>> = HashMap::new();
-    // // 从第4行开始
+    let mut index_to_raw_images: HashMap<i32, (String, Vec<&Image>)> = HashMap::new();
+    // 从第4行开始
     for i in 4..rows + 1 {
-        print!("row: {}", i);
+        let values = get_row_values(items_sheet, i, cols.max(11));
+        if is_end_of_items(&values, 7, 9) {
+            break;
+        }
 
-        let mut cur = OrderExcelDto::default();
+        let mut cur = OrderExcelDto {
+            row: i,
+            ..Default::default()
+        };
         if let Some(previous) = pre {
             // 编号
             cur.number = previous.number;
         }
 
-        let mut images: Vec<&Image> = vec![];
-        for j in 1..cols + 1 {
-            if j == 3 {
-                images = items_sheet.get_images((j, i));
-                tracing::info!("images: {:?}", images.len());
-                continue;
-            }
-            let cell = items_sheet.get_cell((j, i));
-            if cell.is_none() {
-                continue;
-            }
-
-            let cell_value = cell.unwrap().get_raw_value().to_string();
-            if cell_value.is_empty() {
+        for (j, value) in (1..).zip(values.iter()) {
+            if value.is_empty() {
+                // 编号可以沿用上一行, 图片单独处理
+                if j == 2 && !cur.number.is_empty() || j == 3 {
+                    continue;
+                }
+                errors.check_required(i, j, value, &NONE_NULLABLE_JS);
                 continue;
             }
 
             match j {
-                1 => cur.index = cell_value.trim().parse::<i32>().unwrap_or(0),
-                2 => cur.number = cell_value.trim().to_string(),
-                4 => cur.size = cell_value.trim().to_string(),
-                5 => cur.name = cell_value.trim().to_string(),
-                6 => cur.color = cell_value.trim().to_string().to_ascii_uppercase(),
-                7 => cur.count = (cell_value.parse::<f32>().unwrap_or(0.0) * 10.0) as i32,
-                8 => cur.unit = cell_value.trim().to_string(),
-                9 => cur.price = (cell_value.parse::<f32>().unwrap_or(0.0) * 100.0) as i32,
-                10 => cur.total = (cell_value.parse::<f32>().unwrap_or(0.0) * 100.0) as i32,
-                11 => cur.notes = cell_value.trim().to_string(),
+                1 => cur.index = errors.parse_i32(i, j, value),
+                2 => cur.number = value.to_string(),
+                4 => cur.size = value.to_string(),
+                5 => cur.name = value.to_string(),
+                6 => cur.color = value.to_ascii_uppercase(),
+                7 => cur.count = (errors.parse_f32(i, j, value) * 10.0).round() as i32,
+                8 => cur.unit = value.to_string(),
+                9 => cur.price = (errors.parse_f32(i, j, value) * 100.0).round() as i32,
+                10 => cur.total = (errors.parse_f32(i, j, value) * 100.0).round() as i32,
+                11 => cur.notes = value.to_string(),
                 _ => {}
             }
         }
-
-        let mut image_urls = vec![];
-        if !images.is_empty() {
-            for (_, real_goods_image) in images.into_iter().enumerate() {
-                let image_name = format!("{}/{}-{}.png", &now_str, cur.index, cur.number);
-                let image_path = format!("{}/order/{}", STORAGE_FILE_PATH, image_name);
-                fs::create_dir_all(format!("{}/order/{}", STORAGE_FILE_PATH, &now_str))?;
-                real_goods_image.download_image(&image_path);
-                image_urls.push(format!("{}/order/{}", STORAGE_URL_PREFIX, image_name));
-            }
+        if !values[6].is_empty() && cur.count == 0 {
+            errors.push(i, 7, &values[6], "不能为0");
         }
-        if !image_urls.is_empty() {
-            index_to_images.insert(cur.index, image_urls);
+        if !values[8].is_empty() && cur.price == 0 {
+            errors.push(i, 9, &values[8], "不能为0");
         }
 
-        if cur.count == 0 || cur.price == 0 {
-            break;
+        let images = items_sheet.get_images((3, i));
+        tracing::info!("images: {:?}", images.len());
+        if !images.is_empty() {
+            index_to_raw_images.insert(cur.index, (cur.number.clone(), images));
         }
 
         tracing::info!("rows#{:?}: {:?}", i, cur);
@@ -155,19 +138,23 @@ pub async fn parse_order(file_path: &str) -> ERPResult<Vec<OrderExcelDto>> {
     }
 
     print!("rows: {rows:}: cols: {cols:}");
+    errors.into_result()?;
 
-    for (index, item) in items.iter().enumerate() {
-        if item.count == 0 || item.price == 0 {
-            let name = match item.count == 0 {
-                true => "数量",
-                _ => "单价",
-            };
-            return Err(ERPError::ExcelError(format!(
-                "第{}行的 {} 为空/0",
-                index + 4,
-                name
-            )));
+    // 检查都通过了再保存图片
+    let now_str = get_cur_date_str();
+    let mut index_to_images: HashMap<i32, Vec<String>> = HashMap::new();
+    for (index, (number, images)) in index_to_raw_images.into_iter() {
+        let mut image_urls = vec![];
+        for real_goods_image in images.into_iter() {
+            let image_name = format!("{}/{}-{}.png", &now_str, index, number);
+            let image_path = format!("{}/order/{}", STORAGE_FILE_PATH, image_name);
+            if !dry_run {
+                fs::create_dir_all(format!("{}/order/{}", STORAGE_FILE_PATH, &now_str))?;
+                real_goods_image.download_image(&image_path);
+            }
+            image_urls.push(format!("{}/order/{}", STORAGE_URL_PREFIX, image_name));
         }
+        index_to_images.insert(index, image_urls);
     }
 
     // insert images to items
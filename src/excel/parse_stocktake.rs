@@ -0,0 +1,64 @@
+use crate::dto::dto_excel::StocktakeExcelDto;
+use crate::excel::common::{get_row_values, read_xlsx, RowErrors};
+use crate::ERPError;
+use crate::ERPResult;
+use std::collections::HashMap;
+
+/* 编号 名称 颜色 条码 账面库存 盘点数量, 第1行表头, 第2行开始是商品 */
+lazy_static! {
+    pub static ref J_TO_NAME: HashMap<i32, &'static str> = vec![
+        (1, "编号"),
+        (2, "名称"),
+        (3, "颜色"),
+        (4, "条码"),
+        (5, "账面库存"),
+        (6, "盘点数量"),
+    ]
+    .into_iter()
+    .collect();
+}
+
+/// 盘点数量为空的行是没盘到的, 跳过
+pub fn parse_stocktake(file_path: &str) -> ERPResult<Vec<StocktakeExcelDto>> {
+    let sheets = read_xlsx(file_path)?;
+    let sheet = sheets
+        .get_sheet(&0)
+        .ok_or(ERPError::ExcelError("盘点sheet未找到".to_string()))?;
+
+    let (_, rows) = sheet.get_highest_column_and_row();
+    let mut items = vec![];
+    let mut errors = RowErrors::new(sheet.get_name(), &J_TO_NAME);
+
+    for i in 2..rows + 1 {
+        let values = get_row_values(sheet, i, 6);
+        if values.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+        if values[5].is_empty() {
+            continue;
+        }
+
+        let item = StocktakeExcelDto {
+            row: i,
+            number: values[0].to_string(),
+            color: values[2].to_ascii_uppercase(),
+            barcode: values[3].to_string(),
+            counted: errors.parse_i32(i, 6, &values[5]),
+        };
+        if item.barcode.is_empty() {
+            if item.number.is_empty() {
+                errors.push(i, 1, "", "没有条码时不能为空");
+            }
+            if item.color.is_empty() {
+                errors.push(i, 3, "", "没有条码时不能为空");
+            }
+        }
+        if item.counted < 0 {
+            errors.push(i, 6, &values[5], "不能小于0");
+        }
+        items.push(item);
+    }
+
+    errors.into_result()?;
+    Ok(items)
+}
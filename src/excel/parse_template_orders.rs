@@ -0,0 +1,353 @@
+use crate::common::datetime::{get_cur_date_str, parse_ymd};
+use crate::constants::{STORAGE_FILE_PATH, STORAGE_URL_PREFIX};
+use crate::dto::dto_excel::OrderExcelDto;
+use crate::excel::common::{get_row_values, read_xlsx, RowErrors};
+use crate::excel::parse_orders::OrderInfo;
+use crate::model::excel::ExcelTemplateModel;
+use crate::{ERPError, ERPResult};
+use itertools::Itertools;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use umya_spreadsheet::*;
+
+lazy_static! {
+    /// 导入模版里能用的字段 -> 显示的名字
+    pub static ref FIELD_TO_NAME: Vec<(&'static str, &'static str)> = vec![
+        ("index", "序号"),
+        ("number", "编号"),
+        ("image", "图片"),
+        ("size", "尺寸"),
+        ("name", "名称"),
+        ("color", "颜色"),
+        ("count", "数量"),
+        ("unit", "单位"),
+        ("price", "单价"),
+        ("total", "金额"),
+        ("notes", "备注"),
+    ];
+    pub static ref REQUIRED_FIELDS: Vec<&'static str> = vec!["number", "color", "count", "price"];
+}
+
+/// 模版没填日期正则时用这两个, 第一个分组是日期
+pub const DEFAULT_ORDER_DATE_REGEX: &str = r"(?:订单|订货)日期[:：]?\s*(\d\S*)";
+pub const DEFAULT_DELIVERY_DATE_REGEX: &str = r"出货日期[:：]?\s*(\d\S*)";
+
+/// 表头文字去掉所有空白再比较, "数 量" 和 "数量" 算同一列
+fn normalize_header(header: &str) -> String {
+    header.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn field_name(field: &str) -> &'static str {
+    FIELD_TO_NAME
+        .iter()
+        .find(|(key, _)| *key == field)
+        .map(|(_, name)| *name)
+        .unwrap_or("")
+}
+
+/// 模版的 表头文字 -> 字段
+pub fn get_template_columns(template: &ExcelTemplateModel) -> HashMap<String, String> {
+    template
+        .columns
+        .as_object()
+        .map(|columns| {
+            columns
+                .iter()
+                .filter_map(|(header, field)| {
+                    field
+                        .as_str()
+                        .map(|field| (normalize_header(header), field.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 按表头找每个字段在第几列
+fn get_field_to_j(headers: &[String], columns: &HashMap<String, String>) -> HashMap<String, u32> {
+    let mut field_to_j = HashMap::new();
+    for (j, header) in (1..).zip(headers.iter()) {
+        if let Some(field) = columns.get(&normalize_header(header)) {
+            field_to_j.entry(field.clone()).or_insert(j);
+        }
+    }
+    field_to_j
+}
+
+fn get_headers(sheet: &Worksheet, template: &ExcelTemplateModel) -> Vec<String> {
+    let (cols, _) = sheet.get_highest_column_and_row();
+    get_row_values(sheet, template.header_row.max(1) as u32, cols)
+}
+
+/// 客户没指定模版时, 按表头文字找: 模版里的表头都要有, 多个符合的取列最多的
+pub fn detect_template(
+    file_path: &str,
+    templates: &[ExcelTemplateModel],
+) -> ERPResult<Option<ExcelTemplateModel>> {
+    let sheets = read_xlsx(file_path)?;
+    let items_sheet = sheets
+        .get_sheet(&0)
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
+
+    let mut matched: Option<(usize, &ExcelTemplateModel)> = None;
+    for template in templates.iter() {
+        let columns = get_template_columns(template);
+        if columns.is_empty() {
+            continue;
+        }
+        let headers = get_headers(items_sheet, template)
+            .iter()
+            .map(|header| normalize_header(header))
+            .collect::<Vec<String>>();
+        if !columns.keys().all(|header| headers.contains(header)) {
+            continue;
+        }
+        if matched.is_none_or(|(count, _)| columns.len() > count) {
+            matched = Some((columns.len(), template));
+        }
+    }
+
+    Ok(matched.map(|(_, template)| template.clone()))
+}
+
+/// 表头上面的行里按正则找订单日期/出货日期, 找不到的不填
+pub async fn parse_template_order_info(
+    file_path: &str,
+    template: &ExcelTemplateModel,
+) -> ERPResult<OrderInfo> {
+    let mut order_info = OrderInfo::default();
+
+    let sheets = read_xlsx(file_path)?;
+    let items_sheet = sheets
+        .get_sheet(&0)
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
+
+    let regex = |re: &str, default: &str| {
+        let re = if re.is_empty() { default } else { re };
+        Regex::new(re).map_err(|_| ERPError::ExcelError(format!("日期正则有误: {}", re)))
+    };
+    let order_date_re = regex(&template.order_date_regex, DEFAULT_ORDER_DATE_REGEX)?;
+    let delivery_date_re = regex(&template.delivery_date_regex, DEFAULT_DELIVERY_DATE_REGEX)?;
+
+    let (cols, _) = items_sheet.get_highest_column_and_row();
+    let j_to_name = HashMap::new();
+    let mut errors = RowErrors::new(items_sheet.get_name(), &j_to_name);
+
+    for row in 1..template.header_row.max(1) as u32 {
+        // 日期和标题可能在不同的格子里, 拼起来再找
+        let line = get_row_values(items_sheet, row, cols)
+            .into_iter()
+            .filter(|value| !value.is_empty())
+            .join(" ");
+
+        if let Some(captures) = order_date_re.captures(&line) {
+            let value = captures.get(1).map_or("", |m| m.as_str());
+            match parse_ymd(value) {
+                Some(order_date) => order_info.order_date = order_date,
+                None => errors.push(row, 0, value, "订单日期格式不对"),
+            }
+        }
+
+        if let Some(captures) = delivery_date_re.captures(&line) {
+            let value = captures.get(1).map_or("", |m| m.as_str());
+            match parse_ymd(value) {
+                Some(delivery_date) => order_info.delivery_date = delivery_date,
+                None => errors.push(row, 0, value, "出货日期格式不对"),
+            }
+        }
+    }
+    errors.into_result()?;
+
+    Ok(order_info)
+}
+
+/// 按模版的列解析订单, 编号为空的行是上一个商品的其他颜色
+pub async fn parse_template_order(
+    file_path: &str,
+    template: &ExcelTemplateModel,
+    dry_run: bool,
+) -> ERPResult<Vec<OrderExcelDto>> {
+    let sheets = read_xlsx(file_path)?;
+    let items_sheet = sheets
+        .get_sheet(&0)
+        .ok_or(ERPError::ExcelError("商品sheet未找到".to_string()))?;
+
+    let (cols, rows) = items_sheet.get_highest_column_and_row();
+    let field_to_j = get_field_to_j(
+        &get_headers(items_sheet, template),
+        &get_template_columns(template),
+    );
+    let missing = REQUIRED_FIELDS
+        .iter()
+        .filter(|field| !field_to_j.contains_key(**field))
+        .map(|field| field_name(field))
+        .collect::<Vec<&str>>();
+    if !missing.is_empty() {
+        return Err(ERPError::ExcelError(format!(
+            "模版[{}]的表头在第{}行没找到: {}",
+            template.name,
+            template.header_row,
+            missing.join(", ")
+        )));
+    }
+
+    let j_to_name = field_to_j
+        .iter()
+        .map(|(field, j)| (*j as i32, field_name(field)))
+        .collect::<HashMap<i32, &'static str>>();
+    let mut errors = RowErrors::new(items_sheet.get_name(), &j_to_name);
+    let j_of = |field: &str| field_to_j.get(field).copied().unwrap_or(0);
+
+    let mut items: Vec<OrderExcelDto> = vec![];
+    let mut row_to_raw_images: HashMap<u32, Vec<&Image>> = HashMap::new();
+    for i in template.data_start_row.max(1) as u32..rows + 1 {
+        let values = get_row_values(items_sheet, i, cols);
+        let value_of = |field: &str| match field_to_j.get(field) {
+            Some(j) => values[*j as usize - 1].as_str(),
+            None => "",
+        };
+
+        // 序号不是整数(合计等), 或者数量和单价都为空, 表示商品结束了
+        let index = value_of("index");
+        if !index.is_empty() && index.parse::<i32>().is_err()
+            || value_of("count").is_empty() && value_of("price").is_empty()
+        {
+            break;
+        }
+
+        let mut cur = OrderExcelDto {
+            row: i,
+            number: value_of("number").to_string(),
+            size: value_of("size").to_string(),
+            name: value_of("name").to_string(),
+            color: value_of("color").to_ascii_uppercase(),
+            unit: value_of("unit").to_string(),
+            notes: value_of("notes").to_string(),
+            ..Default::default()
+        };
+        cur.index = errors.parse_i32(i, j_of("index"), index);
+        cur.count = (errors.parse_f32(i, j_of("count"), value_of("count")) * 10.0).round() as i32;
+        cur.price = (errors.parse_f32(i, j_of("price"), value_of("price")) * 100.0).round() as i32;
+        cur.total = (errors.parse_f32(i, j_of("total"), value_of("total")) * 100.0).round() as i32;
+
+        match items.last() {
+            // 同一个商品的其他颜色, 编号等沿用上一行
+            Some(pre) if cur.number.is_empty() => {
+                cur.number = pre.number.clone();
+                if index.is_empty() {
+                    cur.index = pre.index;
+                }
+                if cur.size.is_empty() {
+                    cur.size = pre.size.clone();
+                }
+                if cur.name.is_empty() {
+                    cur.name = pre.name.clone();
+                }
+                if cur.unit.is_empty() {
+                    cur.unit = pre.unit.clone();
+                }
+            }
+            // 没有序号列时按商品顺序编
+            pre if index.is_empty() => cur.index = pre.map_or(1, |pre| pre.index + 1),
+            _ => {}
+        }
+        if value_of("total").is_empty() {
+            cur.total = cur.count * cur.price / 10;
+        }
+
+        if cur.number.is_empty() {
+            errors.push(i, j_of("number"), "", "不能为空");
+        }
+        if cur.color.is_empty() {
+            errors.push(i, j_of("color"), "", "不能为空");
+        }
+        for (field, value) in [("count", cur.count), ("price", cur.price)] {
+            match (value_of(field), value) {
+                ("", _) => errors.push(i, j_of(field), "", "不能为空"),
+                (raw, 0) => errors.push(i, j_of(field), raw, "不能为0"),
+                _ => {}
+            }
+        }
+
+        if let Some(j) = field_to_j.get("image") {
+            let images = items_sheet.get_images((*j, i));
+            if !images.is_empty() {
+                row_to_raw_images.insert(i, images);
+            }
+        }
+
+        tracing::info!("rows#{:?}: {:?}", i, cur);
+        items.push(cur);
+    }
+    errors.into_result()?;
+
+    // 检查都通过了再保存图片
+    let now_str = get_cur_date_str();
+    let mut row_to_images: HashMap<u32, Vec<String>> = HashMap::new();
+    for item in items.iter() {
+        let Some(images) = row_to_raw_images.remove(&item.row) else {
+            continue;
+        };
+        let mut image_urls = vec![];
+        for (k, real_goods_image) in images.into_iter().enumerate() {
+            let image_name = format!("{}/{}-{}-{}.png", &now_str, item.row, item.number, k);
+            let image_path = format!("{}/order/{}", STORAGE_FILE_PATH, image_name);
+            if !dry_run {
+                fs::create_dir_all(format!("{}/order/{}", STORAGE_FILE_PATH, &now_str))?;
+                real_goods_image.download_image(&image_path);
+            }
+            image_urls.push(format!("{}/order/{}", STORAGE_URL_PREFIX, image_name));
+        }
+        row_to_images.insert(item.row, image_urls);
+    }
+
+    // 没有图片的行用同一个编号的图片
+    let mut number_to_images: HashMap<String, Vec<String>> = HashMap::new();
+    for item in items.iter_mut() {
+        match row_to_images.remove(&item.row) {
+            Some(images) => {
+                number_to_images
+                    .entry(item.number.clone())
+                    .or_insert(images.clone());
+                item.images = images;
+            }
+            None => {
+                item.images = number_to_images
+                    .get(&item.number)
+                    .cloned()
+                    .unwrap_or_default();
+            }
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::parse_template_orders::{get_field_to_j, normalize_header};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_get_field_to_j() {
+        let columns = vec![
+            ("编号", "number"),
+            ("电镀&颜色", "color"),
+            ("数量", "count"),
+        ]
+        .into_iter()
+        .map(|(header, field)| (normalize_header(header), field.to_string()))
+        .collect::<HashMap<String, String>>();
+        let headers = vec!["序号", "编号", "电镀 & 颜色", "数 量", "编号"]
+            .into_iter()
+            .map(|header| header.to_string())
+            .collect::<Vec<String>>();
+
+        let field_to_j = get_field_to_j(&headers, &columns);
+        assert_eq!(field_to_j.len(), 3);
+        assert_eq!(field_to_j["number"], 2);
+        assert_eq!(field_to_j["color"], 3);
+        assert_eq!(field_to_j["count"], 4);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::constants::{IMPORT_JOB_STATUS_RUNNING, IMPORT_PHASE_QUEUED};
+use crate::dto::dto_import_job::ImportProgressDto;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// 正在后台导入的进度, 导入结束后从这里删掉
+pub type ImportProgressMap = Arc<Mutex<HashMap<i32, ImportProgressDto>>>;
+
+#[derive(Clone)]
+pub struct ImportProgress {
+    job_id: i32,
+    progress: ImportProgressMap,
+}
+
+impl ImportProgress {
+    pub fn new(job_id: i32, progress: &ImportProgressMap) -> Self {
+        let import_progress = Self {
+            job_id,
+            progress: Arc::clone(progress),
+        };
+        import_progress.set_phase(IMPORT_PHASE_QUEUED, 0);
+        import_progress
+    }
+
+    pub fn set_phase(&self, phase: &str, total: i32) {
+        self.update(|p| {
+            p.phase = phase.to_string();
+            p.processed = 0;
+            p.total = total;
+        });
+    }
+
+    pub fn set_processed(&self, processed: i32) {
+        self.update(|p| p.processed = processed);
+    }
+
+    pub fn finish(&self) {
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.remove(&self.job_id);
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ImportProgressDto)) {
+        if let Ok(mut progress) = self.progress.lock() {
+            let entry = progress
+                .entry(self.job_id)
+                .or_insert_with(|| ImportProgressDto {
+                    id: self.job_id,
+                    status: IMPORT_JOB_STATUS_RUNNING,
+                    phase: IMPORT_PHASE_QUEUED.to_string(),
+                    processed: 0,
+                    total: 0,
+                });
+            f(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::excel::progress::{ImportProgress, ImportProgressMap};
+
+    #[test]
+    fn test_import_progress() {
+        let map = ImportProgressMap::default();
+        let progress = ImportProgress::new(1, &map);
+        progress.set_phase("写入数据库", 10);
+        progress.set_processed(3);
+        {
+            let map = map.lock().unwrap();
+            let p = map.get(&1).unwrap();
+            assert_eq!(
+                (p.phase.as_str(), p.processed, p.total),
+                ("写入数据库", 3, 10)
+            );
+        }
+
+        progress.finish();
+        assert!(map.lock().unwrap().is_empty());
+    }
+}
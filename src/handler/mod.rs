@@ -1,6 +1,8 @@
 use crate::config::database::Database;
-use crate::middleware::auth::auth;
+use crate::config::parameter;
+use crate::middleware::jwt_auth::auth;
 use crate::state::account_state::AccountState;
+use crate::state::audit_state::AuditState;
 use crate::state::cate_state::CateState;
 use crate::state::customer_state::CustomerState;
 use crate::state::embryo_state::EmbryoState;
@@ -8,15 +10,18 @@ use crate::state::excel_state::ExcelState;
 use crate::state::item_state::ItemState;
 use crate::state::order_state::OrderState;
 use crate::state::settings_state::SettingsState;
+use crate::state::stocktake_state::StocktakeState;
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
 use axum::extract::DefaultBodyLimit;
 use axum::http::{header, Method};
 use axum::response::Response;
-use axum::routing::IntoMakeService;
 use axum::Router;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 
 mod routes_account;
+mod routes_audit;
 mod routes_cates;
 mod routes_customer;
 mod routes_embryo;
@@ -26,6 +31,7 @@ mod routes_login;
 mod routes_orders;
 mod routes_settings;
 mod routes_static;
+mod routes_stocktake;
 mod routes_upload;
 
 pub trait ListParamToSQLTrait {
@@ -37,7 +43,11 @@ pub trait CreateOrUpdateParamToSQLTrait {
     fn to_sql(&self) -> String;
 }
 
-pub fn routes(db: Arc<Database>) -> IntoMakeService<Router> {
+pub fn routes(db: Arc<Database>) -> IntoMakeServiceWithConnectInfo<Router, SocketAddr> {
+    app(db).into_make_service_with_connect_info::<SocketAddr>()
+}
+
+fn app(db: Arc<Database>) -> Router {
     let cors = CorsLayer::new()
         .allow_origin([
             "https://store-web-five.vercel.app".parse().unwrap(),
@@ -56,7 +66,7 @@ pub fn routes(db: Arc<Database>) -> IntoMakeService<Router> {
         ]);
 
     let auth_state = AccountState::new(&db);
-    let routes_all = Router::new()
+    Router::new()
         .with_state(auth_state.clone())
         .merge(routes_login::routes().with_state(AccountState::new(&db)))
         // .layer(axum::middleware::from_fn_with_state(auth_state.clone(), auth))
@@ -92,6 +102,14 @@ pub fn routes(db: Arc<Database>) -> IntoMakeService<Router> {
                     auth,
                 )),
         )
+        .merge(
+            routes_audit::routes()
+                .with_state(AuditState::new(&db))
+                .layer(axum::middleware::from_fn_with_state(
+                    auth_state.clone(),
+                    auth,
+                )),
+        )
         .merge(
             routes_embryo::routes()
                 .with_state(EmbryoState::new(&db))
@@ -108,7 +126,12 @@ pub fn routes(db: Arc<Database>) -> IntoMakeService<Router> {
                     auth,
                 )),
         )
-        .merge(routes_upload::routes())
+        .merge(
+            routes_upload::routes().layer(axum::middleware::from_fn_with_state(
+                auth_state.clone(),
+                auth,
+            )),
+        )
         .merge(
             routes_items::routes()
                 .with_state(ItemState::new(&db))
@@ -117,18 +140,120 @@ pub fn routes(db: Arc<Database>) -> IntoMakeService<Router> {
                     auth,
                 )),
         )
-        .merge(routes_settings::routes().with_state(SettingsState::new(&db)))
+        .merge(
+            routes_settings::routes()
+                .with_state(SettingsState::new(&db))
+                .layer(axum::middleware::from_fn_with_state(
+                    auth_state.clone(),
+                    auth,
+                )),
+        )
+        .merge(
+            routes_stocktake::routes()
+                .with_state(StocktakeState::new(&db))
+                .layer(axum::middleware::from_fn_with_state(
+                    auth_state.clone(),
+                    auth,
+                )),
+        )
         // todo: for test
         .layer(axum::middleware::map_response(main_response_mapper))
         .fallback_service(routes_static::routes())
-        .layer(DefaultBodyLimit::max(usize::MAX))
-        .layer(cors);
+        .layer(DefaultBodyLimit::max(upload_max_size()))
+        .layer(cors)
+}
 
-    routes_all.into_make_service()
+/// 上传文件大小上限, 默认20MB, 用 UPLOAD_MAX_SIZE_MB 配置
+fn upload_max_size() -> usize {
+    parameter::get_or("UPLOAD_MAX_SIZE_MB", "20")
+        .parse::<usize>()
+        .unwrap_or(20)
+        * 1024
+        * 1024
 }
+
 async fn main_response_mapper(res: Response) -> Response {
     tracing::info!("->> {:<12} - main_response_mapper", "res_mapper");
     tracing::info!("{:?}", res.headers());
     // tokio::time::sleep(std::time::Duration::new(2, 0)).await;
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::database::Database;
+    use crate::handler::app;
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use regex::Regex;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// 不需要登录即可访问的接口
+    const ANONYMOUS_ALLOWLIST: [&str; 2] = ["/api/login", "/api/logout"];
+
+    /// 从各个 routes_*.rs 中找出注册的接口及其请求方法
+    fn registered_routes() -> Vec<(Method, String)> {
+        let sources = [
+            include_str!("routes_account.rs"),
+            include_str!("routes_audit.rs"),
+            include_str!("routes_cates.rs"),
+            include_str!("routes_customer.rs"),
+            include_str!("routes_embryo.rs"),
+            include_str!("routes_excel.rs"),
+            include_str!("routes_items.rs"),
+            include_str!("routes_login.rs"),
+            include_str!("routes_orders.rs"),
+            include_str!("routes_settings.rs"),
+            include_str!("routes_stocktake.rs"),
+            include_str!("routes_upload.rs"),
+        ];
+        let re = Regex::new(r#"\.route\(\s*"([^"]+)",\s*(get|post)\("#).unwrap();
+
+        sources
+            .iter()
+            .flat_map(|source| re.captures_iter(source))
+            .map(|cap| {
+                let method = match &cap[2] {
+                    "get" => Method::GET,
+                    _ => Method::POST,
+                };
+                (method, cap[1].to_string())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_routes_reject_anonymous() {
+        // 未登录的请求在 auth 中间件就被拒绝, 不会访问数据库
+        let db = Arc::new(Database::connect_lazy("postgres://localhost/unused").unwrap());
+
+        let routes = registered_routes();
+        assert!(routes.iter().any(|(_, path)| path == "/api/upload/image"));
+        assert!(routes
+            .iter()
+            .any(|(_, path)| path == "/api/settings/edit/color/value"));
+
+        for (method, path) in routes {
+            if ANONYMOUS_ALLOWLIST.contains(&path.as_str()) {
+                continue;
+            }
+
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(&path)
+                .body(Body::empty())
+                .unwrap();
+            let response = app(db.clone()).oneshot(request).await.unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body)
+                .unwrap_or_else(|_| panic!("{} {} did not return json", method, path));
+
+            assert_eq!(
+                json["code"], 401,
+                "{} {} should reject anonymous requests",
+                method, path
+            );
+        }
+    }
+}
@@ -1,13 +1,55 @@
-use crate::dto::dto_account::AccountDto;
-use crate::response::api_response::APIDataResponse;
+use crate::constants::ROLE_ADMIN;
+use crate::dto::dto_account::{
+    AccountDto, ChangePasswordParams, CreateAccountParams, DisableAccountParams,
+    LoginAttemptQueryParams, QueryParams, ResetPasswordParams, UnlockAccountParams,
+    UpdateRoleParams,
+};
+use crate::middleware::permission::permission;
+use crate::model::account::LoginAttemptModel;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::service::account_service::AccountServiceTrait;
 use crate::state::account_state::AccountState;
-use crate::ERPResult;
-use axum::extract::State;
-use axum::routing::get;
-use axum::{Extension, Router};
+use crate::{ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::middleware::from_fn_with_state;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum_extra::extract::WithRejection;
 
 pub fn routes() -> Router<AccountState> {
-    Router::new().route("/api/account/info", get(account_info))
+    let admin = from_fn_with_state(&[ROLE_ADMIN][..], permission);
+
+    Router::new()
+        .route("/api/account/info", get(account_info))
+        .route("/api/account/change/password", post(api_change_password))
+        .route(
+            "/api/accounts",
+            get(api_account_list).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/account/create",
+            post(api_create_account).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/account/reset/password",
+            post(api_reset_password).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/account/disable",
+            post(api_disable_account).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/account/role",
+            post(api_update_role).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/account/unlock",
+            post(api_unlock_account).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/login/attempts",
+            get(api_login_attempt_list).route_layer(admin),
+        )
 }
 
 pub async fn account_info(
@@ -16,3 +58,136 @@ pub async fn account_info(
 ) -> ERPResult<APIDataResponse<AccountDto>> {
     Ok(APIDataResponse::new(account))
 }
+
+async fn api_account_list(
+    State(state): State<AccountState>,
+    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<AccountDto>> {
+    tracing::info!("->> {:<12}, api_account_list", "handler");
+
+    let accounts = state.account_service.get_account_list(&params).await?;
+    let count = state.account_service.get_account_count(&params).await?;
+
+    Ok(APIListResponse::new(accounts, count))
+}
+
+#[derive(Serialize)]
+struct AccountId {
+    id: i32,
+}
+
+async fn api_create_account(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<CreateAccountParams>, ERPError>,
+) -> ERPResult<APIDataResponse<AccountId>> {
+    tracing::info!("->> {:<12}, api_create_account", "handler");
+
+    let id = state
+        .account_service
+        .create_account(&params, account.id)
+        .await?;
+
+    Ok(APIDataResponse::new(AccountId { id }))
+}
+
+async fn api_change_password(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<ChangePasswordParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_change_password", "handler");
+
+    state
+        .account_service
+        .change_password(account.id, &params)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_reset_password(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<ResetPasswordParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_reset_password", "handler");
+
+    state
+        .account_service
+        .reset_password(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_disable_account(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<DisableAccountParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_disable_account", "handler");
+
+    if params.id == account.id && params.disabled {
+        return Err(ERPError::Failed("不能禁用自己的账号".to_string()));
+    }
+
+    state
+        .account_service
+        .disable_account(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_update_role(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UpdateRoleParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_update_role", "handler");
+
+    if params.id == account.id && params.role != ROLE_ADMIN {
+        return Err(ERPError::Failed("不能取消自己的管理员角色".to_string()));
+    }
+
+    state
+        .account_service
+        .update_role(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_unlock_account(
+    State(state): State<AccountState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UnlockAccountParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_unlock_account", "handler");
+
+    state
+        .account_service
+        .unlock_account(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_login_attempt_list(
+    State(state): State<AccountState>,
+    WithRejection(Query(params), _): WithRejection<Query<LoginAttemptQueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<LoginAttemptModel>> {
+    tracing::info!("->> {:<12}, api_login_attempt_list", "handler");
+
+    let attempts = state
+        .account_service
+        .get_login_attempt_list(&params)
+        .await?;
+    let count = state
+        .account_service
+        .get_login_attempt_count(&params)
+        .await?;
+
+    Ok(APIListResponse::new(attempts, count))
+}
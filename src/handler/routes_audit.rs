@@ -0,0 +1,31 @@
+use crate::constants::ROLE_ADMIN;
+use crate::dto::dto_audit::{AuditLogDto, QueryParams};
+use crate::middleware::permission::permission;
+use crate::response::api_response::APIListResponse;
+use crate::service::audit_service::AuditServiceTrait;
+use crate::state::audit_state::AuditState;
+use crate::{ERPError, ERPResult};
+use axum::extract::{Query, State};
+use axum::middleware::from_fn_with_state;
+use axum::routing::get;
+use axum::Router;
+use axum_extra::extract::WithRejection;
+
+pub fn routes() -> Router<AuditState> {
+    Router::new().route(
+        "/api/audit",
+        get(api_audit_log_list).route_layer(from_fn_with_state(&[ROLE_ADMIN][..], permission)),
+    )
+}
+
+async fn api_audit_log_list(
+    State(state): State<AuditState>,
+    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<AuditLogDto>> {
+    tracing::info!("->> {:<12}, api_audit_log_list", "handler");
+
+    let audit_logs = state.audit_service.get_audit_log_list(&params).await?;
+    let count = state.audit_service.get_audit_log_count(&params).await?;
+
+    Ok(APIListResponse::new(audit_logs, count))
+}
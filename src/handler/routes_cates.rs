@@ -39,20 +39,22 @@ async fn api_sub_cates_list(
 
 async fn api_edit_cate(
     State(state): State<CateState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<EditParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    state.cate_service.edit_cates(&params).await?;
+    state.cate_service.edit_cates(&params, account.id).await?;
 
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_delete_cate(
     State(state): State<CateState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, delete_get_color_values", "handler");
 
-    state.cate_service.delete_cate(&params).await?;
+    state.cate_service.delete_cate(&params, account.id).await?;
 
     Ok(APIEmptyResponse::new())
 }
@@ -1,3 +1,4 @@
+use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_customer::{
     CustomerDeleteParam, CustomerDto, CustomerEditParam, CustomerSearchParam,
 };
@@ -9,7 +10,7 @@ use crate::state::customer_state::CustomerState;
 use crate::{ERPError, ERPResult};
 use axum::extract::{Query, State};
 use axum::routing::{get, post};
-use axum::{Json, Router};
+use axum::{Extension, Json, Router};
 use axum_extra::extract::WithRejection;
 use std::collections::HashMap;
 
@@ -74,16 +75,24 @@ async fn get_customers(
 
 async fn edit_customer(
     State(state): State<CustomerState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(param), _): WithRejection<Json<CustomerEditParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    state.customer_service.edit_customer(&param).await?;
+    state
+        .customer_service
+        .edit_customer(&param, account.id)
+        .await?;
     Ok(APIEmptyResponse::new())
 }
 
 async fn delete_customer(
     State(state): State<CustomerState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Query(param), _): WithRejection<Query<CustomerDeleteParam>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    state.customer_service.delete_customer(param.id).await?;
+    state
+        .customer_service
+        .delete_customer(param.id, account.id)
+        .await?;
     Ok(APIEmptyResponse::new())
 }
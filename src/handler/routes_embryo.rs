@@ -1,26 +1,34 @@
-use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::constants::{DEFAULT_PAGE_SIZE, ROLE_WAREHOUSE, ROLE_WORKSHOP};
 use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_embryo::{
     EditParams, EmbryoDto, EmbryoInOutBucketDto, EmbryoInOutDto, InoutBucketParams,
     InoutListOfBucketParams, InoutListParams, InoutParams, QueryParams,
 };
 use crate::dto::GenericDeleteParams;
+use crate::middleware::permission::permission;
 use crate::repository::embryo_repository::EmbryoRepositoryTrait;
 use crate::response::api_response::{APIEmptyResponse, APIListResponse};
 use crate::service::embryo_service::EmbryoServiceTrait;
 use crate::state::embryo_state::EmbryoState;
 use crate::{ERPError, ERPResult};
 use axum::extract::{Query, State};
+use axum::middleware::from_fn_with_state;
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_extra::extract::WithRejection;
 
 pub fn routes() -> Router<EmbryoState> {
+    let warehouse_or_workshop =
+        from_fn_with_state(&[ROLE_WAREHOUSE, ROLE_WORKSHOP][..], permission);
+
     Router::new()
         .route("/api/embryos", get(api_item_list))
         .route("/api/embryo/edit", post(api_item_edit))
         .route("/api/embryo/delete", post(api_item_delete))
-        .route("/api/embryo/inout", post(api_item_inout))
+        .route(
+            "/api/embryo/inout",
+            post(api_item_inout).route_layer(warehouse_or_workshop),
+        )
         .route("/api/embryo/inout/list", get(api_inout_list))
         .route("/api/embryo/inout/group/list", get(api_inout_group_list)) // 出入库列表
         .route(
@@ -43,21 +51,26 @@ async fn api_item_list(
 
 async fn api_item_edit(
     State(state): State<EmbryoState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<EditParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("api_item_edit : /api/embryo/edit");
 
-    state.embryo_service.edit_item(&params).await?;
+    state.embryo_service.edit_item(&params, account.id).await?;
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_item_delete(
     State(state): State<EmbryoState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("api_item_delete : /api/embryo/delete");
 
-    state.embryo_service.delete_item(&params).await?;
+    state
+        .embryo_service
+        .delete_item(&params, account.id)
+        .await?;
     Ok(APIEmptyResponse::new())
 }
 
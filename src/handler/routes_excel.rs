@@ -1,36 +1,98 @@
 use crate::config::database::DatabaseTrait;
+use crate::constants::{
+    DEFAULT_WAREHOUSE_ID, IMPORT_JOB_STATUS_FAILED, IMPORT_JOB_STATUS_ROLLED_BACK,
+    IMPORT_JOB_STATUS_RUNNING, IMPORT_JOB_STATUS_SUCCEEDED, IMPORT_PHASE_DONE, IMPORT_PHASE_FAILED,
+    IMPORT_PHASE_PARSING, IMPORT_PHASE_QUEUED, IMPORT_PHASE_ROLLED_BACK, IMPORT_PHASE_SAVING,
+    ORDER_STATUS_COMPLETED, ROLE_ADMIN, STORAGE_FILE_PATH,
+};
 use crate::dto::dto_account::AccountDto;
-use crate::dto::dto_excel::{EmbryoExcelDto, ItemExcelDto, OrderExcelDto};
+use crate::dto::dto_excel::{
+    CustomerExcelTemplateParams, EmbryoExcelDto, ExcelTemplateEditParams, ImportErrorDto,
+    ImportPreviewDto, ImportPreviewRowDto, ItemExcelDto, OrderExcelDto,
+};
+use crate::dto::dto_import_job::{
+    ImportCounts, ImportJobDto, ImportJobParams, ImportProgressDto, ImportRollbackDto, QueryParams,
+};
+use crate::dto::GenericDeleteParams;
+use crate::excel::common::{get_first_sheet_name, RowErrors};
 use crate::excel::parse_embryo::parse_embryos;
-use crate::excel::parse_items::parse_items;
-use crate::excel::parse_legacy_orders::{parse_legacy_order, parse_legacy_order_info};
-use crate::excel::parse_orders::{parse_order, parse_order_info};
+use crate::excel::parse_items::{
+    discard_item_images, parse_items, publish_item_images, stage_item_images,
+    J_TO_NAME as ITEM_J_TO_NAME,
+};
+use crate::excel::parse_legacy_orders::{
+    parse_legacy_order, parse_legacy_order_info, J_TO_NAME as LEGACY_ORDER_J_TO_NAME,
+};
+use crate::excel::parse_orders::{parse_order, parse_order_info, OrderInfo};
+use crate::excel::parse_template_orders::{
+    detect_template, parse_template_order, parse_template_order_info,
+};
+use crate::excel::progress::ImportProgress;
+use crate::middleware::permission::permission;
 use crate::model::cates::CateModel;
 use crate::model::embryo::{EmbryoInOutBucketModal, EmbryoInOutModel};
+use crate::model::excel::{CustomerExcelTemplateModel, ExcelTemplateModel};
+use crate::model::import_job::ImportJobModel;
 use crate::model::items::{ItemInOutBucketModal, ItemsInOutModel, ItemsModel};
 use crate::model::order::{ImportedOrderItemModel, OrderItemModel, OrderModel};
-use crate::response::api_response::APIEmptyResponse;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::service::audit_service::AuditServiceTrait;
 use crate::service::cates_service::CateServiceTrait;
 use crate::service::embryo_service::EmbryoServiceTrait;
+use crate::service::excel_template_service::ExcelTemplateServiceTrait;
+use crate::service::import_job_service::ImportJobServiceTrait;
 use crate::service::item_service::ItemServiceTrait;
 use crate::service::order_service::OrderServiceTrait;
 use crate::service::settings_service::SettingsServiceTrait;
+use crate::service::stock_service::StockServiceTrait;
 use crate::state::excel_state::ExcelState;
 use crate::{ERPError, ERPResult};
-use axum::extract::{Multipart, State};
-use axum::response::{Html, IntoResponse};
+use axum::extract::multipart::MultipartError;
+use axum::extract::{Multipart, Query, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::from_fn_with_state;
+use axum::response::{Html, IntoResponse, Response};
 use axum::routing::{get, post};
-use axum::{Extension, Router};
+use axum::{Extension, Json, Router};
+use axum_extra::extract::WithRejection;
 use chrono::{Datelike, Timelike, Utc};
+use itertools::Itertools;
 use rand::Rng;
+use sqlx::PgConnection;
 use std::collections::HashMap;
 use std::default::Default;
 use std::fs;
 
 pub fn routes() -> Router<ExcelState> {
+    let admin = from_fn_with_state(&[ROLE_ADMIN][..], permission);
+
     Router::new()
         .route("/page/upload", get(page_upload_file))
         .route("/api/upload/excel", post(import_excel))
+        .route("/api/import/jobs", get(api_import_job_list))
+        .route("/api/import/job/file", get(api_import_job_file))
+        .route("/api/import/job/progress", get(api_import_job_progress))
+        .route(
+            "/api/import/job/rollback",
+            post(api_import_job_rollback).route_layer(admin.clone()),
+        )
+        .route("/api/excel/templates", get(api_get_excel_templates))
+        .route(
+            "/api/excel/template/edit",
+            post(api_edit_excel_template).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/excel/template/delete",
+            post(api_delete_excel_template).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/excel/customer/templates",
+            get(api_get_customer_excel_templates),
+        )
+        .route(
+            "/api/excel/customer/template",
+            post(api_set_customer_excel_template).route_layer(admin),
+        )
 }
 
 async fn page_upload_file(Extension(_account): Extension<AccountDto>) -> impl IntoResponse {
@@ -56,16 +118,26 @@ async fn import_excel(
     State(state): State<ExcelState>,
     Extension(account): Extension<AccountDto>,
     mut multipart: Multipart,
-) -> ERPResult<APIEmptyResponse> {
+) -> ERPResult<Response> {
     let mut file_path: String = "".to_string();
+    let mut filename: String = "".to_string();
     let mut tp: i32 = 0;
     let mut customer_id: i32 = 0;
+    let mut dry_run = false;
 
     // 获取 二进制文件，保存到本地/tmp，并且目录是当前的时间
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| multipart_error(e, "上传的表单有误"))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let upload_filename = field.file_name().unwrap_or_default().to_string();
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| multipart_error(e, &format!("读取 {} 失败", name)))?;
         if name == "file" {
-            let data = field.bytes().await.unwrap();
             let now = Utc::now();
             let mut rng = rand::thread_rng();
             let file_name = format!(
@@ -84,12 +156,16 @@ async fn import_excel(
                 ERPError::SaveFileFailed(format!("create {} failed", file_path_full))
             })?;
             file_path = file_path_full;
+            filename = upload_filename;
         } else if name == "tp" {
-            let data = String::from_utf8(field.bytes().await.unwrap().to_vec()).unwrap();
+            let data = String::from_utf8_lossy(&data);
             tp = data.parse::<i32>().unwrap_or(0);
         } else if name == "customer_id" {
-            let data = String::from_utf8(field.bytes().await.unwrap().to_vec()).unwrap();
+            let data = String::from_utf8_lossy(&data);
             customer_id = data.parse::<i32>().unwrap_or(0);
+        } else if name == "dry_run" {
+            let data = String::from_utf8_lossy(&data);
+            dry_run = data.trim() == "1" || data.trim() == "true";
         }
     }
 
@@ -98,6 +174,81 @@ async fn import_excel(
         return Err(ERPError::Failed("save excel file failed".to_string()));
     }
 
+    // 只预览, 不写库
+    if dry_run {
+        let color_to_value = get_color_to_value(&state).await?;
+        let report = preview_excel(&state, &file_path, tp, customer_id, color_to_value).await;
+        let _ = fs::remove_file(&file_path);
+        return Ok(APIDataResponse::new(report?).into_response());
+    }
+
+    // 原文件保存下来, 导入记录里可以下载
+    let file_path = store_import_file(&file_path)?;
+    let job_id = state
+        .import_job_service
+        .add_import_job(&ImportJobModel {
+            id: 0,
+            account_id: account.id,
+            tp,
+            customer_id,
+            filename,
+            file_path: file_path.clone(),
+            row_count: 0,
+            created_count: 0,
+            updated_count: 0,
+            status: IMPORT_JOB_STATUS_RUNNING,
+            errors: None,
+            create_time: Utc::now(),
+            finish_time: None,
+        })
+        .await?;
+    let job = state.import_job_service.get_import_job(job_id).await?;
+    let job = ImportJobDto::from(job, &account.name);
+
+    // 放到后台排队导入, 先返回导入记录, 进度用 /api/import/job/progress 查
+    let progress = ImportProgress::new(job_id, &state.import_progress);
+    tokio::spawn(async move {
+        let _permit = state.import_queue.acquire().await;
+
+        // 单独起一个task, 导入时panic了也能把导入记录标记为失败
+        let task_state = state.clone();
+        let task_account = account.clone();
+        let task_progress = progress.clone();
+        let result = tokio::spawn(async move {
+            run_import_job(
+                &task_state,
+                &task_account,
+                job_id,
+                tp,
+                customer_id,
+                &file_path,
+                &task_progress,
+            )
+            .await
+        })
+        .await
+        .unwrap_or_else(|e| Err(ERPError::Failed(format!("导入出错: {}", e))));
+
+        if let Err(err) = finish_import_job(&state, job_id, result).await {
+            tracing::error!("finish import job {} failed: {:?}", job_id, err);
+        }
+        progress.finish();
+    });
+
+    Ok(APIDataResponse::new(job).into_response())
+}
+
+pub(super) fn multipart_error(e: MultipartError, msg: &str) -> ERPError {
+    match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ERPError::ParamError(format!(
+            "文件太大, 不能超过{}MB",
+            super::upload_max_size() / 1024 / 1024
+        )),
+        _ => ERPError::ParamError(format!("{}: {}", msg, e)),
+    }
+}
+
+async fn get_color_to_value(state: &ExcelState) -> ERPResult<HashMap<String, i32>> {
     let color_to_value = state
         .settings_service
         .get_all_color_to_values()
@@ -106,18 +257,610 @@ async fn import_excel(
         .map(|cv| (cv.color, cv.value))
         .collect::<HashMap<_, _>>();
 
+    Ok(color_to_value)
+}
+
+async fn run_import_job(
+    state: &ExcelState,
+    account: &AccountDto,
+    job_id: i32,
+    tp: i32,
+    customer_id: i32,
+    file_path: &str,
+    progress: &ImportProgress,
+) -> ERPResult<ImportCounts> {
+    progress.set_phase(IMPORT_PHASE_PARSING, 0);
+    let color_to_value = get_color_to_value(state).await?;
+
     match tp {
-        0 => process_item_excel(&state, &file_path, color_to_value, &account).await?,
-        1 => process_embryo_excel(&state, &file_path, color_to_value, &account).await?,
-        3 => process_order_excel(&state, &file_path, color_to_value, customer_id, &account).await?,
+        1 => {
+            process_embryo_excel(state, file_path, color_to_value, account, job_id, progress).await
+        }
+        3 => {
+            process_order_excel(
+                state,
+                file_path,
+                color_to_value,
+                customer_id,
+                account,
+                job_id,
+                progress,
+            )
+            .await
+        }
         4 => {
-            process_legacy_order_excel(&state, &file_path, color_to_value, customer_id, &account)
-                .await?
+            process_legacy_order_excel(
+                state,
+                file_path,
+                color_to_value,
+                customer_id,
+                account,
+                job_id,
+                progress,
+            )
+            .await
+        }
+        _ => {
+            let result =
+                process_item_excel(state, file_path, color_to_value, account, job_id, progress)
+                    .await;
+            discard_item_images(job_id);
+            result
         }
-        _ => process_item_excel(&state, &file_path, color_to_value, &account).await?,
     }
+}
 
-    Ok(APIEmptyResponse::new())
+/// 记录导入结果
+async fn finish_import_job(
+    state: &ExcelState,
+    job_id: i32,
+    result: ERPResult<ImportCounts>,
+) -> ERPResult<()> {
+    let counts = match result {
+        Ok(counts) => counts,
+        Err(err) => {
+            tracing::error!("import job {} failed: {:?}", job_id, err);
+            let errors = match &err {
+                ERPError::ExcelRowErrors(errors) => serde_json::json!(errors),
+                _ => serde_json::json!([]),
+            };
+            return state
+                .import_job_service
+                .finish_import_job(
+                    job_id,
+                    IMPORT_JOB_STATUS_FAILED,
+                    &ImportCounts::default(),
+                    Some(serde_json::json!({"msg": err.to_string(), "errors": errors})),
+                )
+                .await;
+        }
+    };
+
+    // 库存不足但允许出库的, 记在导入记录里提示
+    let warnings = match counts.warnings.is_empty() {
+        true => None,
+        false => Some(serde_json::json!({"msg": "库存不足", "warnings": counts.warnings})),
+    };
+    state
+        .import_job_service
+        .finish_import_job(job_id, IMPORT_JOB_STATUS_SUCCEEDED, &counts, warnings)
+        .await
+}
+
+/// 导入会批量写入多张表, 在导入的事务里按导入记一条操作日志
+async fn add_import_audit_log(
+    state: &ExcelState,
+    conn: &mut PgConnection,
+    account_id: i32,
+    job_id: i32,
+    counts: &ImportCounts,
+) -> ERPResult<()> {
+    let job = sqlx::query!(
+        "select tp, customer_id from import_jobs where id = $1",
+        job_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    state
+        .audit_service
+        .add_audit_log(
+            conn,
+            account_id,
+            "import_jobs",
+            job_id,
+            "import",
+            None,
+            Some(serde_json::json!({
+                "tp": job.tp,
+                "customer_id": job.customer_id,
+                "row_count": counts.row_count,
+                "created_count": counts.created_count,
+                "updated_count": counts.updated_count,
+            })),
+        )
+        .await
+}
+
+/// 把上传的临时文件移到 {STORAGE_FILE_PATH}/import/{日期}/ 下
+fn store_import_file(tmp_path: &str) -> ERPResult<String> {
+    let now = Utc::now();
+    let dir_path = format!(
+        "{}/import/{}{:02}{:02}",
+        STORAGE_FILE_PATH,
+        now.year(),
+        now.month(),
+        now.day()
+    );
+    fs::create_dir_all(&dir_path)
+        .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", dir_path)))?;
+
+    let file_name = tmp_path.rsplit('/').next().unwrap_or_default();
+    let file_path = format!("{}/{}", dir_path, file_name);
+    // /tmp 可能和存储目录不在一个分区, 不能直接rename
+    fs::copy(tmp_path, &file_path)
+        .map_err(|_| ERPError::SaveFileFailed(format!("create {} failed", file_path)))?;
+    let _ = fs::remove_file(tmp_path);
+
+    Ok(file_path)
+}
+
+async fn api_import_job_list(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(mut params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<ImportJobDto>> {
+    tracing::info!("->> {:<12}, api_import_job_list", "handler");
+
+    // 非管理员只能看自己的导入记录
+    if account.role != ROLE_ADMIN {
+        params.account_id = Some(account.id);
+    }
+
+    let jobs = state
+        .import_job_service
+        .get_import_job_list(&params)
+        .await?;
+    let count = state
+        .import_job_service
+        .get_import_job_count(&params)
+        .await?;
+
+    Ok(APIListResponse::new(jobs, count))
+}
+
+/// 后台导入的进度, 导入结束了按导入记录的状态返回
+async fn api_import_job_progress(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(params), _): WithRejection<Query<ImportJobParams>, ERPError>,
+) -> ERPResult<APIDataResponse<ImportProgressDto>> {
+    tracing::info!("->> {:<12}, api_import_job_progress", "handler");
+
+    let job = state.import_job_service.get_import_job(params.id).await?;
+    if account.role != ROLE_ADMIN && job.account_id != account.id {
+        return Err(ERPError::NoPermission("只能查看自己的导入".to_string()));
+    }
+
+    let progress = state
+        .import_progress
+        .lock()
+        .ok()
+        .and_then(|progress| progress.get(&job.id).cloned());
+    let progress = progress.unwrap_or_else(|| {
+        let phase = match job.status {
+            IMPORT_JOB_STATUS_SUCCEEDED => IMPORT_PHASE_DONE,
+            IMPORT_JOB_STATUS_ROLLED_BACK => IMPORT_PHASE_ROLLED_BACK,
+            IMPORT_JOB_STATUS_FAILED => IMPORT_PHASE_FAILED,
+            _ => IMPORT_PHASE_QUEUED,
+        };
+        ImportProgressDto {
+            id: job.id,
+            status: job.status,
+            phase: phase.to_string(),
+            processed: job.row_count,
+            total: job.row_count,
+        }
+    });
+
+    Ok(APIDataResponse::new(progress))
+}
+
+/// 下载导入时上传的原文件
+async fn api_import_job_file(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(params), _): WithRejection<Query<ImportJobParams>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    tracing::info!("->> {:<12}, api_import_job_file", "handler");
+
+    let job = state.import_job_service.get_import_job(params.id).await?;
+    if account.role != ROLE_ADMIN && job.account_id != account.id {
+        return Err(ERPError::NoPermission("只能下载自己上传的文件".to_string()));
+    }
+
+    let bytes = tokio::fs::read(&job.file_path)
+        .await
+        .map_err(|_| ERPError::NotFound("导入的原文件".to_string()))?;
+    let filename = match job.filename.is_empty() {
+        true => format!("import-{}.xlsx", job.id),
+        false => job.filename,
+    };
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"import-{}.xlsx\"; filename*=UTF-8''{}",
+                    job.id,
+                    encode_filename(&filename)
+                ),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// 撤销一次导入: 删掉导入新增的数据, 被后面的数据用到了或者库存不够减回去就不让撤销;
+/// 对已有商品的修改不会还原
+async fn api_import_job_rollback(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<ImportJobParams>, ERPError>,
+) -> ERPResult<APIDataResponse<ImportRollbackDto>> {
+    tracing::info!("->> {:<12}, api_import_job_rollback", "handler");
+
+    let rollback = state
+        .import_job_service
+        .rollback_import_job(params.id, account.id)
+        .await?;
+
+    Ok(APIDataResponse::new(rollback))
+}
+
+/// 中文文件名按 RFC 5987 百分号编码
+fn encode_filename(filename: &str) -> String {
+    filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// 解析和检查都走一遍, 不写数据库也不保存图片
+async fn preview_excel(
+    state: &ExcelState,
+    file_path: &str,
+    tp: i32,
+    customer_id: i32,
+    color_to_value: HashMap<String, i32>,
+) -> ERPResult<ImportPreviewDto> {
+    let mut report = ImportPreviewDto {
+        tp,
+        ..Default::default()
+    };
+
+    let res = match tp {
+        1 => preview_embryo_excel(state, file_path, &mut report).await,
+        3 => preview_order_excel(state, file_path, customer_id, &mut report).await,
+        4 => preview_legacy_order_excel(state, file_path, customer_id, &mut report).await,
+        _ => preview_item_excel(state, file_path, color_to_value, &mut report).await,
+    };
+
+    // 表格本身的问题放到报告里, 数据库/文件出错直接返回
+    match res {
+        Err(e @ (ERPError::DBError(_) | ERPError::IOError(_))) => return Err(e),
+        Err(ERPError::ExcelRowErrors(errors)) => report.errors.extend(errors),
+        Err(e) => report.errors.push(ImportErrorDto {
+            message: e.to_string(),
+            ..Default::default()
+        }),
+        Ok(_) => {}
+    }
+
+    Ok(report)
+}
+
+async fn preview_item_excel(
+    state: &ExcelState,
+    file_path: &str,
+    color_to_value: HashMap<String, i32>,
+    report: &mut ImportPreviewDto,
+) -> ERPResult<()> {
+    let (items, _) = parse_items(file_path, color_to_value.clone(), true, None).await?;
+
+    report.new_colors = items
+        .iter()
+        .filter(|item| !item.color.is_empty() && !color_to_value.contains_key(&item.color))
+        .map(|item| item.color.clone())
+        .unique()
+        .sorted()
+        .collect();
+
+    let cate_data = get_cate_data(state).await?;
+    let new_cates = get_new_cates(&cate_data, &items)?;
+    report.new_cates.extend(new_cates.cate1s);
+    report.new_cates.extend(
+        new_cates
+            .cate2s
+            .into_iter()
+            .map(|(cate1, cate2)| format!("{}/{}", cate1, cate2)),
+    );
+
+    let barcodes = items
+        .iter()
+        .map(|item| item.barcode.clone())
+        .collect::<Vec<_>>();
+    let barcode_to_item = sqlx::query_as!(
+        ItemsModel,
+        "select * from items where barcode = any($1)",
+        &barcodes
+    )
+    .fetch_all(state.db.get_pool())
+    .await?
+    .into_iter()
+    .map(|item| (item.barcode.clone(), item))
+    .collect::<HashMap<_, _>>();
+
+    let sheet_name = get_first_sheet_name(file_path);
+    let mut errors = RowErrors::new(&sheet_name, &ITEM_J_TO_NAME);
+    let empty_hash = HashMap::new();
+    let mut barcode_to_row = HashMap::new();
+    for item in items.iter() {
+        if let Some(row) = barcode_to_row.insert(&item.barcode, item.row) {
+            errors.push(item.row, 9, &item.barcode, &format!("和第{}行重复", row));
+            continue;
+        }
+
+        let mut row = ImportPreviewRowDto {
+            row: item.row,
+            number: item.number.clone(),
+            name: item.name.clone(),
+            color: item.color.clone(),
+            barcode: item.barcode.clone(),
+            count: item.count,
+            message: "".to_string(),
+        };
+        let stock_in = match item.count {
+            0 => "".to_string(),
+            count => format!("入库{}", count),
+        };
+
+        let existing = match barcode_to_item.get(&item.barcode) {
+            Some(existing) => existing,
+            None => {
+                if item.images.is_empty() {
+                    errors.push(item.row, 3, "", &format!("序号 {} 没找到图片", item.index));
+                    continue;
+                }
+                row.message = stock_in;
+                report.create.push(row);
+                continue;
+            }
+        };
+
+        let cate1_id = cate_data.existing_cate1_to_id.get(&item.cates1);
+        let cate2_id = cate1_id.and_then(|cate1_id| {
+            cate_data
+                .existing_cate1_id_to_cate2_to_cate2_id
+                .get(cate1_id)
+                .unwrap_or(&empty_hash)
+                .get(&item.cates2)
+        });
+        let mut changed = vec![];
+        if existing.number != item.number {
+            changed.push("编号");
+        }
+        if existing.name != item.name {
+            changed.push("名称");
+        }
+        if existing.size != item.size {
+            changed.push("规格");
+        }
+        if existing.color != item.color {
+            changed.push("颜色");
+        }
+        if cate1_id != Some(&existing.cate1_id) || cate2_id != Some(&existing.cate2_id) {
+            changed.push("类别");
+        }
+        if existing.unit != item.unit {
+            changed.push("单位");
+        }
+        if existing.cost != item.cost {
+            changed.push("成本");
+        }
+        if existing.price != item.price {
+            changed.push("售价");
+        }
+        if existing.notes != item.notes {
+            changed.push("备注");
+        }
+
+        if changed.is_empty() && item.count == 0 {
+            row.message = "没有变化".to_string();
+            report.skip.push(row);
+            continue;
+        }
+
+        row.message = [changed.join(","), stock_in]
+            .into_iter()
+            .filter(|message| !message.is_empty())
+            .join("; ");
+        report.update.push(row);
+    }
+
+    errors.into_result()
+}
+
+async fn preview_embryo_excel(
+    state: &ExcelState,
+    file_path: &str,
+    report: &mut ImportPreviewDto,
+) -> ERPResult<()> {
+    let items = parse_embryos(file_path, true)?;
+
+    let numbers = items
+        .iter()
+        .map(|item| item.number.clone())
+        .collect::<Vec<_>>();
+    let existing_numbers = sqlx::query!(
+        "select number from embryos where number = any($1)",
+        &numbers
+    )
+    .fetch_all(state.db.get_pool())
+    .await?
+    .into_iter()
+    .map(|item| item.number)
+    .collect::<Vec<_>>();
+
+    // 已有的胚只入库, 不改信息
+    for item in items {
+        let row = ImportPreviewRowDto {
+            row: item.row,
+            number: item.number.clone(),
+            name: item.name.clone(),
+            color: item.color.clone(),
+            barcode: "".to_string(),
+            count: item.count,
+            message: format!("入库{}", item.count),
+        };
+        match (existing_numbers.contains(&item.number), item.count) {
+            (false, _) => report.create.push(row),
+            (true, 0) => report.skip.push(ImportPreviewRowDto {
+                message: "已存在".to_string(),
+                ..row
+            }),
+            (true, _) => report.update.push(row),
+        }
+    }
+
+    Ok(())
+}
+
+async fn preview_order_excel(
+    state: &ExcelState,
+    file_path: &str,
+    customer_id: i32,
+    report: &mut ImportPreviewDto,
+) -> ERPResult<()> {
+    let (_, items) = parse_order_excel(state, file_path, 3, customer_id, true, report).await?;
+
+    report.create = items
+        .into_iter()
+        .map(|item| ImportPreviewRowDto {
+            row: item.row,
+            number: item.number,
+            name: item.name,
+            color: item.color,
+            barcode: "".to_string(),
+            count: item.count,
+            message: "".to_string(),
+        })
+        .collect();
+
+    Ok(())
+}
+
+async fn preview_legacy_order_excel(
+    state: &ExcelState,
+    file_path: &str,
+    customer_id: i32,
+    report: &mut ImportPreviewDto,
+) -> ERPResult<()> {
+    let (_, items) = parse_order_excel(state, file_path, 4, customer_id, true, report).await?;
+
+    let item_numbers = items
+        .iter()
+        .map(|item| item.number.clone())
+        .collect::<Vec<String>>();
+    let number_and_color_to_item = state
+        .item_service
+        .get_item_with_numbers(item_numbers)
+        .await?
+        .into_iter()
+        .map(|item| ((item.number.clone(), item.color.clone()), item))
+        .collect::<HashMap<_, _>>();
+
+    let sheet_name = get_first_sheet_name(file_path);
+    let mut errors = RowErrors::new(&sheet_name, &LEGACY_ORDER_J_TO_NAME);
+    for item in items {
+        match number_and_color_to_item.get(&(item.number.clone(), item.color.clone())) {
+            Some(item_model) => report.create.push(ImportPreviewRowDto {
+                row: item.row,
+                number: item.number,
+                name: item_model.name.clone(),
+                color: item.color,
+                barcode: item_model.barcode.clone(),
+                count: item.count,
+                message: format!("出库{}", item.count as f64 / 10.0),
+            }),
+            None => errors.push(
+                item.row,
+                2,
+                &item.number,
+                &format!("颜色为{} 的产品未预先录入", item.color),
+            ),
+        }
+    }
+
+    errors.into_result()
+}
+
+/// 订单用哪个导入模版: 先看客户指定的, 没有就按表头文字找
+async fn get_order_template(
+    state: &ExcelState,
+    customer_id: i32,
+    file_path: &str,
+) -> ERPResult<Option<ExcelTemplateModel>> {
+    if let Some(template) = state
+        .excel_template_service
+        .get_customer_excel_template(customer_id)
+        .await?
+    {
+        return Ok(Some(template));
+    }
+
+    let templates = state.excel_template_service.get_excel_templates().await?;
+    detect_template(file_path, &templates)
+}
+
+/// 有模版按模版解析, 没有的按tp用默认格式, 用到的模版名记到预览里
+async fn parse_order_excel(
+    state: &ExcelState,
+    file_path: &str,
+    tp: i32,
+    customer_id: i32,
+    dry_run: bool,
+    report: &mut ImportPreviewDto,
+) -> ERPResult<(OrderInfo, Vec<OrderExcelDto>)> {
+    match get_order_template(state, customer_id, file_path).await? {
+        Some(template) => {
+            tracing::info!("order excel template: {}", template.name);
+            report.template = template.name.clone();
+            let order_info = parse_template_order_info(file_path, &template).await?;
+            let items = parse_template_order(file_path, &template, dry_run).await?;
+            Ok((order_info, items))
+        }
+        None if tp == 4 => {
+            let order_info = parse_legacy_order_info(file_path).await?;
+            let items = parse_legacy_order(file_path).await?;
+            Ok((order_info, items))
+        }
+        None => {
+            let order_info = parse_order_info(file_path).await?;
+            let items = parse_order(file_path, dry_run).await?;
+            Ok((order_info, items))
+        }
+    }
 }
 
 async fn process_order_excel(
@@ -126,27 +869,42 @@ async fn process_order_excel(
     _color_to_value: HashMap<String, i32>,
     customer_id: i32,
     account: &AccountDto,
-) -> ERPResult<()> {
+    job_id: i32,
+    progress: &ImportProgress,
+) -> ERPResult<ImportCounts> {
     tracing::info!("import excel for order....");
-    let order_info = parse_order_info(file_path).await?;
-    let items = parse_order(file_path).await?;
+    let (order_info, items) = parse_order_excel(
+        state,
+        file_path,
+        3,
+        customer_id,
+        false,
+        &mut ImportPreviewDto::default(),
+    )
+    .await?;
 
     tracing::info!("{:?}", order_info);
     tracing::info!("{:?}", items);
 
+    progress.set_phase(IMPORT_PHASE_SAVING, items.len() as i32);
     let utc_create_time = Utc::now();
+    let mut tx = state.db.get_pool().begin().await?;
     let order_id = state
         .order_service
-        .add_order(&OrderModel {
-            id: 0,
-            order_no: "".to_string(),
-            account_id: account.id,
-            tp: 1,
-            customer_id,
-            order_date: order_info.order_date,
-            delivery_date: order_info.delivery_date,
-            create_time: utc_create_time,
-        })
+        .add_order(
+            &mut tx,
+            &OrderModel {
+                id: 0,
+                order_no: "".to_string(),
+                account_id: account.id,
+                tp: 1,
+                status: ORDER_STATUS_COMPLETED,
+                customer_id,
+                order_date: order_info.order_date,
+                delivery_date: order_info.delivery_date,
+                create_time: utc_create_time,
+            },
+        )
         .await?;
 
     let order_items = items
@@ -170,10 +928,23 @@ async fn process_order_excel(
 
     state
         .order_service
-        .insert_just_imported_order_items(&order_items)
+        .insert_just_imported_order_items(&mut tx, &order_items)
         .await?;
+    state
+        .import_job_service
+        .add_import_job_entities(&mut tx, job_id, "orders", &[order_id])
+        .await?;
+    let counts = ImportCounts {
+        row_count: order_items.len() as i32,
+        created_count: order_items.len() as i32,
+        updated_count: 0,
+        ..Default::default()
+    };
+    add_import_audit_log(state, &mut tx, account.id, job_id, &counts).await?;
+    tx.commit().await?;
+    progress.set_processed(order_items.len() as i32);
 
-    Ok(())
+    Ok(counts)
 }
 
 async fn process_legacy_order_excel(
@@ -182,15 +953,24 @@ async fn process_legacy_order_excel(
     _color_to_value: HashMap<String, i32>,
     customer_id: i32,
     account: &AccountDto,
-) -> ERPResult<()> {
+    job_id: i32,
+    progress: &ImportProgress,
+) -> ERPResult<ImportCounts> {
     tracing::info!("import excel for order....");
-    let order_info = parse_legacy_order_info(file_path).await?;
-    let items = parse_legacy_order(file_path).await?;
+    let (order_info, items) = parse_order_excel(
+        state,
+        file_path,
+        4,
+        customer_id,
+        false,
+        &mut ImportPreviewDto::default(),
+    )
+    .await?;
 
     tracing::info!("{:?}", order_info);
     tracing::info!("{:?}", items);
 
-    let item_ids = check_legacy_order_data_valid(state, &items).await?;
+    let item_ids = check_legacy_order_data_valid(state, file_path, &items).await?;
     let item_models = state.item_service.get_item_with_ids(item_ids).await?;
     let mut number_to_color_to_id = HashMap::new();
     item_models.iter().for_each(|item| {
@@ -205,33 +985,43 @@ async fn process_legacy_order_excel(
         .collect::<HashMap<i32, ItemsModel>>();
 
     // let order_create_time = NaiveDateTime::default();
+    progress.set_phase(IMPORT_PHASE_SAVING, items.len() as i32);
     let utc_create_time = Utc::now();
+    let mut tx = state.db.get_pool().begin().await?;
     let order_id = state
         .order_service
-        .add_order(&OrderModel {
-            id: 0,
-            order_no: "".to_string(),
-            account_id: account.id,
-            tp: 1,
-            customer_id,
-            order_date: order_info.order_date,
-            delivery_date: order_info.delivery_date,
-            create_time: utc_create_time,
-        })
+        .add_order(
+            &mut tx,
+            &OrderModel {
+                id: 0,
+                order_no: "".to_string(),
+                account_id: account.id,
+                tp: 1,
+                status: ORDER_STATUS_COMPLETED,
+                customer_id,
+                order_date: order_info.order_date,
+                delivery_date: order_info.delivery_date,
+                create_time: utc_create_time,
+            },
+        )
         .await?;
 
     let empty_color_to_id_hashmap: HashMap<String, i32> = HashMap::new();
 
     let bucket_id = state
         .item_service
-        .add_inout_bucket(ItemInOutBucketModal {
-            id: 0,
-            account_id: account.id,
-            in_true_out_false: false,
-            via: "order_excel".to_string(),
-            order_id,
-            create_time: utc_create_time,
-        })
+        .add_inout_bucket(
+            &mut tx,
+            ItemInOutBucketModal {
+                id: 0,
+                account_id: account.id,
+                in_true_out_false: false,
+                via: "order_excel".to_string(),
+                order_id,
+                warehouse_id: DEFAULT_WAREHOUSE_ID,
+                create_time: utc_create_time,
+            },
+        )
         .await?
         .id;
 
@@ -248,16 +1038,31 @@ async fn process_legacy_order_excel(
                 id: 0,
                 bucket_id,
                 item_id: *item_id,
-                count: item.count,
+                count: -item.count,
                 current_cost: item.price,
-                current_total: item.total,
+                current_total: -item.total,
             }
         })
         .collect::<Vec<ItemsInOutModel>>();
 
+    let stock_rows = item_inouts
+        .iter()
+        .map(|item| (item.item_id, item.count))
+        .collect::<Vec<_>>();
+    let warnings = state
+        .stock_service
+        .check_item_stock_out(
+            &mut tx,
+            DEFAULT_WAREHOUSE_ID,
+            &stock_rows,
+            0,
+            true,
+            account.id,
+        )
+        .await?;
     state
         .item_service
-        .add_multiple_items_inouts(&item_inouts)
+        .add_multiple_items_inouts(&mut tx, &item_inouts)
         .await?;
 
     let order_items = items
@@ -287,14 +1092,32 @@ async fn process_legacy_order_excel(
 
     state
         .order_service
-        .insert_just_order_items(&order_items)
+        .insert_just_order_items(&mut tx, &order_items)
+        .await?;
+    state
+        .import_job_service
+        .add_import_job_entities(&mut tx, job_id, "orders", &[order_id])
         .await?;
+    state
+        .import_job_service
+        .add_import_job_entities(&mut tx, job_id, "item_inout_bucket", &[bucket_id])
+        .await?;
+    let counts = ImportCounts {
+        row_count: order_items.len() as i32,
+        created_count: order_items.len() as i32,
+        updated_count: 0,
+        warnings,
+    };
+    add_import_audit_log(state, &mut tx, account.id, job_id, &counts).await?;
+    tx.commit().await?;
+    progress.set_processed(order_items.len() as i32);
 
-    Ok(())
+    Ok(counts)
 }
 
 async fn check_legacy_order_data_valid(
     state: &ExcelState,
+    file_path: &str,
     excel_items: &[OrderExcelDto],
 ) -> ERPResult<Vec<i32>> {
     let item_numbers = excel_items
@@ -315,6 +1138,8 @@ async fn check_legacy_order_data_valid(
             .push(item.color.clone());
     });
 
+    let sheet_name = get_first_sheet_name(file_path);
+    let mut errors = RowErrors::new(&sheet_name, &LEGACY_ORDER_J_TO_NAME);
     let empty_color_vec: Vec<String> = vec![];
     for item in excel_items {
         let colors = item_number_to_colors
@@ -322,21 +1147,15 @@ async fn check_legacy_order_data_valid(
             .unwrap_or(&empty_color_vec);
 
         if !colors.contains(&item.color) {
-            return Err(ERPError::NotFound(format!(
-                "编号为{} 和 颜色为{} 的产品未预先录入",
-                item.number, item.color
-            )));
+            errors.push(
+                item.row,
+                2,
+                &item.number,
+                &format!("颜色为{} 的产品未预先录入", item.color),
+            );
         }
     }
-
-    // let mut number_to_color_to_id = HashMap::new();
-    // items.into_iter().for_each(|item| {
-    //     number_to_color_to_id
-    //         .entry(item.number)
-    //         .or_insert(HashMap::new())
-    //         .insert(item.color, item.id);
-    // });
-    // Ok(number_to_color_to_id)
+    errors.into_result()?;
 
     Ok(items.into_iter().map(|item| item.id).collect::<Vec<i32>>())
 }
@@ -347,9 +1166,11 @@ async fn process_embryo_excel(
     file_path: &str,
     _color_to_value: HashMap<String, i32>,
     account: &AccountDto,
-) -> ERPResult<()> {
+    job_id: i32,
+    progress: &ImportProgress,
+) -> ERPResult<ImportCounts> {
     tracing::info!("import excel for embryo....");
-    let items = parse_embryos(&file_path)?;
+    let items = parse_embryos(file_path, false)?;
 
     // 检查数据的正确性
     check_embryo_date_valid(&items)?;
@@ -390,33 +1211,51 @@ async fn process_embryo_excel(
         }
     };
 
+    let row_count = items.len() as i32;
     let to_add_items = items
         .into_iter()
         .filter(|item| !existing_numbers.contains(&item.number))
         .collect::<Vec<_>>();
 
+    // 新增胚和入库记录在同一个事务里
+    progress.set_phase(IMPORT_PHASE_SAVING, row_count);
+    let mut tx = state.db.get_pool().begin().await?;
     if !to_add_items.is_empty() {
-        state
+        let embryo_ids = state
             .embryo_service
-            .insert_multiple_items(&to_add_items)
+            .insert_multiple_items(&mut tx, &to_add_items)
             .await?
-            .iter()
-            .for_each(|item| {
-                number_to_id.insert(item.number.clone(), item.id);
-            });
+            .into_iter()
+            .map(|item| {
+                number_to_id.insert(item.number, item.id);
+                item.id
+            })
+            .collect::<Vec<_>>();
+        state
+            .import_job_service
+            .add_import_job_entities(&mut tx, job_id, "embryos", &embryo_ids)
+            .await?;
     }
 
     let bucket_id = state
         .embryo_service
-        .add_inout_bucket(EmbryoInOutBucketModal {
-            id: 0,
-            account_id: account.id,
-            in_true_out_false: true,
-            via: "excel".to_string(),
-            create_time: Default::default(),
-        })
+        .add_inout_bucket(
+            &mut tx,
+            EmbryoInOutBucketModal {
+                id: 0,
+                account_id: account.id,
+                in_true_out_false: true,
+                via: "excel".to_string(),
+                warehouse_id: DEFAULT_WAREHOUSE_ID,
+                create_time: Default::default(),
+            },
+        )
         .await?
         .id;
+    state
+        .import_job_service
+        .add_import_job_entities(&mut tx, job_id, "embryo_inout_bucket", &[bucket_id])
+        .await?;
 
     let ins = number_to_count
         .into_iter()
@@ -438,11 +1277,20 @@ async fn process_embryo_excel(
     if !ins.is_empty() {
         state
             .embryo_service
-            .insert_multiple_items_inouts(&ins)
+            .insert_multiple_items_inouts(&mut tx, &ins)
             .await?;
     }
+    let counts = ImportCounts {
+        row_count,
+        created_count: to_add_items.len() as i32,
+        updated_count: 0,
+        ..Default::default()
+    };
+    add_import_audit_log(state, &mut tx, account.id, job_id, &counts).await?;
+    tx.commit().await?;
+    progress.set_processed(row_count);
 
-    Ok(())
+    Ok(counts)
 }
 
 fn check_embryo_date_valid(items: &[EmbryoExcelDto]) -> ERPResult<()> {
@@ -457,18 +1305,21 @@ async fn process_item_excel(
     file_path: &str,
     color_to_value: HashMap<String, i32>,
     account: &AccountDto,
-) -> ERPResult<()> {
+    job_id: i32,
+    progress: &ImportProgress,
+) -> ERPResult<ImportCounts> {
     tracing::info!("import excel....");
-    let items = parse_items(state, &file_path, color_to_value).await?;
+    let existing_colors = color_to_value.keys().cloned().collect::<Vec<_>>();
+    let max_color_value = color_to_value.values().max().copied().unwrap_or(0);
+    let (items, item_images) =
+        parse_items(file_path, color_to_value, false, Some(progress)).await?;
     if items.len() == 0 {
-        return Ok(());
+        return Ok(ImportCounts::default());
     }
+    let row_count = items.len() as i32;
 
     // 检查数据的正确性
-    check_if_excel_data_valid(&state, &items).await?;
-
-    // 对未出现过的 类别，入库(并返回所有的类别）
-    let cate_data = handle_cates(&state, &items).await?;
+    check_if_excel_data_valid(file_path, &items)?;
 
     let mut barcode_to_id: HashMap<String, i32> = HashMap::new();
     let barcode_to_count = items
@@ -511,61 +1362,125 @@ async fn process_item_excel(
     };
     tracing::info!("existing_barcodes: {:?}", existing_barcodes);
 
-    let to_add_items = items
+    if let Some(item) = items
+        .iter()
+        .find(|item| item.images.is_empty() && !existing_barcodes.contains(&item.barcode))
+    {
+        return Err(ERPError::NotFound(format!(
+            "序号 {} 没找到图片",
+            item.index
+        )));
+    }
+    let staging_path = stage_item_images(job_id, &item_images)?;
+
+    // 新颜色, 类别, 产品和入库记录在同一个事务里
+    progress.set_phase(IMPORT_PHASE_SAVING, row_count);
+    let mut tx = state.db.get_pool().begin().await?;
+
+    // 新颜色记到导入记录里, 撤销时一起删掉
+    let new_colors = items
+        .iter()
+        .filter(|item| !existing_colors.contains(&item.color))
+        .map(|item| item.color.clone())
+        .unique()
+        .sorted()
+        .collect::<Vec<_>>();
+    if !new_colors.is_empty() {
+        // 条码是按parse_items推算的颜色值算的, 入库的值必须一致
+        let expected = (max_color_value + 1..)
+            .zip(new_colors.iter())
+            .map(|(value, color)| (color.clone(), value))
+            .collect::<HashMap<_, _>>();
+        let added = state
+            .settings_service
+            .add_multiple_color_to_value(&mut tx, new_colors)
+            .await?;
+        if added != expected {
+            return Err(ERPError::Collision(
+                "颜色设置在导入期间有变化, 请重新导入".to_string(),
+            ));
+        }
+        let color_ids = sqlx::query!(
+            "select id from color_settings where color = any($1)",
+            &added.into_keys().collect::<Vec<_>>()
+        )
+        .fetch_all(&mut *tx)
+        .await?
         .into_iter()
-        .filter(|item| !existing_barcodes.contains(&item.barcode))
-        .collect::<Vec<ItemExcelDto>>();
+        .map(|row| row.id)
+        .collect::<Vec<_>>();
+        state
+            .import_job_service
+            .add_import_job_entities(&mut tx, job_id, "color_settings", &color_ids)
+            .await?;
+    }
 
+    // 对未出现过的 类别，入库(并返回所有的类别）
+    let cate_data = handle_cates(state, &mut tx, &items, job_id).await?;
     let empty_cate2_to_cate2_id: HashMap<String, i32> = HashMap::new();
-    if !to_add_items.is_empty() {
-        let mut item_models = vec![];
-        for item in to_add_items {
-            let cate1_id = *cate_data
-                .existing_cate1_to_id
-                .get(&item.cates1)
-                .unwrap_or(&0);
-
-            if cate1_id == 0 {
-                return Err(ERPError::Failed(format!(
-                    "大类 {} 未成功收录",
-                    &item.cates1
-                )));
-            }
-
-            let cate2_id = *cate_data
-                .existing_cate1_id_to_cate2_to_cate2_id
-                .get(&cate1_id)
-                .unwrap_or(&empty_cate2_to_cate2_id)
-                .get(&item.cates2)
-                .unwrap_or(&0);
+    let mut item_models = vec![];
+    // 已存在的条码更新商品信息(导出的商品表改完再导回来)
+    let mut update_item_models = vec![];
+    for item in items {
+        let cate1_id = *cate_data
+            .existing_cate1_to_id
+            .get(&item.cates1)
+            .unwrap_or(&0);
+
+        if cate1_id == 0 {
+            return Err(ERPError::Failed(format!(
+                "大类 {} 未成功收录",
+                &item.cates1
+            )));
+        }
 
-            if cate2_id == 0 {
-                return Err(ERPError::Failed(format!(
-                    "小类 {} 未成功收录",
-                    &item.cates2
-                )));
-            }
+        let cate2_id = *cate_data
+            .existing_cate1_id_to_cate2_to_cate2_id
+            .get(&cate1_id)
+            .unwrap_or(&empty_cate2_to_cate2_id)
+            .get(&item.cates2)
+            .unwrap_or(&0);
+
+        if cate2_id == 0 {
+            return Err(ERPError::Failed(format!(
+                "小类 {} 未成功收录",
+                &item.cates2
+            )));
+        }
 
-            let item_model = ItemsModel {
-                id: 0,
-                color: item.color,
-                cate1_id,
-                cate2_id,
-                name: item.name,
-                size: item.size,
-                unit: item.unit,
-                price: item.price,
-                barcode: item.barcode,
-                notes: "".to_string(),
-                images: item.images,
-                create_time: Default::default(),
-                cost: item.cost,
-                number: item.number,
-            };
+        let item_model = ItemsModel {
+            id: *barcode_to_id.get(&item.barcode).unwrap_or(&0),
+            color: item.color,
+            cate1_id,
+            cate2_id,
+            name: item.name,
+            size: item.size,
+            unit: item.unit,
+            price: item.price,
+            barcode: item.barcode,
+            notes: item.notes,
+            images: item.images,
+            create_time: Default::default(),
+            cost: item.cost,
+            number: item.number,
+        };
 
-            item_models.push(item_model)
+        if existing_barcodes.contains(&item_model.barcode) {
+            update_item_models.push(item_model);
+        } else {
+            item_models.push(item_model);
         }
+    }
 
+    tracing::info!("update {:?} items", update_item_models.len());
+    state
+        .item_service
+        .update_multiple_items(&mut tx, &update_item_models, account.id)
+        .await?;
+    progress.set_processed(update_item_models.len() as i32);
+
+    let mut new_item_ids = vec![];
+    if !item_models.is_empty() {
         tracing::info!("insert {:?} items", item_models.len());
 
         let mut st = 0;
@@ -575,51 +1490,41 @@ async fn process_item_excel(
                 tracing::info!("{:?} \n", &item_models[st..].len());
                 state
                     .item_service
-                    .insert_multiple_items(&item_models[st..])
+                    .insert_multiple_items(&mut tx, &item_models[st..])
                     .await?
                     .into_iter()
                     .for_each(|item| {
+                        new_item_ids.push(item.id);
                         barcode_to_id.insert(item.barcode.clone(), item.id);
                     });
             } else {
                 tracing::info!("{:?} \n", &item_models[st..(st + 1000)].len());
                 state
                     .item_service
-                    .insert_multiple_items(&item_models[st..(st + 1000)])
+                    .insert_multiple_items(&mut tx, &item_models[st..(st + 1000)])
                     .await?
                     .into_iter()
                     .for_each(|item| {
+                        new_item_ids.push(item.id);
                         barcode_to_id.insert(item.barcode.clone(), item.id);
                     });
             }
             st += l;
+            progress.set_processed((update_item_models.len() + st.min(item_models.len())) as i32);
         }
     }
 
-    // 先添加库存bucket
-    let bucket = state
-        .item_service
-        .add_inout_bucket(ItemInOutBucketModal {
-            id: 0,
-            account_id: account.id,
-            in_true_out_false: true,
-            via: "excel".to_string(),
-            order_id: 0,
-            create_time: Default::default(),
-        })
-        .await?;
-    let bucket_id = bucket.id;
-
-    // 添加库存
+    // 添加库存, 数量为0的只更新商品不入库
     let ins = barcode_to_count
         .into_iter()
+        .filter(|(_, count)| *count != 0)
         .map(|(barcode, count)| {
             let item_id = barcode_to_id.get(&barcode).unwrap_or(&0);
             let current_cost = barcode_to_cost.get(&barcode).unwrap_or(&0);
             let current_total = *current_cost * count;
             ItemsInOutModel {
                 id: 0,
-                bucket_id,
+                bucket_id: 0,
                 item_id: *item_id,
                 count,
                 current_cost: *current_cost,
@@ -629,71 +1534,85 @@ async fn process_item_excel(
         .collect::<Vec<_>>();
 
     if !ins.is_empty() {
+        // 先添加库存bucket
+        let bucket = state
+            .item_service
+            .add_inout_bucket(
+                &mut tx,
+                ItemInOutBucketModal {
+                    id: 0,
+                    account_id: account.id,
+                    in_true_out_false: true,
+                    via: "excel".to_string(),
+                    order_id: 0,
+                    warehouse_id: DEFAULT_WAREHOUSE_ID,
+                    create_time: Default::default(),
+                },
+            )
+            .await?;
+
         state
             .item_service
-            .insert_multiple_items_inouts(&ins, bucket_id)
+            .insert_multiple_items_inouts(&mut tx, &ins, bucket.id)
+            .await?;
+        state
+            .import_job_service
+            .add_import_job_entities(&mut tx, job_id, "item_inout_bucket", &[bucket.id])
             .await?;
     }
+    state
+        .import_job_service
+        .add_import_job_entities(&mut tx, job_id, "items", &new_item_ids)
+        .await?;
+    let counts = ImportCounts {
+        row_count,
+        created_count: new_item_ids.len() as i32,
+        updated_count: update_item_models.len() as i32,
+        ..Default::default()
+    };
+    add_import_audit_log(state, &mut tx, account.id, job_id, &counts).await?;
+    tx.commit().await?;
+    publish_item_images(&staging_path, &item_images)?;
+    progress.set_processed(row_count);
 
-    Ok(())
+    Ok(counts)
 }
 
-async fn check_if_excel_data_valid(
-    _state: &ExcelState,
-    items: &[ItemExcelDto<'_>],
-) -> ERPResult<()> {
+fn check_if_excel_data_valid(file_path: &str, items: &[ItemExcelDto<'_>]) -> ERPResult<()> {
     // 不能为空的字段，图片（可多张），名称，颜色，大类，单位，售价，成本，编号
-    // no need
-
-    // 编号必须是6位
-    // 算了吧
-
-    // 颜色必须预先录入
-    // 已处理
+    // 在parse_items里已检查
 
     // barcode验证重复
-    let mut barcode_to_count = HashMap::new();
+    let sheet_name = get_first_sheet_name(file_path);
+    let mut errors = RowErrors::new(&sheet_name, &ITEM_J_TO_NAME);
+    let mut barcode_to_row = HashMap::new();
     for item in items {
-        let count = barcode_to_count.entry(&item.barcode).or_insert(0);
-        *count += 1;
-    }
-
-    let dup_barcodes = barcode_to_count
-        .iter()
-        .filter_map(|(code, count)| {
-            if count <= &1 {
-                None
-            } else {
-                Some(code.as_str())
-            }
-        })
-        .collect::<Vec<_>>();
-
-    if !dup_barcodes.is_empty() {
-        return Err(ERPError::ExcelError(format!(
-            "重复的条形码有: {}",
-            dup_barcodes.join(",")
-        )));
+        if let Some(row) = barcode_to_row.insert(&item.barcode, item.row) {
+            errors.push(item.row, 9, &item.barcode, &format!("和第{}行重复", row));
+        }
     }
 
-    Ok(())
+    errors.into_result()
 }
 
 struct CateData {
     existing_cate1_to_id: HashMap<String, i32>,
     existing_cate1_id_to_cate2_to_cate2_id: HashMap<i32, HashMap<String, i32>>,
 }
-async fn handle_cates(state: &ExcelState, items: &[ItemExcelDto<'_>]) -> ERPResult<CateData> {
-    // 不需要处理，只需要把cates记录到数据库里
-    // todo!()
 
-    // 先处理类别
+/// 表格里有, 数据库里还没有的类别
+struct NewCates {
+    cate1s: Vec<String>,
+    cate2s: Vec<(String, String)>, // (大类, 小类)
+}
+
+async fn get_cate_data(state: &ExcelState) -> ERPResult<CateData> {
     let cates = sqlx::query_as!(CateModel, "select * from cates")
         .fetch_all(state.db.get_pool())
         .await
         .map_err(ERPError::DBError)?;
 
-    let mut existing_cate1_to_id = cates
+    let existing_cate1_to_id = cates
         .iter()
         .filter_map(|cate| match cate.cate_type {
             0 => Some((cate.name.clone(), cate.id)),
@@ -701,7 +1620,6 @@ async fn handle_cates(state: &ExcelState, items: &[ItemExcelDto<'_>]) -> ERPResu
         })
         .collect::<HashMap<String, i32>>();
 
-    // let mut existing_cate1_id_to_cate2_to_cate2_id: HashMap<i32, HashMap<String, i32>> =
     let mut existing_cate1_id_to_cate2_to_cate2_id = HashMap::new();
     for cate in cates.iter() {
         if cate.cate_type == 0 {
@@ -719,6 +1637,13 @@ async fn handle_cates(state: &ExcelState, items: &[ItemExcelDto<'_>]) -> ERPResu
         existing_cate1_id_to_cate2_to_cate2_id
     );
 
+    Ok(CateData {
+        existing_cate1_to_id,
+        existing_cate1_id_to_cate2_to_cate2_id,
+    })
+}
+
+fn get_new_cates(cate_data: &CateData, items: &[ItemExcelDto<'_>]) -> ERPResult<NewCates> {
     let mut cate1_to_cate2s: HashMap<String, Vec<String>> = HashMap::new();
     for item in items.iter() {
         // todo: 必须得有cate1，然后才有cate2
@@ -736,76 +1661,176 @@ async fn handle_cates(state: &ExcelState, items: &[ItemExcelDto<'_>]) -> ERPResu
             cate2s.push(item.cates2.clone());
         }
     }
+    tracing::info!("cate1_to_cate2s: {:?}", cate1_to_cate2s);
 
-    // 添加cate1
-    let to_add_cate1s = cate1_to_cate2s
-        .iter()
-        .filter_map(|(k, _)| {
-            if existing_cate1_to_id.contains_key(k) {
-                None
-            } else {
-                Some(k.as_str())
+    let empty_hash = HashMap::new();
+    let mut new_cates = NewCates {
+        cate1s: vec![],
+        cate2s: vec![],
+    };
+    for (cate1, cate2s) in cate1_to_cate2s.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        let existing_cate2s = match cate_data.existing_cate1_to_id.get(&cate1) {
+            Some(cate1_id) => cate_data
+                .existing_cate1_id_to_cate2_to_cate2_id
+                .get(cate1_id)
+                .unwrap_or(&empty_hash),
+            None => {
+                new_cates.cate1s.push(cate1.clone());
+                &empty_hash
             }
-        })
-        .collect::<Vec<&str>>();
-    if !to_add_cate1s.is_empty() {
+        };
+
+        for cate2 in cate2s {
+            if !existing_cate2s.contains_key(&cate2) {
+                new_cates.cate2s.push((cate1.clone(), cate2));
+            }
+        }
+    }
+
+    Ok(new_cates)
+}
+
+async fn handle_cates(
+    state: &ExcelState,
+    conn: &mut PgConnection,
+    items: &[ItemExcelDto<'_>],
+    job_id: i32,
+) -> ERPResult<CateData> {
+    // 不需要处理，只需要把cates记录到数据库里
+    let mut cate_data = get_cate_data(state).await?;
+    let new_cates = get_new_cates(&cate_data, items)?;
+    let mut new_cate_ids = vec![];
+
+    // 添加cate1
+    if !new_cates.cate1s.is_empty() {
+        let to_add_cate1s = new_cates
+            .cate1s
+            .iter()
+            .map(|cate1| cate1.as_str())
+            .collect::<Vec<&str>>();
         let new_cate1_to_id = state
             .cate_service
-            .insert_multiple_cate1(&to_add_cate1s)
+            .insert_multiple_cate1(conn, &to_add_cate1s)
             .await?;
         for (k, v) in new_cate1_to_id {
-            existing_cate1_to_id.insert(k, v);
+            new_cate_ids.push(v);
+            cate_data.existing_cate1_to_id.insert(k, v);
         }
     }
 
-    let empty_hash = HashMap::new();
     let mut to_add_cate2s = vec![];
-    for (cate1, cate2s) in cate1_to_cate2s.iter() {
-        let cate1_id = existing_cate1_to_id.get(cate1).ok_or(ERPError::ExcelError(
-            "有点问题，如果在次出现，找周".to_string(),
-        ))?;
-
-        for cate2 in cate2s {
-            if existing_cate1_id_to_cate2_to_cate2_id
-                .get(cate1_id)
-                .unwrap_or(&empty_hash)
-                .contains_key(cate2)
-            {
-                continue;
-            }
-
-            to_add_cate2s.push(CateModel {
-                id: 0,
-                index: 0,
-                name: cate2.to_string(),
-                cate_type: 1,
-                parent_id: *cate1_id,
-                create_time: Default::default(),
-            })
-        }
+    for (cate1, cate2) in new_cates.cate2s {
+        let cate1_id = cate_data
+            .existing_cate1_to_id
+            .get(&cate1)
+            .ok_or(ERPError::ExcelError(
+                "有点问题，如果在次出现，找周".to_string(),
+            ))?;
+
+        to_add_cate2s.push(CateModel {
+            id: 0,
+            index: 0,
+            name: cate2,
+            cate_type: 1,
+            parent_id: *cate1_id,
+            create_time: Default::default(),
+        })
     }
     if !to_add_cate2s.is_empty() {
         let new_cates = state
             .cate_service
-            .insert_multiple_cate2(&to_add_cate2s)
+            .insert_multiple_cate2(conn, &to_add_cate2s)
             .await?;
         for new_cate in new_cates {
-            existing_cate1_id_to_cate2_to_cate2_id
+            new_cate_ids.push(new_cate.id);
+            cate_data
+                .existing_cate1_id_to_cate2_to_cate2_id
                 .entry(new_cate.parent_id)
                 .or_insert(HashMap::new())
                 .insert(new_cate.name, new_cate.id);
         }
     }
 
-    tracing::info!("cate1_to_cate2s: {:?}", cate1_to_cate2s);
-    tracing::info!("existing_cate1_to_id: {:?}", existing_cate1_to_id);
+    state
+        .import_job_service
+        .add_import_job_entities(conn, job_id, "cates", &new_cate_ids)
+        .await?;
+
+    tracing::info!("existing_cate1_to_id: {:?}", cate_data.existing_cate1_to_id);
     tracing::info!(
         "existing_cate1_id_to_cate2_to_cate2_id: {:?}",
-        existing_cate1_id_to_cate2_to_cate2_id
+        cate_data.existing_cate1_id_to_cate2_to_cate2_id
     );
 
-    Ok(CateData {
-        existing_cate1_to_id,
-        existing_cate1_id_to_cate2_to_cate2_id,
-    })
+    Ok(cate_data)
+}
+
+async fn api_get_excel_templates(
+    State(state): State<ExcelState>,
+) -> ERPResult<APIListResponse<ExcelTemplateModel>> {
+    tracing::info!("->> {:<12}, api_get_excel_templates", "handler");
+
+    let templates = state.excel_template_service.get_excel_templates().await?;
+    let len = templates.len() as i32;
+
+    Ok(APIListResponse::new(templates, len))
+}
+
+async fn api_edit_excel_template(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<ExcelTemplateEditParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_edit_excel_template", "handler");
+
+    state
+        .excel_template_service
+        .edit_excel_template(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_delete_excel_template(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_delete_excel_template", "handler");
+
+    state
+        .excel_template_service
+        .delete_excel_template(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_get_customer_excel_templates(
+    State(state): State<ExcelState>,
+) -> ERPResult<APIListResponse<CustomerExcelTemplateModel>> {
+    tracing::info!("->> {:<12}, api_get_customer_excel_templates", "handler");
+
+    let customer_templates = state
+        .excel_template_service
+        .get_customer_excel_templates()
+        .await?;
+    let len = customer_templates.len() as i32;
+
+    Ok(APIListResponse::new(customer_templates, len))
+}
+
+async fn api_set_customer_excel_template(
+    State(state): State<ExcelState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<CustomerExcelTemplateParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_set_customer_excel_template", "handler");
+
+    state
+        .excel_template_service
+        .set_customer_excel_template(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
 }
@@ -1,27 +1,46 @@
-use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::constants::{DEFAULT_PAGE_SIZE, ROLE_WAREHOUSE};
 use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_items::{
     DeleteParams, EditParams, InoutBucketParams, InoutListOfBucketParams, InoutParams,
     InoutQueryParams, ItemInOutBucketDto, ItemInOutDto, ItemSearchParams, ItemStockOutMultiParams,
-    ItemsDto, QueryParams,
+    ItemTransferParams, ItemsDto, QueryParams,
 };
-use crate::response::api_response::{APIEmptyResponse, APIListResponse};
+use crate::dto::dto_stock::{InsufficientStockDto, ItemStockDto};
+use crate::excel::export_items::export_items;
+use crate::middleware::permission::permission;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
 use crate::service::item_service::ItemServiceTrait;
 use crate::state::item_state::ItemState;
 use crate::{ERPError, ERPResult};
 use axum::extract::{Query, State};
+use axum::http::header;
+use axum::middleware::from_fn_with_state;
+use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_extra::extract::WithRejection;
 
 pub fn routes() -> Router<ItemState> {
+    let warehouse = from_fn_with_state(&[ROLE_WAREHOUSE][..], permission);
+
     Router::new()
         .route("/api/items", get(api_item_list))
+        .route("/api/items/export", get(api_item_export))
         .route("/api/item/edit", post(api_item_edit))
         .route("/api/item/delete", post(api_item_delete))
         .route("/api/item/stock", get(api_item_stock))
-        .route("/api/item/stock/out", post(api_item_stock_out))
-        .route("/api/item/inout", post(api_item_inout))
+        .route(
+            "/api/item/stock/out",
+            post(api_item_stock_out).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/item/inout",
+            post(api_item_inout).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/item/transfer",
+            post(api_item_transfer).route_layer(warehouse),
+        )
         .route("/api/item/inout/list", get(api_inout_list))
         .route("/api/item/inout/group/list", get(api_inout_group_list)) // 出入库列表
         .route(
@@ -40,60 +59,113 @@ async fn api_item_list(
     Ok(APIListResponse::new(items, count))
 }
 
+async fn api_item_export(
+    State(state): State<ItemState>,
+    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    // 条件和列表一致, 不分页
+    let count = state.item_service.get_item_count(&params).await?;
+    let params = QueryParams {
+        page: Some(1),
+        page_size: Some(count.max(1)),
+        ..params
+    };
+    let items = state.item_service.get_item_list(&params).await?;
+
+    let filename = format!("items-{}.xlsx", chrono::Local::now().format("%Y%m%d%H%M%S"));
+    let bytes = tokio::task::spawn_blocking(move || export_items(&items))
+        .await
+        .map_err(|e| ERPError::Failed(format!("生成excel失败: {}", e)))??;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        bytes,
+    ))
+}
+
 async fn api_item_edit(
     State(state): State<ItemState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<EditParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    state.item_service.edit_item(&params).await?;
+    state.item_service.edit_item(&params, account.id).await?;
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_item_delete(
     State(state): State<ItemState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<DeleteParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
-    state.item_service.delete_item(&params).await?;
+    state.item_service.delete_item(&params, account.id).await?;
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_item_stock(
-    State(_state): State<ItemState>,
-    WithRejection(Query(_params), _): WithRejection<Query<QueryParams>, ERPError>,
-) -> ERPResult<APIListResponse<ItemsDto>> {
-    // let items = state.item_service.get_item_list(&params).await?;
-    // let items_dto = state.item_service.to_items_dto(items).await?;
-    // let count = state.item_service.get_item_count(&params).await?;
-    // Ok(APIListResponse::new(items_dto, count))
-
-    todo!()
+    State(state): State<ItemState>,
+    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<ItemStockDto>> {
+    // 筛选条件和 /api/items 一样
+    let items = state
+        .item_service
+        .get_item_list(&params)
+        .await?
+        .iter()
+        .map(ItemStockDto::from)
+        .collect::<Vec<_>>();
+    let count = state.item_service.get_item_count(&params).await?;
+    Ok(APIListResponse::new(items, count))
 }
 
 async fn api_item_stock_out(
     State(state): State<ItemState>,
     Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<ItemStockOutMultiParams>, ERPError>,
-) -> ERPResult<APIEmptyResponse> {
+) -> ERPResult<APIDataResponse<Vec<InsufficientStockDto>>> {
     tracing::info!("api_item_inout : /api/item/stock/out");
 
-    state
+    // 库存不够但按设置允许出库的, 返回给前端提示
+    let warnings = state
         .item_service
         .stock_out_multiple(&params, account.id)
         .await?;
 
-    Ok(APIEmptyResponse::new())
+    Ok(APIDataResponse::new(warnings))
+}
+
+async fn api_item_transfer(
+    State(state): State<ItemState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<ItemTransferParams>, ERPError>,
+) -> ERPResult<APIDataResponse<Vec<InsufficientStockDto>>> {
+    tracing::info!("api_item_transfer : /api/item/transfer");
+    let warnings = state
+        .item_service
+        .transfer_items(&params, account.id)
+        .await?;
+    Ok(APIDataResponse::new(warnings))
 }
 
 async fn api_item_inout(
     State(state): State<ItemState>,
     Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<InoutParams>, ERPError>,
-) -> ERPResult<APIEmptyResponse> {
+) -> ERPResult<APIDataResponse<Vec<InsufficientStockDto>>> {
     tracing::info!("api_item_inout : /api/item/inout");
-    state
+    let warnings = state
         .item_service
         .add_item_inout(&params, account.id)
         .await?;
-    Ok(APIEmptyResponse::new())
+    Ok(APIDataResponse::new(warnings))
 }
 
 async fn api_inout_group_list(
@@ -1,14 +1,23 @@
-use crate::dto::dto_account::AccountDto;
+use crate::common::password::{hash_password, is_hashed, verify_password};
+use crate::config::database::DatabaseTrait;
+use crate::config::parameter;
+use crate::constants::{
+    LOGIN_FAILURE_WINDOW_MINUTES, LOGIN_MAX_FAILURES_PER_ACCOUNT, LOGIN_MAX_FAILURES_PER_IP,
+};
+use crate::dto::dto_account::{AccountDto, LoginDto};
+use crate::middleware::jwt_auth::{create_token, token_max_age_minutes, TOKEN_COOKIE_NAME};
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse};
+use crate::service::account_service::AccountServiceTrait;
 use crate::state::account_state::AccountState;
 use crate::{ERPError, ERPResult};
-use axum::extract::State;
-use axum::http::header;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap};
 use axum::response::IntoResponse;
 use axum::{routing::post, Json, Router};
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use axum_extra::extract::WithRejection;
 use serde::Deserialize;
+use std::net::SocketAddr;
 
 pub fn routes() -> Router<AccountState> {
     Router::new()
@@ -22,36 +31,131 @@ pub struct LoginPayload {
     pub password: String,
 }
 
+/// 前面有几层反向代理, 用 TRUSTED_PROXY_COUNT 配置, 默认0(直连)
+fn trusted_proxy_count() -> usize {
+    parameter::get_or("TRUSTED_PROXY_COUNT", "0")
+        .parse::<usize>()
+        .unwrap_or(0)
+}
+
+/// 客户端ip: 每层代理都会在 X-Forwarded-For 后面追加它看到的地址, 左边的是客户端自己填的不可信,
+/// 所以取从右数第 trusted_proxies 个; 没配置代理时只用连接的地址
+fn client_ip(
+    headers: &HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    trusted_proxies: usize,
+) -> String {
+    let connect_ip = connect_info.map(|ConnectInfo(addr)| addr.ip().to_string());
+    if trusted_proxies == 0 {
+        return connect_ip.unwrap_or_default();
+    }
+
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim())
+        .filter(|ip| !ip.is_empty())
+        .collect::<Vec<_>>();
+    hops.len()
+        .checked_sub(trusted_proxies)
+        .and_then(|index| hops.get(index))
+        .map(|ip| ip.to_string())
+        .or(connect_ip)
+        .unwrap_or_default()
+}
+
 async fn api_login(
     State(state): State<AccountState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     WithRejection(Json(payload), _): WithRejection<Json<LoginPayload>, ERPError>,
 ) -> Result<impl IntoResponse, ERPError> {
     tracing::info!("->> {:<12}, api_login", "handler");
 
-    let account = state
+    let ip = client_ip(&headers, connect_info, trusted_proxy_count());
+    let ip_failures = state
+        .account_service
+        .get_recent_login_failure_count_of_ip(&ip)
+        .await?;
+    if ip_failures >= LOGIN_MAX_FAILURES_PER_IP {
+        return Err(ERPError::TooManyLoginAttempts(LOGIN_FAILURE_WINDOW_MINUTES));
+    }
+
+    let Some(account) = state
         .account_repo
         .find_user_by_account(&payload.account)
         .await
-        .ok_or(ERPError::NotFound("账号不存在".to_string()))?;
+    else {
+        state
+            .account_service
+            .add_login_attempt(&payload.account, None, &ip, false)
+            .await?;
+        return Err(ERPError::NotFound("账号不存在".to_string()));
+    };
+
+    if account.disabled {
+        return Err(ERPError::AccountDisabled);
+    }
+
+    if account.locked_at.is_some() {
+        state
+            .account_service
+            .add_login_attempt(&payload.account, Some(account.id), &ip, false)
+            .await?;
+        return Err(ERPError::AccountLocked);
+    }
+
+    if !verify_password(&payload.password, &account.password) {
+        state
+            .account_service
+            .add_login_attempt(&payload.account, Some(account.id), &ip, false)
+            .await?;
+
+        let failures = state
+            .account_service
+            .get_recent_login_failure_count_of_account(account.id)
+            .await?;
+        if failures >= LOGIN_MAX_FAILURES_PER_ACCOUNT {
+            state.account_service.lock_account(account.id).await?;
+            return Err(ERPError::AccountLocked);
+        }
 
-    // todo: hash password.
-    if account.password != payload.password {
         return Err(ERPError::LoginFailForPasswordIsWrong);
     }
 
-    let account_id = account.id;
-    let account_dto = AccountDto::from(account);
+    state
+        .account_service
+        .add_login_attempt(&payload.account, Some(account.id), &ip, true)
+        .await?;
 
-    let cookie = Cookie::build("account_id", account_id.to_string())
+    // 老账号的明文密码，登录成功后顺便换成hash
+    if !is_hashed(&account.password) {
+        let hashed = hash_password(&payload.password)?;
+        let mut conn = state.account_repo.db.get_pool().acquire().await?;
+        state
+            .account_repo
+            .update_password(&mut conn, account.id, &hashed)
+            .await?;
+    }
+
+    let token = create_token(account.id)?;
+    let login_dto = LoginDto {
+        account: AccountDto::from(account),
+        token: token.clone(),
+    };
+
+    let cookie = Cookie::build(TOKEN_COOKIE_NAME, token)
         .path("/")
-        .max_age(time::Duration::days(365 * 10))
+        .max_age(time::Duration::minutes(token_max_age_minutes()))
         .same_site(SameSite::None)
         .domain("")
         .http_only(true)
         .secure(true)
         .finish();
 
-    let mut response = APIDataResponse::new(account_dto).into_response();
+    let mut response = APIDataResponse::new(login_dto).into_response();
     response
         .headers_mut()
         .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
@@ -60,7 +164,7 @@ async fn api_login(
 }
 
 async fn api_logout(State(_state): State<AccountState>) -> ERPResult<impl IntoResponse> {
-    let cookie = Cookie::build("account_id", "")
+    let cookie = Cookie::build(TOKEN_COOKIE_NAME, "")
         .path("/")
         .max_age(time::Duration::hours(-1))
         .domain("")
@@ -78,11 +182,29 @@ async fn api_logout(State(_state): State<AccountState>) -> ERPResult<impl IntoRe
 
 #[cfg(test)]
 mod tests {
-    use crate::handler::routes_login::LoginPayload;
-    use crate::ERPResult;
+    use crate::handler::routes_login::{client_ip, LoginPayload};
+    use axum::extract::ConnectInfo;
+    use axum::http::HeaderMap;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_client_ip() {
+        let addr: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_ip(&headers, Some(ConnectInfo(addr)), 1), "10.0.0.1");
+
+        // 没配置代理时不信任转发的头
+        headers.insert("x-forwarded-for", "2.2.2.2, 3.3.3.3".parse().unwrap());
+        assert_eq!(client_ip(&headers, Some(ConnectInfo(addr)), 0), "10.0.0.1");
+
+        // 客户端自己填的 2.2.2.2 不算, 取代理追加的
+        assert_eq!(client_ip(&headers, Some(ConnectInfo(addr)), 1), "3.3.3.3");
+        assert_eq!(client_ip(&headers, None, 2), "2.2.2.2");
+        assert_eq!(client_ip(&headers, Some(ConnectInfo(addr)), 3), "10.0.0.1");
+    }
 
     #[tokio::test]
-    async fn test() -> ERPResult<()> {
+    async fn test() -> anyhow::Result<()> {
         let param = LoginPayload {
             account: "test".to_string(),
             password: "test".to_string(),
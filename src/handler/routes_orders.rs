@@ -1,15 +1,26 @@
+use crate::constants::{ROLE_ADMIN, ROLE_SALES, ROLE_WAREHOUSE};
 use crate::dto::dto_account::AccountDto;
+use crate::dto::dto_excel::OrderExcelDto;
 use crate::dto::dto_orders::{
-    CreateOrderParams, DeleteOrderParams, ImportedOrderDetailDto, OrderDetailDto,
-    OrderDetailQueryParams, OrderInListDto, QueryParams,
+    CreateOrderParams, DeleteOrderParams, EditOrderParams, ImportedOrderDetailDto, OrderDetailDto,
+    OrderDetailQueryParams, OrderDto, OrderInListDto, OrderItemDto, QueryParams,
+    UpdateOrderStatusParams,
 };
+use crate::dto::dto_stock::InsufficientStockDto;
+use crate::excel::export_orders::export_order;
+use crate::middleware::permission::permission;
+use crate::model::order::ImportedOrderItemModel;
+use crate::pdf::delivery_note::{render_delivery_note, DeliveryNoteTemplate};
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
 use crate::service::order_service::OrderServiceTrait;
 use crate::state::order_state::OrderState;
 use crate::{ERPError, ERPResult};
 use axum::extract::Query;
+use axum::http::header;
+use axum::middleware::from_fn_with_state;
 use axum::{
     extract::State,
+    response::IntoResponse,
     routing::{get, post},
     Extension, Json, Router,
 };
@@ -19,18 +30,55 @@ pub fn routes() -> Router<OrderState> {
     Router::new()
         .route("/api/orders/list", get(api_order_list))
         .route("/api/imported/orders/list", get(api_imported_order_list))
-        .route("/api/orders/create", post(api_create_order))
+        .route(
+            "/api/orders/create",
+            post(api_create_order).route_layer(from_fn_with_state(&[ROLE_SALES][..], permission)),
+        )
+        .route(
+            "/api/order/edit",
+            post(api_order_edit).route_layer(from_fn_with_state(&[ROLE_SALES][..], permission)),
+        )
         .route("/api/order/detail", get(api_order_detail))
+        .route("/api/order/pdf", get(api_order_pdf))
+        .route("/api/order/export", get(api_order_export))
         .route("/api/imported/order/detail", get(api_imported_order_detail))
-        .route("/api/order/delete", post(api_order_delete))
+        .route(
+            "/api/order/delete",
+            post(api_order_delete).route_layer(from_fn_with_state(&[ROLE_ADMIN][..], permission)),
+        )
+        .route(
+            "/api/order/confirm",
+            post(api_order_confirm).route_layer(from_fn_with_state(&[ROLE_SALES][..], permission)),
+        )
+        .route(
+            "/api/order/ship",
+            post(api_order_ship).route_layer(from_fn_with_state(&[ROLE_WAREHOUSE][..], permission)),
+        )
+        .route(
+            "/api/order/complete",
+            post(api_order_complete).route_layer(from_fn_with_state(&[ROLE_SALES][..], permission)),
+        )
+        .route(
+            "/api/order/cancel",
+            post(api_order_cancel).route_layer(from_fn_with_state(&[ROLE_SALES][..], permission)),
+        )
+}
+
+/// 业务部的账号只能看到自己的订单
+fn check_order_visible(account: &AccountDto, order: &OrderDto) -> ERPResult<()> {
+    if account.role == ROLE_SALES && order.account_id != account.id {
+        return Err(ERPError::NoPermission("只能查看自己的订单".to_string()));
+    }
+    Ok(())
 }
 
 async fn api_order_detail(
     State(state): State<OrderState>,
-    Extension(_): Extension<AccountDto>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Query(params), _): WithRejection<Query<OrderDetailQueryParams>, ERPError>,
 ) -> ERPResult<APIDataResponse<OrderDetailDto>> {
     let order_dto = state.order_service.get_order(params.order_id).await?;
+    check_order_visible(&account, &order_dto)?;
     let order_items_dtos = state.order_service.get_order_items(params.order_id).await?;
 
     Ok(APIDataResponse::new(OrderDetailDto {
@@ -39,12 +87,140 @@ async fn api_order_detail(
     }))
 }
 
+/// 出库单pdf
+async fn api_order_pdf(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(params), _): WithRejection<Query<OrderDetailQueryParams>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    let order_dto = state.order_service.get_order(params.order_id).await?;
+    check_order_visible(&account, &order_dto)?;
+    // 旧格式导入的订单商品在 order_items 里, 可以生成; 按模版导入的只有 import_order_items, 没有商品
+    let order_items_dtos = state.order_service.get_order_items(params.order_id).await?;
+    if order_items_dtos.is_empty() && order_dto.tp != 0 {
+        return Err(ERPError::Failed(
+            "导入的订单没有关联商品, 不能生成出库单".to_string(),
+        ));
+    }
+    let detail = OrderDetailDto {
+        order: order_dto,
+        items: order_items_dtos,
+    };
+
+    let filename = format!("{}.pdf", detail.order.order_no);
+    let template = DeliveryNoteTemplate::load()?;
+    // 读图片和生成pdf比较耗时, 不占用异步线程
+    let bytes = tokio::task::spawn_blocking(move || render_delivery_note(&template, &detail))
+        .await
+        .map_err(|e| ERPError::Failed(format!("生成pdf失败: {}", e)))??;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// 导出的行: 有 order_items 的用 order_items, 没有的(按模版导入的订单)用 import_order_items
+fn order_excel_rows(
+    order_items: Vec<OrderItemDto>,
+    imported_items: Vec<ImportedOrderItemModel>,
+) -> Vec<OrderExcelDto> {
+    if !order_items.is_empty() {
+        return order_items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| OrderExcelDto {
+                row: 0,
+                index: index as i32 + 1,
+                number: item.number,
+                images: item.images,
+                size: item.size,
+                name: item.name,
+                color: item.color,
+                count: item.count,
+                unit: item.unit,
+                price: item.price,
+                total: item.total_price,
+                notes: "".to_string(),
+            })
+            .collect();
+    }
+
+    imported_items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| OrderExcelDto {
+            row: 0,
+            index: index as i32 + 1,
+            number: item.number,
+            images: item.images,
+            size: item.size,
+            name: item.name,
+            color: item.color,
+            count: item.count,
+            unit: item.unit,
+            price: item.price,
+            total: item.total_price,
+            notes: "".to_string(),
+        })
+        .collect()
+}
+
+/// 导出成和导入一样格式的excel
+async fn api_order_export(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(params), _): WithRejection<Query<OrderDetailQueryParams>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    let order_dto = state.order_service.get_order(params.order_id).await?;
+    check_order_visible(&account, &order_dto)?;
+
+    // 旧格式导入的订单商品也在 order_items 里, 按明细实际在哪张表取
+    let order_items = state.order_service.get_order_items(params.order_id).await?;
+    let imported_items = match order_items.is_empty() {
+        true => {
+            state
+                .order_service
+                .get_imported_order_items(params.order_id)
+                .await?
+        }
+        false => vec![],
+    };
+    let items = order_excel_rows(order_items, imported_items);
+
+    let filename = format!("{}.xlsx", order_dto.order_no);
+    let bytes = tokio::task::spawn_blocking(move || export_order(&order_dto, &items))
+        .await
+        .map_err(|e| ERPError::Failed(format!("生成excel失败: {}", e)))??;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        bytes,
+    ))
+}
+
 async fn api_imported_order_detail(
     State(state): State<OrderState>,
-    Extension(_): Extension<AccountDto>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Query(params), _): WithRejection<Query<OrderDetailQueryParams>, ERPError>,
 ) -> ERPResult<APIDataResponse<ImportedOrderDetailDto>> {
     let order_dto = state.order_service.get_order(params.order_id).await?;
+    check_order_visible(&account, &order_dto)?;
     let order_items_dtos = state
         .order_service
         .get_imported_order_items(params.order_id)
@@ -58,9 +234,12 @@ async fn api_imported_order_detail(
 
 async fn api_order_list(
     State(state): State<OrderState>,
-    Extension(_account): Extension<AccountDto>,
-    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(mut params), _): WithRejection<Query<QueryParams>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderInListDto>> {
+    if account.role == ROLE_SALES {
+        params.account_id = account.id;
+    }
     tracing::info!("api_order_list...");
     let orders = state.order_service.get_order_list(&params).await?;
     tracing::info!("orders.len: {}", orders.len());
@@ -72,9 +251,12 @@ async fn api_order_list(
 
 async fn api_imported_order_list(
     State(state): State<OrderState>,
-    Extension(_account): Extension<AccountDto>,
-    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Query(mut params), _): WithRejection<Query<QueryParams>, ERPError>,
 ) -> ERPResult<APIListResponse<OrderInListDto>> {
+    if account.role == ROLE_SALES {
+        params.account_id = account.id;
+    }
     tracing::info!("api_order_list...");
     let orders = state.order_service.get_imported_order_list(&params).await?;
     tracing::info!("orders.len: {}", orders.len());
@@ -107,22 +289,160 @@ async fn api_create_order(
     Ok(APIDataResponse::new(OrderId { id: order_id }))
 }
 
+async fn api_order_edit(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<EditOrderParams>, ERPError>,
+) -> ERPResult<APIDataResponse<Vec<InsufficientStockDto>>> {
+    let order = state.order_service.get_order(params.id).await?;
+    check_order_visible(&account, &order)?;
+    // 修改已发货的订单, 库存不够但按设置允许出库的, 返回给前端提示
+    let warnings = state.order_service.edit_order(&params, account.id).await?;
+
+    Ok(APIDataResponse::new(warnings))
+}
+
 async fn api_order_delete(
     State(state): State<OrderState>,
-    Extension(_): Extension<AccountDto>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<DeleteOrderParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     let order = state.order_service.get_order(params.id).await?;
     match order.tp {
         0 => {
             // 正常订单
-            state.order_service.delete_order(params.id).await?;
+            state
+                .order_service
+                .delete_order(params.id, account.id)
+                .await?;
         }
         _ => {
             // 导入订单
-            state.order_service.delete_import_order(params.id).await?;
+            state
+                .order_service
+                .delete_import_order(params.id, account.id)
+                .await?;
         }
     }
 
     Ok(APIEmptyResponse::new())
 }
+
+async fn api_order_confirm(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UpdateOrderStatusParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let order = state.order_service.get_order(params.id).await?;
+    check_order_visible(&account, &order)?;
+    state
+        .order_service
+        .confirm_order(params.id, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_order_ship(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UpdateOrderStatusParams>, ERPError>,
+) -> ERPResult<APIDataResponse<Vec<InsufficientStockDto>>> {
+    let warnings = state
+        .order_service
+        .ship_order(params.id, params.warehouse_id, account.id)
+        .await?;
+
+    Ok(APIDataResponse::new(warnings))
+}
+
+async fn api_order_complete(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UpdateOrderStatusParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let order = state.order_service.get_order(params.id).await?;
+    check_order_visible(&account, &order)?;
+    state
+        .order_service
+        .complete_order(params.id, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_order_cancel(
+    State(state): State<OrderState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<UpdateOrderStatusParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    let order = state.order_service.get_order(params.id).await?;
+    check_order_visible(&account, &order)?;
+    state
+        .order_service
+        .cancel_order(params.id, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn order_item(number: &str, count: i32) -> OrderItemDto {
+        OrderItemDto {
+            id: 1,
+            order_id: 1,
+            index: 1,
+            item_id: 1,
+            images: vec![],
+            size: "".to_string(),
+            color: "GOLD".to_string(),
+            name: "耳环".to_string(),
+            number: number.to_string(),
+            unit: "对".to_string(),
+            count,
+            origin_price: 100,
+            price: 100,
+            total_price: 100 * count,
+            discount: 100,
+            create_time: Utc::now(),
+        }
+    }
+
+    fn imported_item(number: &str, count: i32) -> ImportedOrderItemModel {
+        ImportedOrderItemModel {
+            id: 1,
+            index: 1,
+            order_id: 1,
+            number: number.to_string(),
+            images: vec![],
+            size: "".to_string(),
+            name: "项链".to_string(),
+            color: "SILVER".to_string(),
+            count,
+            unit: "条".to_string(),
+            price: 200,
+            total_price: 200 * count,
+            create_time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_order_excel_rows() {
+        // 旧格式导入的订单(tp 1)商品在 order_items 里
+        let rows = order_excel_rows(vec![order_item("A1", 2), order_item("A2", 3)], vec![]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[1].index, rows[1].number.as_str()), (2, "A2"));
+        assert_eq!(rows[1].total, 300);
+
+        // 按模版导入的订单商品只在 import_order_items 里
+        let rows = order_excel_rows(vec![], vec![imported_item("B1", 4)]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].number.as_str(), rows[0].count), ("B1", 4));
+
+        assert!(order_excel_rows(vec![], vec![]).is_empty());
+    }
+}
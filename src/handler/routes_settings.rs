@@ -1,20 +1,28 @@
+use crate::constants::ROLE_ADMIN;
+use crate::dto::dto_account::AccountDto;
 use crate::dto::dto_settings::{
-    ColorEditParams, CustomerTypeEditParams, GlobalSettingsUpdateParams,
+    ColorEditParams, CustomerTypeEditParams, GlobalSettingsUpdateParams, WarehouseEditParams,
 };
 use crate::dto::GenericDeleteParams;
-use crate::model::settings::{ColorSettingsModel, CustomerTypeModel, GlobalSettingsModel};
+use crate::middleware::permission::permission;
+use crate::model::settings::{
+    ColorSettingsModel, CustomerTypeModel, GlobalSettingsModel, WarehouseModel,
+};
 use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
 use crate::service::settings_service::SettingsServiceTrait;
 use crate::state::settings_state::SettingsState;
 use crate::{ERPError, ERPResult};
+use axum::middleware::from_fn_with_state;
 use axum::{
     extract::State,
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use axum_extra::extract::WithRejection;
 
 pub fn routes() -> Router<SettingsState> {
+    let admin = from_fn_with_state(&[ROLE_ADMIN][..], permission);
+
     Router::new()
         .route(
             "/api/settings/color/value/sort/by/color",
@@ -23,25 +31,34 @@ pub fn routes() -> Router<SettingsState> {
         .route("/api/settings/color/value", get(api_get_color_values))
         .route(
             "/api/settings/edit/color/value",
-            post(api_edit_color_values),
+            post(api_edit_color_values).route_layer(admin.clone()),
         )
         .route(
             "/api/settings/delete/color/value",
-            post(api_delete_color_values),
+            post(api_delete_color_values).route_layer(admin.clone()),
         )
         .route("/api/settings/global", get(api_get_global_settings))
         .route(
             "/api/settings/global/update",
-            post(api_update_global_settings),
+            post(api_update_global_settings).route_layer(admin.clone()),
         )
         .route("/api/settings/customer/types", get(api_get_customer_types))
         .route(
             "/api/settings/edit/customer/type",
-            post(api_edit_customer_type),
+            post(api_edit_customer_type).route_layer(admin.clone()),
         )
         .route(
             "/api/settings/delete/customer/type",
-            post(api_delete_customer_type),
+            post(api_delete_customer_type).route_layer(admin.clone()),
+        )
+        .route("/api/settings/warehouses", get(api_get_warehouses))
+        .route(
+            "/api/settings/edit/warehouse",
+            post(api_edit_warehouse).route_layer(admin.clone()),
+        )
+        .route(
+            "/api/settings/delete/warehouse",
+            post(api_delete_warehouse).route_layer(admin),
         )
 }
 
@@ -69,24 +86,29 @@ async fn api_get_color_values(
 
 async fn api_edit_color_values(
     State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<ColorEditParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, api_get_color_values", "handler");
 
-    state.settings_service.edit_color_to_value(&params).await?;
+    state
+        .settings_service
+        .edit_color_to_value(&params, account.id)
+        .await?;
 
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_delete_color_values(
     State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, delete_get_color_values", "handler");
 
     state
         .settings_service
-        .delete_color_to_value(&params)
+        .delete_color_to_value(&params, account.id)
         .await?;
 
     Ok(APIEmptyResponse::new())
@@ -104,13 +126,14 @@ async fn api_get_global_settings(
 
 async fn api_update_global_settings(
     State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<GlobalSettingsUpdateParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, api_update_global_settings", "handler");
 
     state
         .settings_service
-        .update_global_settings(&params)
+        .update_global_settings(&params, account.id)
         .await?;
 
     Ok(APIEmptyResponse::new())
@@ -129,22 +152,71 @@ async fn api_get_customer_types(
 
 async fn api_edit_customer_type(
     State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<CustomerTypeEditParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, api_edit_customer_type", "handler");
 
-    state.settings_service.edit_customer_type(&params).await?;
+    state
+        .settings_service
+        .edit_customer_type(&params, account.id)
+        .await?;
 
     Ok(APIEmptyResponse::new())
 }
 
 async fn api_delete_customer_type(
     State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
     WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
 ) -> ERPResult<APIEmptyResponse> {
     tracing::info!("->> {:<12}, api_delete_customer_type", "handler");
 
-    state.settings_service.delete_customer_type(&params).await?;
+    state
+        .settings_service
+        .delete_customer_type(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_get_warehouses(
+    State(state): State<SettingsState>,
+) -> ERPResult<APIListResponse<WarehouseModel>> {
+    tracing::info!("->> {:<12}, api_get_warehouses", "handler");
+
+    let warehouses = state.settings_service.get_warehouses().await?;
+    let len = warehouses.len() as i32;
+
+    Ok(APIListResponse::new(warehouses, len))
+}
+
+async fn api_edit_warehouse(
+    State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<WarehouseEditParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_edit_warehouse", "handler");
+
+    state
+        .settings_service
+        .edit_warehouse(&params, account.id)
+        .await?;
+
+    Ok(APIEmptyResponse::new())
+}
+
+async fn api_delete_warehouse(
+    State(state): State<SettingsState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<GenericDeleteParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    tracing::info!("->> {:<12}, api_delete_warehouse", "handler");
+
+    state
+        .settings_service
+        .delete_warehouse(&params, account.id)
+        .await?;
 
     Ok(APIEmptyResponse::new())
 }
@@ -0,0 +1,204 @@
+use crate::constants::ROLE_WAREHOUSE;
+use crate::dto::dto_account::AccountDto;
+use crate::dto::dto_stocktake::{
+    QueryParams, StocktakeCountParams, StocktakeDetailDto, StocktakeDto, StocktakeParams,
+    StocktakeStartParams,
+};
+use crate::excel::common::get_first_sheet_name;
+use crate::excel::export_stocktake::export_stocktake;
+use crate::excel::parse_stocktake::parse_stocktake;
+use crate::handler::routes_excel::multipart_error;
+use crate::middleware::permission::permission;
+use crate::response::api_response::{APIDataResponse, APIEmptyResponse, APIListResponse};
+use crate::service::stocktake_service::StocktakeServiceTrait;
+use crate::state::stocktake_state::StocktakeState;
+use crate::{ERPError, ERPResult};
+use axum::extract::{Multipart, Query, State};
+use axum::http::header;
+use axum::middleware::from_fn_with_state;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum_extra::extract::WithRejection;
+use rand::Rng;
+use std::fs;
+
+pub fn routes() -> Router<StocktakeState> {
+    let warehouse = from_fn_with_state(&[ROLE_WAREHOUSE][..], permission);
+
+    Router::new()
+        .route("/api/stocktakes", get(api_stocktake_list))
+        .route("/api/stocktake", get(api_stocktake_detail))
+        .route("/api/stocktake/export", get(api_stocktake_export))
+        .route(
+            "/api/stocktake/start",
+            post(api_stocktake_start).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/stocktake/count",
+            post(api_stocktake_count).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/stocktake/upload",
+            post(api_stocktake_upload).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/stocktake/post",
+            post(api_stocktake_post).route_layer(warehouse.clone()),
+        )
+        .route(
+            "/api/stocktake/cancel",
+            post(api_stocktake_cancel).route_layer(warehouse),
+        )
+}
+
+async fn api_stocktake_list(
+    State(state): State<StocktakeState>,
+    WithRejection(Query(params), _): WithRejection<Query<QueryParams>, ERPError>,
+) -> ERPResult<APIListResponse<StocktakeDto>> {
+    let stocktakes = state.stocktake_service.get_stocktake_list(&params).await?;
+    let count = state.stocktake_service.get_stocktake_count(&params).await?;
+    Ok(APIListResponse::new(stocktakes, count))
+}
+
+async fn api_stocktake_detail(
+    State(state): State<StocktakeState>,
+    WithRejection(Query(params), _): WithRejection<Query<StocktakeParams>, ERPError>,
+) -> ERPResult<APIDataResponse<StocktakeDetailDto>> {
+    let detail = state
+        .stocktake_service
+        .get_stocktake_detail(params.id)
+        .await?;
+    Ok(APIDataResponse::new(detail))
+}
+
+/// 下载盘点表, 填好盘点数量后用 /api/stocktake/upload 上传
+async fn api_stocktake_export(
+    State(state): State<StocktakeState>,
+    WithRejection(Query(params), _): WithRejection<Query<StocktakeParams>, ERPError>,
+) -> ERPResult<impl IntoResponse> {
+    let rows = state
+        .stocktake_service
+        .get_stocktake_sheet(params.id)
+        .await?;
+
+    let filename = format!("stocktake-{}.xlsx", params.id);
+    let bytes = tokio::task::spawn_blocking(move || export_stocktake(&rows))
+        .await
+        .map_err(|e| ERPError::Failed(format!("生成excel失败: {}", e)))??;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+async fn api_stocktake_start(
+    State(state): State<StocktakeState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<StocktakeStartParams>, ERPError>,
+) -> ERPResult<APIDataResponse<StocktakeDto>> {
+    let stocktake = state
+        .stocktake_service
+        .start_stocktake(&params, account.id)
+        .await?;
+    Ok(APIDataResponse::new(stocktake))
+}
+
+async fn api_stocktake_count(
+    State(state): State<StocktakeState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<StocktakeCountParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    state
+        .stocktake_service
+        .count_items(&params, account.id)
+        .await?;
+    Ok(APIEmptyResponse::new())
+}
+
+/// 表单: id 盘点id, file 盘点表; 返回录入的商品数
+async fn api_stocktake_upload(
+    State(state): State<StocktakeState>,
+    Extension(account): Extension<AccountDto>,
+    mut multipart: Multipart,
+) -> ERPResult<APIDataResponse<i32>> {
+    let mut id = 0;
+    let mut file_path = "".to_string();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| multipart_error(e, "上传的表单有误"))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| multipart_error(e, &format!("读取 {} 失败", name)))?;
+        if name == "file" {
+            let file_path_full = format!(
+                "/tmp/stocktake-{}{:04}.xlsx",
+                chrono::Local::now().format("%Y%m%d%H%M%S"),
+                rand::thread_rng().gen_range(0..9999)
+            );
+            fs::write(&file_path_full, data).map_err(|_| {
+                ERPError::SaveFileFailed(format!("create {} failed", file_path_full))
+            })?;
+            file_path = file_path_full;
+        } else if name == "id" {
+            id = String::from_utf8_lossy(&data).parse::<i32>().unwrap_or(0);
+        }
+    }
+
+    if file_path.is_empty() {
+        return Err(ERPError::ParamNeeded("file".to_string()));
+    }
+    let parse_path = file_path.clone();
+    let rows = tokio::task::spawn_blocking(move || {
+        parse_stocktake(&parse_path).map(|rows| (get_first_sheet_name(&parse_path), rows))
+    })
+    .await
+    .map_err(|e| ERPError::ExcelError(format!("解析盘点表失败: {}", e)));
+    let _ = fs::remove_file(&file_path);
+    let (sheet, rows) = rows??;
+
+    let count = state
+        .stocktake_service
+        .count_items_from_excel(id, &sheet, &rows, account.id)
+        .await?;
+    Ok(APIDataResponse::new(count))
+}
+
+async fn api_stocktake_post(
+    State(state): State<StocktakeState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<StocktakeParams>, ERPError>,
+) -> ERPResult<APIDataResponse<StocktakeDto>> {
+    let stocktake = state
+        .stocktake_service
+        .post_stocktake(params.id, account.id)
+        .await?;
+    Ok(APIDataResponse::new(stocktake))
+}
+
+async fn api_stocktake_cancel(
+    State(state): State<StocktakeState>,
+    Extension(account): Extension<AccountDto>,
+    WithRejection(Json(params), _): WithRejection<Json<StocktakeParams>, ERPError>,
+) -> ERPResult<APIEmptyResponse> {
+    state
+        .stocktake_service
+        .cancel_stocktake(params.id, account.id)
+        .await?;
+    Ok(APIEmptyResponse::new())
+}
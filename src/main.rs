@@ -6,6 +6,9 @@ extern crate lazy_static;
 pub use self::error::{ERPError, ERPResult};
 use crate::config::database::DatabaseTrait;
 use crate::config::{database, parameter};
+use crate::service::import_job_service::{ImportJobService, ImportJobServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
+use clap::{Parser, Subcommand};
 use std::net::SocketAddr;
 use std::sync::Arc;
 // use tokio::net::TcpListener;
@@ -19,14 +22,31 @@ mod excel;
 mod handler;
 mod middleware;
 mod model;
+mod pdf;
 mod repository;
 mod response;
 mod service;
 mod state;
 
+#[derive(Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 按出入库记录重新计算库存, 打印对不上的; 加 --fix 时改成算出来的
+    ReconcileStock {
+        #[arg(long)]
+        fix: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
 
     parameter::init();
     let port = parameter::get("PORT")
@@ -36,6 +56,20 @@ async fn main() {
     let database = database::Database::init()
         .await
         .unwrap_or_else(|e| panic!("Database error: {}", e));
+    let database = Arc::new(database);
+
+    if let Some(Command::ReconcileStock { fix }) = cli.command {
+        reconcile_stock(&database, fix)
+            .await
+            .unwrap_or_else(|e| panic!("reconcile stock error: {:?}", e));
+        return;
+    }
+
+    let import_job_service = ImportJobService::new(&database);
+    match import_job_service.fail_unfinished_import_jobs().await {
+        Ok(count) => tracing::info!("=> {count} unfinished import jobs marked as failed"),
+        Err(e) => tracing::error!("fail unfinished import jobs: {:?}", e),
+    }
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     tracing::info!("=> Listen on {addr} \n");
@@ -47,7 +81,34 @@ async fn main() {
     // .await
     // .expect("TODO: panic message");
     axum::Server::bind(&addr)
-        .serve(handler::routes(Arc::new(database)))
+        .serve(handler::routes(database))
         .await
         .unwrap();
 }
+
+async fn reconcile_stock(database: &Arc<database::Database>, fix: bool) -> ERPResult<()> {
+    let stock_service = StockService::new(database);
+    let item_drifts = stock_service.reconcile_item_stock(fix).await?;
+    let embryo_drifts = stock_service.reconcile_embryo_stock(fix).await?;
+
+    for (name, drifts) in [("商品", item_drifts), ("库存胚", embryo_drifts)] {
+        println!("{}库存对不上的有{}个", name, drifts.len());
+        for drift in drifts.iter() {
+            println!(
+                "  id={} 编号={} 颜色={} 仓库={} 出入库合计={} 库存={} 差={}",
+                drift.id,
+                drift.number,
+                drift.color,
+                drift.warehouse_id,
+                drift.expected,
+                drift.actual,
+                drift.actual - drift.expected
+            );
+        }
+    }
+    if fix {
+        println!("已按出入库记录修正");
+    }
+
+    Ok(())
+}
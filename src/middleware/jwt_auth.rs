@@ -1,99 +1,117 @@
-// use std::sync::Arc;
-
-// use axum::{
-//     extract::State,
-//     http::{header, Request, StatusCode},
-//     middleware::Next,
-//     response::IntoResponse,
-//     Json,
-// };
-
-// use axum_extra::extract::cookie::CookieJar;
-// use jsonwebtoken::{decode, DecodingKey, Validation};
-// use serde::Serialize;
-
-// use crate::{
-//     model::{TokenClaims, User},
-//     AppState,
-// };
-
-// #[derive(Debug, Serialize)]
-// pub struct ErrorResponse {
-//     pub status: &'static str,
-//     pub message: String,
-// }
-
-// pub async fn auth<B>(
-//     cookie_jar: CookieJar,
-//     State(data): State<Arc<AppState>>,
-//     mut req: Request<B>,
-//     next: Next<B>,
-// ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-//     let token = cookie_jar
-//         .get("token")
-//         .map(|cookie| cookie.value().to_string())
-//         .or_else(|| {
-//             req.headers()
-//                 .get(header::AUTHORIZATION)
-//                 .and_then(|auth_header| auth_header.to_str().ok())
-//                 .and_then(|auth_value| {
-//                     if auth_value.starts_with("Bearer ") {
-//                         Some(auth_value[7..].to_owned())
-//                     } else {
-//                         None
-//                     }
-//                 })
-//         });
-//
-//     let token = token.ok_or_else(|| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "You are not logged in, please provide token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     let claims = decode::<TokenClaims>(
-//         &token,
-//         &DecodingKey::from_secret(data.env.jwt_secret.as_ref()),
-//         &Validation::default(),
-//     )
-//     .map_err(|_| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "Invalid token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?
-//     .claims;
-//
-//     let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "Invalid token".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     let user = sqlx::query_as!(User, "SELECT * FROM users WHERE id = $1", user_id)
-//         .fetch_optional(&data.db)
-//         .await
-//         .map_err(|e| {
-//             let json_error = ErrorResponse {
-//                 status: "fail",
-//                 message: format!("Error fetching user from database: {}", e),
-//             };
-//             (StatusCode::INTERNAL_SERVER_ERROR, Json(json_error))
-//         })?;
-//
-//     let user = user.ok_or_else(|| {
-//         let json_error = ErrorResponse {
-//             status: "fail",
-//             message: "The user belonging to this token no longer exists".to_string(),
-//         };
-//         (StatusCode::UNAUTHORIZED, Json(json_error))
-//     })?;
-//
-//     req.extensions_mut().insert(user);
-//     Ok(next.run(req).await)
-// }
+use crate::config::parameter;
+use crate::dto::dto_account::AccountDto;
+use crate::state::account_state::AccountState;
+use crate::{ERPError, ERPResult};
+use axum::{
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::IntoResponse,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+
+pub const TOKEN_COOKIE_NAME: &str = "token";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub sub: String, // account id
+    pub iat: usize,
+    pub exp: usize,
+}
+
+/// token有效期(分钟)，从 JWT_MAXAGE 读取
+pub fn token_max_age_minutes() -> i64 {
+    parameter::get("JWT_MAXAGE")
+        .parse::<i64>()
+        .expect("JWT_MAXAGE should be number")
+}
+
+pub fn create_token(account_id: i32) -> ERPResult<String> {
+    let now = Utc::now();
+    let claims = TokenClaims {
+        sub: account_id.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::minutes(token_max_age_minutes())).timestamp() as usize,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(parameter::get("JWT_SECRET").as_ref()),
+    )
+    .map_err(|_| ERPError::Failed("生成token失败".to_string()))
+}
+
+pub fn decode_token(token: &str) -> ERPResult<i32> {
+    let claims = decode::<TokenClaims>(
+        token,
+        &DecodingKey::from_secret(parameter::get("JWT_SECRET").as_ref()),
+        &Validation::default(),
+    )
+    .map_err(|_| ERPError::NotAuthorized)?
+    .claims;
+
+    claims
+        .sub
+        .parse::<i32>()
+        .map_err(|_| ERPError::NotAuthorized)
+}
+
+pub async fn auth<B>(
+    cookie_jar: CookieJar,
+    State(state): State<AccountState>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, ERPError> {
+    let token = cookie_jar
+        .get(TOKEN_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .or_else(|| {
+            req.headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|auth_header| auth_header.to_str().ok())
+                .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+                .map(|token| token.to_owned())
+        })
+        .ok_or(ERPError::NotAuthorized)?;
+
+    let account_id = decode_token(&token)?;
+
+    let account = state
+        .account_repo
+        .find_user_by_id(account_id)
+        .await
+        .ok_or(ERPError::AccountNotFound)?;
+    if account.disabled {
+        return Err(ERPError::AccountDisabled);
+    }
+
+    let account_dto = AccountDto::from(account);
+
+    req.extensions_mut().insert(account_dto);
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::jwt_auth::{create_token, decode_token};
+    use crate::ERPError;
+
+    #[test]
+    fn test_token_round_trip() {
+        std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::set_var("JWT_MAXAGE", "60");
+
+        let token = create_token(42).unwrap();
+        assert_eq!(decode_token(&token).unwrap(), 42);
+
+        let mut tampered = token.clone();
+        tampered.push('x');
+        assert!(matches!(
+            decode_token(&tampered),
+            Err(ERPError::NotAuthorized)
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+use crate::dto::dto_account::AccountDto;
+use crate::ERPError;
+use axum::{extract::State, http::Request, middleware::Next, response::IntoResponse, Extension};
+
+/// 需要挂在 auth 之后，按角色限制接口, 例:
+/// post(handler).route_layer(from_fn_with_state(&[ROLE_WAREHOUSE][..], permission))
+pub async fn permission<B>(
+    State(roles): State<&'static [&'static str]>,
+    Extension(account): Extension<AccountDto>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, ERPError> {
+    if !account.has_role(roles) {
+        return Err(ERPError::NoPermission(format!(
+            "{}({})",
+            req.uri().path(),
+            account.department
+        )));
+    }
+
+    Ok(next.run(req).await)
+}
@@ -6,5 +6,19 @@ pub struct AccountModel {
     pub name: String,
     pub account: String,
     pub password: String,
+    pub disabled: bool,
+    pub role: String,
+    pub create_time: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub unlocked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct LoginAttemptModel {
+    pub id: i32,
+    pub account: String,
+    pub account_id: Option<i32>,
+    pub ip: String,
+    pub success: bool,
     pub create_time: DateTime<Utc>,
 }
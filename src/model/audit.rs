@@ -0,0 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct AuditLogModel {
+    pub id: i32,
+    pub account_id: i32,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub create_time: DateTime<Utc>,
+}
@@ -20,6 +20,7 @@ pub struct EmbryoInOutBucketModal {
     pub account_id: i32,            // 商品图片
     pub in_true_out_false: bool,    // 增加还是减少
     pub via: String,                // 规格
+    pub warehouse_id: i32,          // 仓库id
     pub create_time: DateTime<Utc>, // 创建时间
 }
 
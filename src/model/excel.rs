@@ -1,6 +1,21 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct ExcelTemplateModel {
+    pub id: i32,
+    pub name: String,
+    pub header_row: i32,     // 表头在第几行
+    pub data_start_row: i32, // 数据从第几行开始
+    pub columns: Value,      // 表头文字 -> 字段
+    pub order_date_regex: String,
+    pub delivery_date_regex: String,
+    pub create_time: DateTime<Utc>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
 pub struct CustomerExcelTemplateModel {
-    pub id: i32,             // SERIAL,
-    pub customer_no: String, // 客户编号
-    pub template_id: i32,    // 备注
+    pub id: i32,          // SERIAL,
+    pub customer_id: i32, // 客户id
+    pub template_id: i32, // 导入模版id
 }
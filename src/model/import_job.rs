@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct ImportJobModel {
+    pub id: i32,
+    pub account_id: i32,
+    pub tp: i32,
+    pub customer_id: i32,
+    pub filename: String,
+    pub file_path: String,
+    pub row_count: i32,
+    pub created_count: i32,
+    pub updated_count: i32,
+    pub status: i32,
+    pub errors: Option<Value>,
+    pub create_time: DateTime<Utc>,
+    pub finish_time: Option<DateTime<Utc>>,
+}
@@ -27,6 +27,7 @@ pub struct ItemInOutBucketModal {
     pub in_true_out_false: bool,    // 增加还是减少
     pub via: String,                // 规格
     pub order_id: i32,              // 颜色
+    pub warehouse_id: i32,          // 仓库id
     pub create_time: DateTime<Utc>, // 创建时间
 }
 
@@ -1,8 +1,11 @@
 pub mod account;
+pub mod audit;
 pub mod cates;
 pub mod customer;
 pub mod embryo;
 pub mod excel;
+pub mod import_job;
 pub mod items;
 pub mod order;
 pub mod settings;
+pub mod stocktake;
@@ -5,6 +5,8 @@ pub struct GlobalSettingsModel {
     pub id: i32,
     pub units: Vec<String>,
     pub accounts: Vec<String>,
+    pub negative_stock_policy: i32, // 库存不够时: 0不允许 1允许但提示 2只允许指定的账号
+    pub negative_stock_account_ids: Vec<i32>, // 可以出库到负数的账号
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
@@ -21,3 +23,11 @@ pub struct CustomerTypeModel {
     pub ty_pe: String,
     pub create_time: DateTime<Utc>, // 父类ID
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, sqlx::FromRow)]
+pub struct WarehouseModel {
+    pub id: i32,
+    pub name: String,  // 仓库名: 门店, 后仓, 车间...
+    pub notes: String, // 备注
+    pub create_time: DateTime<Utc>,
+}
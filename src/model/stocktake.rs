@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct StocktakeModel {
+    pub id: i32,
+    pub warehouse_id: i32, // 盘点的仓库
+    pub account_id: i32,   // 发起的账号
+    pub status: i32,       // 0 盘点中, 1 已过账, 2 已取消
+    pub notes: String,
+    pub bucket_id: i32, // 过账生成的出入库记录, 没有差异时为0
+    pub create_time: DateTime<Utc>,
+    pub close_time: Option<DateTime<Utc>>, // 过账/取消的时间
+}
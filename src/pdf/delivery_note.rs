@@ -0,0 +1,414 @@
+use crate::common::items::image_url_to_path;
+use crate::common::money::money_to_chinese_uppercase;
+use crate::config::parameter;
+use crate::constants::STORAGE_FILE_PATH;
+use crate::dto::dto_orders::{OrderDetailDto, OrderItemDto};
+use crate::{ERPError, ERPResult};
+use printpdf::image_crate::{self, DynamicImage};
+use printpdf::{
+    Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point,
+};
+use std::collections::HashMap;
+use std::fs;
+
+/// 1pt = 0.3528mm, 估算文字宽度用
+const PT_TO_MM: f32 = 0.3528;
+/// 缩略图最大像素, 避免原图太大导致pdf过大
+const THUMBNAIL_PX: u32 = 160;
+
+/// 表格的列
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryNoteField {
+    Index,
+    Image,
+    Number,
+    Name,
+    Size,
+    Color,
+    Count,
+    OriginPrice,
+    Price,
+    Discount,
+    Total,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeliveryNoteColumn {
+    pub field: DeliveryNoteField,
+    pub title: String,
+    pub width_mm: f32,
+}
+
+/// 出库单模版, 通过 DELIVERY_NOTE_TEMPLATE 指定json文件, 没有配置的字段使用默认值
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeliveryNoteTemplate {
+    pub company: String,
+    pub title: String,
+    /// 需要包含中文字形的ttf字体文件
+    pub font_path: String,
+    pub font_size: f32,
+    pub page_width_mm: f32,
+    pub page_height_mm: f32,
+    pub margin_mm: f32,
+    pub header_height_mm: f32,
+    pub row_height_mm: f32,
+    pub columns: Vec<DeliveryNoteColumn>,
+    /// 表格下方的签字栏等
+    pub footer: Vec<String>,
+}
+
+impl Default for DeliveryNoteTemplate {
+    fn default() -> Self {
+        let column = |field, title: &str, width_mm| DeliveryNoteColumn {
+            field,
+            title: title.to_string(),
+            width_mm,
+        };
+        Self {
+            company: "".to_string(),
+            title: "出库单".to_string(),
+            font_path: format!("{}/fonts/delivery_note.ttf", STORAGE_FILE_PATH),
+            font_size: 9.0,
+            page_width_mm: 210.0,
+            page_height_mm: 297.0,
+            margin_mm: 10.0,
+            header_height_mm: 8.0,
+            row_height_mm: 16.0,
+            columns: vec![
+                column(DeliveryNoteField::Index, "序号", 10.0),
+                column(DeliveryNoteField::Image, "图片", 18.0),
+                column(DeliveryNoteField::Number, "编号", 22.0),
+                column(DeliveryNoteField::Name, "名称", 30.0),
+                column(DeliveryNoteField::Size, "规格", 18.0),
+                column(DeliveryNoteField::Color, "颜色", 16.0),
+                column(DeliveryNoteField::Count, "数量", 14.0),
+                column(DeliveryNoteField::Price, "单价", 18.0),
+                column(DeliveryNoteField::Discount, "折扣", 14.0),
+                column(DeliveryNoteField::Total, "金额", 30.0),
+            ],
+            footer: vec![
+                "送货人签字:                    收货人签字:".to_string(),
+                "收货日期:".to_string(),
+            ],
+        }
+    }
+}
+
+impl DeliveryNoteTemplate {
+    pub fn load() -> ERPResult<Self> {
+        let path = parameter::get_or("DELIVERY_NOTE_TEMPLATE", "");
+        if path.is_empty() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| ERPError::Failed(format!("出库单模版{}有误: {}", path, e)))
+    }
+}
+
+/// 数量存的是实际数量*10
+fn format_count(count: i32) -> String {
+    if count % 10 == 0 {
+        format!("{}", count / 10)
+    } else {
+        format!("{:.1}", count as f32 / 10.0)
+    }
+}
+
+/// 金额存的是分
+fn format_money(cents: i64) -> String {
+    format!("{:.2}", cents as f64 / 100.0)
+}
+
+/// 估算文字宽度(mm): 中文按字号宽, 英文数字按一半
+fn text_width_mm(text: &str, font_size: f32) -> f32 {
+    text.chars()
+        .map(|c| if c.is_ascii() { 0.5 } else { 1.0 })
+        .sum::<f32>()
+        * font_size
+        * PT_TO_MM
+}
+
+/// 超出宽度的文字截断
+fn fit_text(text: &str, width_mm: f32, font_size: f32) -> String {
+    let mut res = String::new();
+    for c in text.chars() {
+        res.push(c);
+        if text_width_mm(&res, font_size) > width_mm {
+            res.pop();
+            break;
+        }
+    }
+    res
+}
+
+/// 只读本地文件, 不走网络
+fn load_thumbnail(url: &str) -> Option<DynamicImage> {
+    let path = image_url_to_path(url);
+    match image_crate::open(&path) {
+        Ok(image) if image.width() > THUMBNAIL_PX || image.height() > THUMBNAIL_PX => Some(
+            DynamicImage::ImageRgb8(image.thumbnail(THUMBNAIL_PX, THUMBNAIL_PX).to_rgb8()),
+        ),
+        Ok(image) => Some(DynamicImage::ImageRgb8(image.to_rgb8())),
+        Err(e) => {
+            tracing::warn!("load image {} failed: {:?}", path, e);
+            None
+        }
+    }
+}
+
+fn cell_text(item: &OrderItemDto, field: DeliveryNoteField, index: usize) -> String {
+    match field {
+        DeliveryNoteField::Index => format!("{}", index + 1),
+        DeliveryNoteField::Image => "".to_string(),
+        DeliveryNoteField::Number => item.number.clone(),
+        DeliveryNoteField::Name => item.name.clone(),
+        DeliveryNoteField::Size => item.size.clone(),
+        DeliveryNoteField::Color => item.color.clone(),
+        DeliveryNoteField::Count => format_count(item.count),
+        DeliveryNoteField::OriginPrice => format_money(item.origin_price as i64),
+        DeliveryNoteField::Price => format_money(item.price as i64),
+        DeliveryNoteField::Discount => format!("{}%", item.discount),
+        DeliveryNoteField::Total => format_money(item.total_price as i64),
+    }
+}
+
+struct Writer<'a> {
+    template: &'a DeliveryNoteTemplate,
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    /// 当前写到的位置(距离页面底部)
+    y: f32,
+}
+
+impl<'a> Writer<'a> {
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(
+            Mm(self.template.page_width_mm),
+            Mm(self.template.page_height_mm),
+            "layer",
+        );
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = self.template.page_height_mm - self.template.margin_mm;
+    }
+
+    fn text(&self, text: &str, font_size: f32, x: f32, y: f32) {
+        self.layer
+            .use_text(text, font_size, Mm(x), Mm(y), &self.font);
+    }
+
+    fn line(&self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(x1), Mm(y1)), false),
+                (Point::new(Mm(x2), Mm(y2)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    /// 一行表格: 上下边框, 每列的左边框和文字
+    fn row(&self, height: f32, cells: &[String]) {
+        let template = self.template;
+        let left = template.margin_mm;
+        let right = left + template.columns.iter().map(|c| c.width_mm).sum::<f32>();
+        let bottom = self.y - height;
+        self.line(left, self.y, right, self.y);
+        self.line(left, bottom, right, bottom);
+
+        let mut x = left;
+        let text_y = bottom + (height - template.font_size * PT_TO_MM) / 2.0;
+        for (column, cell) in template.columns.iter().zip(cells) {
+            self.line(x, self.y, x, bottom);
+            let text = fit_text(cell, column.width_mm - 2.0, template.font_size);
+            self.text(&text, template.font_size, x + 1.0, text_y);
+            x += column.width_mm;
+        }
+        self.line(right, self.y, right, bottom);
+    }
+
+    fn header_row(&mut self) {
+        let titles = self
+            .template
+            .columns
+            .iter()
+            .map(|column| column.title.clone())
+            .collect::<Vec<_>>();
+        self.row(self.template.header_height_mm, &titles);
+        self.y -= self.template.header_height_mm;
+    }
+
+    fn image(&self, image: &DynamicImage, x: f32, y: f32, width: f32, height: f32) {
+        // 按dpi缩放到格子里
+        let dpi =
+            (image.width() as f32 / (width / 25.4)).max(image.height() as f32 / (height / 25.4));
+        let image_width = image.width() as f32 / dpi * 25.4;
+        let image_height = image.height() as f32 / dpi * 25.4;
+        Image::from_dynamic_image(image).add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(Mm(x + (width - image_width) / 2.0)),
+                translate_y: Some(Mm(y + (height - image_height) / 2.0)),
+                dpi: Some(dpi),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// 把订单详情生成出库单pdf
+pub fn render_delivery_note(
+    template: &DeliveryNoteTemplate,
+    detail: &OrderDetailDto,
+) -> ERPResult<Vec<u8>> {
+    let font_data = fs::read(&template.font_path)
+        .map_err(|e| ERPError::Failed(format!("读取字体文件{}失败: {}", template.font_path, e)))?;
+
+    let order = &detail.order;
+    let (doc, page, layer) = PdfDocument::new(
+        format!("{}{}", template.title, order.order_no),
+        Mm(template.page_width_mm),
+        Mm(template.page_height_mm),
+        "layer",
+    );
+    let font = doc
+        .add_external_font(font_data.as_slice())
+        .map_err(|e| ERPError::Failed(format!("加载字体失败: {:?}", e)))?;
+    let layer = doc.get_page(page).get_layer(layer);
+
+    let mut writer = Writer {
+        template,
+        doc,
+        layer,
+        font,
+        y: template.page_height_mm - template.margin_mm,
+    };
+    let font_size = template.font_size;
+    let line_height = font_size * PT_TO_MM * 1.8;
+    let left = template.margin_mm;
+
+    // 标题
+    if !template.company.is_empty() {
+        let size = font_size + 6.0;
+        let x = (template.page_width_mm - text_width_mm(&template.company, size)) / 2.0;
+        writer.y -= size * PT_TO_MM;
+        writer.text(&template.company, size, x, writer.y);
+        writer.y -= line_height;
+    }
+    let size = font_size + 4.0;
+    let x = (template.page_width_mm - text_width_mm(&template.title, size)) / 2.0;
+    writer.y -= size * PT_TO_MM;
+    writer.text(&template.title, size, x, writer.y);
+    writer.y -= line_height * 1.5;
+
+    // 订单信息
+    let half = (template.page_width_mm - template.margin_mm * 2.0) / 2.0;
+    writer.text(
+        &format!("客户: {}", order.customer),
+        font_size,
+        left,
+        writer.y,
+    );
+    writer.text(
+        &format!("订单号: {}", order.order_no),
+        font_size,
+        left + half,
+        writer.y,
+    );
+    writer.y -= line_height;
+    writer.text(
+        &format!("订单日期: {}", order.order_date.format("%Y-%m-%d")),
+        font_size,
+        left,
+        writer.y,
+    );
+    writer.text(
+        &format!("交货日期: {}", order.delivery_date.format("%Y-%m-%d")),
+        font_size,
+        left + half,
+        writer.y,
+    );
+    writer.y -= line_height / 2.0;
+
+    // 商品表格, 一页放不下时换页并重新画表头
+    let mut thumbnails: HashMap<&str, Option<DynamicImage>> = HashMap::new();
+    writer.header_row();
+    for (index, item) in detail.items.iter().enumerate() {
+        if writer.y - template.row_height_mm < template.margin_mm {
+            writer.new_page();
+            writer.header_row();
+        }
+        let cells = template
+            .columns
+            .iter()
+            .map(|column| cell_text(item, column.field, index))
+            .collect::<Vec<_>>();
+        writer.row(template.row_height_mm, &cells);
+
+        let mut x = left;
+        for column in template.columns.iter() {
+            if column.field == DeliveryNoteField::Image {
+                let image = item.images.first().and_then(|url| {
+                    thumbnails
+                        .entry(url.as_str())
+                        .or_insert_with(|| load_thumbnail(url))
+                        .as_ref()
+                });
+                if let Some(image) = image {
+                    writer.image(
+                        image,
+                        x + 1.0,
+                        writer.y - template.row_height_mm + 1.0,
+                        column.width_mm - 2.0,
+                        template.row_height_mm - 2.0,
+                    );
+                }
+            }
+            x += column.width_mm;
+        }
+        writer.y -= template.row_height_mm;
+    }
+
+    // 合计和签字栏
+    let footer_height = line_height * (3 + template.footer.len()) as f32;
+    if writer.y - footer_height < template.margin_mm {
+        writer.new_page();
+    }
+    let total_count = detail.items.iter().map(|item| item.count).sum::<i32>();
+    let total = detail
+        .items
+        .iter()
+        .map(|item| item.total_price as i64)
+        .sum::<i64>();
+    writer.y -= line_height;
+    writer.text(
+        &format!(
+            "合计数量: {}    合计金额: {}",
+            format_count(total_count),
+            format_money(total)
+        ),
+        font_size,
+        left,
+        writer.y,
+    );
+    writer.y -= line_height;
+    writer.text(
+        &format!("金额大写: {}", money_to_chinese_uppercase(total)),
+        font_size,
+        left,
+        writer.y,
+    );
+    for footer in template.footer.iter() {
+        writer.y -= line_height * 1.5;
+        writer.text(footer, font_size, left, writer.y);
+    }
+
+    writer
+        .doc
+        .save_to_bytes()
+        .map_err(|e| ERPError::Failed(format!("生成pdf失败: {:?}", e)))
+}
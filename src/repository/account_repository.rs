@@ -1,5 +1,7 @@
 use crate::config::database::{Database, DatabaseTrait};
 use crate::model::account::AccountModel;
+use crate::ERPResult;
+use sqlx::PgConnection;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -22,4 +24,32 @@ impl AccountRepo {
         .await
         .unwrap_or(None)
     }
+
+    pub async fn find_user_by_id(&self, account_id: i32) -> Option<AccountModel> {
+        sqlx::query_as!(
+            AccountModel,
+            "select * from accounts where id = $1",
+            account_id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await
+        .unwrap_or(None)
+    }
+
+    pub async fn update_password(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        password: &str,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            "update accounts set password = $1 where id = $2",
+            password,
+            account_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
 }
@@ -1,15 +1,16 @@
 use crate::config::database::{Database, DatabaseTrait};
 use crate::dto::dto_embryo::{EmbryoDto, EmbryoInOutDto};
 use crate::model::embryo::EmbryoModel;
+use crate::service::stock_service::{StockService, StockServiceTrait};
 use crate::ERPResult;
 use async_trait::async_trait;
 use sqlx;
-use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct EmbryoRepository {
     pub(crate) db: Arc<Database>,
+    stock_service: StockService,
 }
 
 #[async_trait]
@@ -35,33 +36,26 @@ impl EmbryoRepositoryTrait for EmbryoRepository {
     fn new(db_conn: &Arc<Database>) -> Self {
         Self {
             db: Arc::clone(db_conn),
+            stock_service: StockService::new(db_conn),
         }
     }
 
     async fn embryos_to_embryo_dtos(&self, embryos: Vec<EmbryoModel>) -> ERPResult<Vec<EmbryoDto>> {
         let embryo_ids = embryos.iter().map(|item| item.id).collect::<Vec<_>>();
 
-        let embryo_id_to_count = sqlx::query!(
-            r#"
-            select embryo_id, sum(count) 
-            from embryo_inout 
-            where embryo_id = any($1)
-            group by embryo_id
-            "#,
-            &embryo_ids
-        )
-        .fetch_all(self.db.get_pool())
-        .await?
-        .into_iter()
-        .map(|r| (r.embryo_id, r.sum.unwrap_or(0) as i32))
-        .collect::<HashMap<_, _>>();
+        let embryo_id_to_count = self.stock_service.get_embryo_stock(&embryo_ids).await?;
+        let mut embryo_id_to_stocks = self
+            .stock_service
+            .get_embryo_warehouse_stock(&embryo_ids)
+            .await?;
 
         let embryo_dtos = embryos
             .into_iter()
             .map(|item| {
                 let count = embryo_id_to_count.get(&item.id).unwrap_or(&0);
+                let stocks = embryo_id_to_stocks.remove(&item.id).unwrap_or_default();
 
-                EmbryoDto::from(item, *count)
+                EmbryoDto::from(item, *count, stocks)
             })
             .collect::<Vec<_>>();
 
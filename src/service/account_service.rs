@@ -1,24 +1,98 @@
+use crate::common::password::{hash_password, verify_password};
 use crate::config::database::{Database, DatabaseTrait};
-use crate::model::account::AccountModel;
-use crate::ERPResult;
+use crate::constants::{
+    AUDIT_CREATE, AUDIT_UPDATE, DEFAULT_PAGE_SIZE, LOGIN_FAILURE_WINDOW_MINUTES, ROLE_TO_DEPARTMENT,
+};
+use crate::dto::dto_account::{
+    AccountDto, ChangePasswordParams, CreateAccountParams, DisableAccountParams,
+    LoginAttemptQueryParams, QueryParams, ResetPasswordParams, UnlockAccountParams,
+    UpdateRoleParams,
+};
+use crate::model::account::{AccountModel, LoginAttemptModel};
+use crate::repository::account_repository::AccountRepo;
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
+use sqlx::{Postgres, QueryBuilder};
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AccountService {
     pub db: Arc<Database>,
+    account_repo: AccountRepo,
+    audit_service: AuditService,
 }
 
 #[async_trait]
 pub trait AccountServiceTrait {
     fn new(db: &Arc<Database>) -> Self;
     async fn get_accounts(&self, account_ids: &[i32]) -> ERPResult<Vec<AccountModel>>;
+    async fn get_account(&self, account_id: i32) -> ERPResult<AccountModel>;
+    async fn get_account_list(&self, params: &QueryParams) -> ERPResult<Vec<AccountDto>>;
+    async fn get_account_count(&self, params: &QueryParams) -> ERPResult<i32>;
+    async fn create_account(
+        &self,
+        params: &CreateAccountParams,
+        operator_id: i32,
+    ) -> ERPResult<i32>;
+    async fn change_password(
+        &self,
+        account_id: i32,
+        params: &ChangePasswordParams,
+    ) -> ERPResult<()>;
+    async fn reset_password(&self, params: &ResetPasswordParams, operator_id: i32)
+        -> ERPResult<()>;
+    async fn disable_account(
+        &self,
+        params: &DisableAccountParams,
+        operator_id: i32,
+    ) -> ERPResult<()>;
+    async fn update_role(&self, params: &UpdateRoleParams, operator_id: i32) -> ERPResult<()>;
+
+    async fn add_login_attempt(
+        &self,
+        account: &str,
+        account_id: Option<i32>,
+        ip: &str,
+        success: bool,
+    ) -> ERPResult<()>;
+    async fn get_recent_login_failure_count_of_account(&self, account_id: i32) -> ERPResult<i64>;
+    async fn get_recent_login_failure_count_of_ip(&self, ip: &str) -> ERPResult<i64>;
+    async fn lock_account(&self, account_id: i32) -> ERPResult<()>;
+    async fn unlock_account(&self, params: &UnlockAccountParams, operator_id: i32)
+        -> ERPResult<()>;
+    async fn get_login_attempt_list(
+        &self,
+        params: &LoginAttemptQueryParams,
+    ) -> ERPResult<Vec<LoginAttemptModel>>;
+    async fn get_login_attempt_count(&self, params: &LoginAttemptQueryParams) -> ERPResult<i32>;
+}
+
+fn push_login_attempt_filters(sql: &mut QueryBuilder<Postgres>, params: &LoginAttemptQueryParams) {
+    sql.push(" where 1 = 1 ");
+    if let Some(account) = params
+        .account
+        .as_ref()
+        .filter(|account| !account.is_empty())
+    {
+        sql.push(" and account = ").push_bind(account.to_string());
+    }
+    if let Some(ip) = params.ip.as_ref().filter(|ip| !ip.is_empty()) {
+        sql.push(" and ip = ").push_bind(ip.to_string());
+    }
+    if let Some(success) = params.success {
+        sql.push(" and success = ").push_bind(success);
+    }
 }
 
 #[async_trait]
 impl AccountServiceTrait for AccountService {
     fn new(db: &Arc<Database>) -> Self {
-        Self { db: Arc::clone(db) }
+        Self {
+            db: Arc::clone(db),
+            account_repo: AccountRepo::new(db),
+            audit_service: AuditService::new(db),
+        }
     }
 
     async fn get_accounts(&self, account_ids: &[i32]) -> ERPResult<Vec<AccountModel>> {
@@ -32,4 +106,408 @@ impl AccountServiceTrait for AccountService {
 
         Ok(accounts)
     }
+
+    async fn get_account(&self, account_id: i32) -> ERPResult<AccountModel> {
+        sqlx::query_as!(
+            AccountModel,
+            "select * from accounts where id = $1",
+            account_id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?
+        .ok_or(ERPError::AccountNotFound)
+    }
+
+    async fn get_account_list(&self, params: &QueryParams) -> ERPResult<Vec<AccountDto>> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select * from accounts ");
+        if let Some(name) = params.name.as_ref().filter(|name| !name.is_empty()) {
+            sql.push(" where name like ")
+                .push_bind(format!("%{}%", name))
+                .push(" or account like ")
+                .push_bind(format!("%{}%", name));
+        }
+
+        let page = params.page.unwrap_or(1);
+        let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let offset = (page - 1) * page_size;
+        sql.push(format!(
+            " order by id desc limit {page_size} offset {offset}"
+        ));
+
+        let accounts = sql
+            .build_query_as::<AccountModel>()
+            .fetch_all(self.db.get_pool())
+            .await?
+            .into_iter()
+            .map(AccountDto::from)
+            .collect::<Vec<_>>();
+
+        Ok(accounts)
+    }
+
+    async fn get_account_count(&self, params: &QueryParams) -> ERPResult<i32> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select count(1) from accounts ");
+        if let Some(name) = params.name.as_ref().filter(|name| !name.is_empty()) {
+            sql.push(" where name like ")
+                .push_bind(format!("%{}%", name))
+                .push(" or account like ")
+                .push_bind(format!("%{}%", name));
+        }
+
+        let count = sql
+            .build_query_as::<(i64,)>()
+            .fetch_one(self.db.get_pool())
+            .await?
+            .0 as i32;
+
+        Ok(count)
+    }
+
+    async fn create_account(
+        &self,
+        params: &CreateAccountParams,
+        operator_id: i32,
+    ) -> ERPResult<i32> {
+        if params.account.is_empty() || params.password.is_empty() {
+            return Err(ERPError::ParamNeeded("账号和密码不能为空".to_string()));
+        }
+        if !ROLE_TO_DEPARTMENT.contains_key(params.role.as_str()) {
+            return Err(ERPError::ParamError(format!("角色{}不存在", params.role)));
+        }
+
+        let existing = sqlx::query!(
+            "select count(1) from accounts where account = $1",
+            params.account
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0);
+        if existing > 0 {
+            return Err(ERPError::AlreadyExists(format!(
+                "账号{}已存在",
+                params.account
+            )));
+        }
+
+        let hashed = hash_password(&params.password)?;
+        let mut tx = self.db.get_pool().begin().await?;
+        let account = sqlx::query_as!(
+            AccountModel,
+            r#"
+            insert into accounts (name, account, password, role)
+            values ($1, $2, $3, $4)
+            returning *
+            "#,
+            params.name,
+            params.account,
+            hashed,
+            params.role
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let account_id = account.id;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                operator_id,
+                "accounts",
+                account_id,
+                AUDIT_CREATE,
+                None,
+                snapshot(&AccountDto::from(account)),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(account_id)
+    }
+
+    async fn change_password(
+        &self,
+        account_id: i32,
+        params: &ChangePasswordParams,
+    ) -> ERPResult<()> {
+        if params.new_password.is_empty() {
+            return Err(ERPError::ParamNeeded("新密码不能为空".to_string()));
+        }
+
+        let account = self.get_account(account_id).await?;
+        if !verify_password(&params.old_password, &account.password) {
+            return Err(ERPError::LoginFailForPasswordIsWrong);
+        }
+
+        let hashed = hash_password(&params.new_password)?;
+        let mut tx = self.db.get_pool().begin().await?;
+        self.account_repo
+            .update_password(&mut tx, account_id, &hashed)
+            .await?;
+
+        // 不记录密码
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "accounts",
+                account_id,
+                "change_password",
+                None,
+                None,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn reset_password(
+        &self,
+        params: &ResetPasswordParams,
+        operator_id: i32,
+    ) -> ERPResult<()> {
+        if params.password.is_empty() {
+            return Err(ERPError::ParamNeeded("新密码不能为空".to_string()));
+        }
+
+        let account = self.get_account(params.id).await?;
+        let hashed = hash_password(&params.password)?;
+        let mut tx = self.db.get_pool().begin().await?;
+        self.account_repo
+            .update_password(&mut tx, account.id, &hashed)
+            .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                operator_id,
+                "accounts",
+                account.id,
+                "reset_password",
+                None,
+                None,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn disable_account(
+        &self,
+        params: &DisableAccountParams,
+        operator_id: i32,
+    ) -> ERPResult<()> {
+        let account = self.get_account(params.id).await?;
+        let mut tx = self.db.get_pool().begin().await?;
+        let updated = sqlx::query_as!(
+            AccountModel,
+            "update accounts set disabled = $1 where id = $2 returning *",
+            params.disabled,
+            account.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                operator_id,
+                "accounts",
+                account.id,
+                AUDIT_UPDATE,
+                snapshot(&AccountDto::from(account)),
+                snapshot(&AccountDto::from(updated)),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_role(&self, params: &UpdateRoleParams, operator_id: i32) -> ERPResult<()> {
+        if !ROLE_TO_DEPARTMENT.contains_key(params.role.as_str()) {
+            return Err(ERPError::ParamError(format!("角色{}不存在", params.role)));
+        }
+
+        let account = self.get_account(params.id).await?;
+        let mut tx = self.db.get_pool().begin().await?;
+        let updated = sqlx::query_as!(
+            AccountModel,
+            "update accounts set role = $1 where id = $2 returning *",
+            params.role,
+            account.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                operator_id,
+                "accounts",
+                account.id,
+                AUDIT_UPDATE,
+                snapshot(&AccountDto::from(account)),
+                snapshot(&AccountDto::from(updated)),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn add_login_attempt(
+        &self,
+        account: &str,
+        account_id: Option<i32>,
+        ip: &str,
+        success: bool,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            "insert into login_attempts (account, account_id, ip, success) values ($1, $2, $3, $4)",
+            account,
+            account_id,
+            ip,
+            success
+        )
+        .execute(self.db.get_pool())
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_recent_login_failure_count_of_account(&self, account_id: i32) -> ERPResult<i64> {
+        // 只统计时间窗口内、最近一次登录成功和管理员解锁之后的失败次数
+        let count = sqlx::query!(
+            r#"
+            select count(1)
+            from login_attempts la, accounts a
+            where la.account_id = a.id
+              and a.id = $1
+              and not la.success
+              and la.create_time > greatest(
+                now() - make_interval(mins => $2),
+                a.unlocked_at,
+                (select max(create_time) from login_attempts where account_id = $1 and success)
+              )
+            "#,
+            account_id,
+            LOGIN_FAILURE_WINDOW_MINUTES
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0);
+
+        Ok(count)
+    }
+
+    async fn get_recent_login_failure_count_of_ip(&self, ip: &str) -> ERPResult<i64> {
+        let count = sqlx::query!(
+            r#"
+            select count(1)
+            from login_attempts
+            where ip = $1
+              and not success
+              and create_time > now() - make_interval(mins => $2)
+            "#,
+            ip,
+            LOGIN_FAILURE_WINDOW_MINUTES
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0);
+
+        Ok(count)
+    }
+
+    async fn lock_account(&self, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let locked = sqlx::query!(
+            "update accounts set locked_at = now() where id = $1 and locked_at is null",
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        // 登录失败自动锁定, 操作人记为0
+        if locked > 0 {
+            self.audit_service
+                .add_audit_log(&mut tx, 0, "accounts", account_id, "lock", None, None)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn unlock_account(
+        &self,
+        params: &UnlockAccountParams,
+        operator_id: i32,
+    ) -> ERPResult<()> {
+        let account = self.get_account(params.id).await?;
+        let mut tx = self.db.get_pool().begin().await?;
+        let updated = sqlx::query_as!(
+            AccountModel,
+            "update accounts set locked_at = null, unlocked_at = now() where id = $1 returning *",
+            account.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                operator_id,
+                "accounts",
+                account.id,
+                "unlock",
+                snapshot(&AccountDto::from(account)),
+                snapshot(&AccountDto::from(updated)),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_login_attempt_list(
+        &self,
+        params: &LoginAttemptQueryParams,
+    ) -> ERPResult<Vec<LoginAttemptModel>> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select * from login_attempts ");
+        push_login_attempt_filters(&mut sql, params);
+
+        let page = params.page.unwrap_or(1);
+        let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let offset = (page - 1) * page_size;
+        sql.push(format!(
+            " order by id desc limit {page_size} offset {offset}"
+        ));
+
+        let attempts = sql
+            .build_query_as::<LoginAttemptModel>()
+            .fetch_all(self.db.get_pool())
+            .await?;
+
+        Ok(attempts)
+    }
+
+    async fn get_login_attempt_count(&self, params: &LoginAttemptQueryParams) -> ERPResult<i32> {
+        let mut sql: QueryBuilder<Postgres> =
+            QueryBuilder::new("select count(1) from login_attempts ");
+        push_login_attempt_filters(&mut sql, params);
+
+        let count = sql
+            .build_query_as::<(i64,)>()
+            .fetch_one(self.db.get_pool())
+            .await?
+            .0 as i32;
+
+        Ok(count)
+    }
 }
@@ -0,0 +1,190 @@
+use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::dto::dto_audit::{AuditLogDto, QueryParams};
+use crate::model::audit::AuditLogModel;
+use crate::ERPResult;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AuditService {
+    pub db: Arc<Database>,
+}
+
+/// 把数据转成操作日志里保存的json
+pub fn snapshot<T: Serialize>(data: &T) -> Option<Value> {
+    serde_json::to_value(data).ok()
+}
+
+#[async_trait]
+pub trait AuditServiceTrait {
+    fn new(db: &Arc<Database>) -> Self;
+    /// 和要记录的修改在同一个事务里写, 提交前调用
+    #[allow(clippy::too_many_arguments)]
+    async fn add_audit_log(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        entity: &str,
+        entity_id: i32,
+        action: &str,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> ERPResult<()>;
+    /// 批量修改时一次写多条, rows: (实体id, 修改前, 修改后)
+    async fn add_audit_logs(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        entity: &str,
+        action: &str,
+        rows: &[(i32, Option<Value>, Option<Value>)],
+    ) -> ERPResult<()>;
+    async fn get_audit_log_list(&self, params: &QueryParams) -> ERPResult<Vec<AuditLogDto>>;
+    async fn get_audit_log_count(&self, params: &QueryParams) -> ERPResult<i32>;
+}
+
+fn push_filters(sql: &mut QueryBuilder<Postgres>, params: &QueryParams) {
+    sql.push(" where 1 = 1 ");
+    if let Some(account_id) = params.account_id.filter(|id| *id != 0) {
+        sql.push(" and account_id = ").push_bind(account_id);
+    }
+    if let Some(entity) = params.entity.as_ref().filter(|entity| !entity.is_empty()) {
+        sql.push(" and entity = ").push_bind(entity.to_string());
+    }
+    if let Some(entity_id) = params.entity_id.filter(|id| *id != 0) {
+        sql.push(" and entity_id = ").push_bind(entity_id);
+    }
+    if let Some(st) = params.create_time_st.as_ref().filter(|st| !st.is_empty()) {
+        sql.push(" and create_time >= ")
+            .push_bind(st.to_string())
+            .push("::timestamptz");
+    }
+    if let Some(ed) = params.create_time_ed.as_ref().filter(|ed| !ed.is_empty()) {
+        sql.push(" and create_time <= ")
+            .push_bind(ed.to_string())
+            .push("::timestamptz");
+    }
+}
+
+#[async_trait]
+impl AuditServiceTrait for AuditService {
+    fn new(db: &Arc<Database>) -> Self {
+        Self { db: Arc::clone(db) }
+    }
+
+    async fn add_audit_log(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        entity: &str,
+        entity_id: i32,
+        action: &str,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            r#"
+            insert into audit_log (account_id, entity, entity_id, action, before, after)
+            values ($1, $2, $3, $4, $5, $6)
+            "#,
+            account_id,
+            entity,
+            entity_id,
+            action,
+            before,
+            after
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_audit_logs(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        entity: &str,
+        action: &str,
+        rows: &[(i32, Option<Value>, Option<Value>)],
+    ) -> ERPResult<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new(
+            "insert into audit_log (account_id, entity, entity_id, action, before, after) ",
+        );
+        sql.push_values(rows, |mut b, (entity_id, before, after)| {
+            b.push_bind(account_id)
+                .push_bind(entity)
+                .push_bind(entity_id)
+                .push_bind(action)
+                .push_bind(before)
+                .push_bind(after);
+        });
+        sql.build().execute(conn).await?;
+
+        Ok(())
+    }
+
+    async fn get_audit_log_list(&self, params: &QueryParams) -> ERPResult<Vec<AuditLogDto>> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select * from audit_log ");
+        push_filters(&mut sql, params);
+
+        let page = params.page.unwrap_or(1);
+        let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let offset = (page - 1) * page_size;
+        sql.push(format!(
+            " order by id desc limit {page_size} offset {offset}"
+        ));
+
+        let audit_logs = sql
+            .build_query_as::<AuditLogModel>()
+            .fetch_all(self.db.get_pool())
+            .await?;
+
+        let account_ids = audit_logs
+            .iter()
+            .map(|item| item.account_id)
+            .collect::<Vec<_>>();
+        let id_to_name = sqlx::query!(
+            "select id, name from accounts where id = any($1)",
+            &account_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect::<HashMap<i32, String>>();
+
+        let empty = "".to_string();
+        let dtos = audit_logs
+            .into_iter()
+            .map(|item| {
+                let name = id_to_name.get(&item.account_id).unwrap_or(&empty).clone();
+                AuditLogDto::from(item, &name)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(dtos)
+    }
+
+    async fn get_audit_log_count(&self, params: &QueryParams) -> ERPResult<i32> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select count(1) from audit_log ");
+        push_filters(&mut sql, params);
+
+        let count = sql
+            .build_query_as::<(i64,)>()
+            .fetch_one(self.db.get_pool())
+            .await?
+            .0 as i32;
+
+        Ok(count)
+    }
+}
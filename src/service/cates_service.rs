@@ -1,16 +1,19 @@
 use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE};
 use crate::dto::dto_cates::{CateDto, EditParams};
 use crate::dto::GenericDeleteParams;
 use crate::model::cates::CateModel;
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
 use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
-use sqlx::{Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct CateService {
     pub db: Arc<Database>,
+    audit_service: AuditService,
 }
 
 #[async_trait]
@@ -20,21 +23,32 @@ pub trait CateServiceTrait {
 
     async fn get_sub_cates_of(&self, parent_id: i32) -> ERPResult<Vec<CateDto>>;
 
-    async fn edit_cates(&self, params: &EditParams) -> ERPResult<()>;
+    async fn edit_cates(&self, params: &EditParams, account_id: i32) -> ERPResult<()>;
 
     async fn extract_cates(&self) -> ERPResult<()>;
 
-    async fn delete_cate(&self, params: &GenericDeleteParams) -> ERPResult<()>;
+    async fn delete_cate(&self, params: &GenericDeleteParams, account_id: i32) -> ERPResult<()>;
 
-    async fn insert_multiple_cate1(&self, names: &[&str]) -> ERPResult<HashMap<String, i32>>;
+    async fn insert_multiple_cate1(
+        &self,
+        conn: &mut PgConnection,
+        names: &[&str],
+    ) -> ERPResult<HashMap<String, i32>>;
 
-    async fn insert_multiple_cate2(&self, items: &[CateModel]) -> ERPResult<Vec<CateModel>>;
+    async fn insert_multiple_cate2(
+        &self,
+        conn: &mut PgConnection,
+        items: &[CateModel],
+    ) -> ERPResult<Vec<CateModel>>;
 }
 
 #[async_trait]
 impl CateServiceTrait for CateService {
     fn new(db: &Arc<Database>) -> Self {
-        Self { db: Arc::clone(db) }
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+        }
     }
 
     async fn get_all_cates(&self) -> ERPResult<Vec<CateDto>> {
@@ -86,7 +100,7 @@ impl CateServiceTrait for CateService {
         Ok(cates)
     }
 
-    async fn edit_cates(&self, params: &EditParams) -> ERPResult<()> {
+    async fn edit_cates(&self, params: &EditParams, account_id: i32) -> ERPResult<()> {
         let cates = self.get_all_cates().await?;
 
         // checking collision
@@ -148,18 +162,34 @@ impl CateServiceTrait for CateService {
         match params.id {
             0 => {
                 // 新增item
-                sqlx::query!(
+                let mut tx = self.db.get_pool().begin().await?;
+                let cate = sqlx::query_as!(
+                    CateModel,
                     r#"
                     insert into cates (index, name, cate_type, parent_id)
-                    values ($1, $2, $3, $4);
+                    values ($1, $2, $3, $4)
+                    returning *;
                     "#,
                     params.index,
                     params.name,
                     params.cate_type,
                     params.parent_id,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "cates",
+                        cate.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&cate),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 // 修改item
@@ -174,10 +204,13 @@ impl CateServiceTrait for CateService {
                     }
                 }
 
-                sqlx::query!(
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    CateModel,
                     r#"
                     update cates set index=$1, name=$2, cate_type=$3, parent_id=$4
                     where id=$5
+                    returning *
                     "#,
                     params.index,
                     params.name,
@@ -185,8 +218,21 @@ impl CateServiceTrait for CateService {
                     params.parent_id,
                     params.id,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "cates",
+                        cate.id,
+                        AUDIT_UPDATE,
+                        snapshot(&cate),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         };
 
@@ -197,7 +243,7 @@ impl CateServiceTrait for CateService {
         todo!()
     }
 
-    async fn delete_cate(&self, params: &GenericDeleteParams) -> ERPResult<()> {
+    async fn delete_cate(&self, params: &GenericDeleteParams, account_id: i32) -> ERPResult<()> {
         let cate = sqlx::query_as!(CateModel, "select * from cates where id = $1", params.id)
             .fetch_one(self.db.get_pool())
             .await?;
@@ -246,14 +292,32 @@ impl CateServiceTrait for CateService {
             }
         }
 
+        let mut tx = self.db.get_pool().begin().await?;
         sqlx::query!("delete from cates where id = $1", params.id)
-            .execute(self.db.get_pool())
+            .execute(&mut *tx)
             .await?;
 
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "cates",
+                cate.id,
+                AUDIT_DELETE,
+                snapshot(&cate),
+                None,
+            )
+            .await?;
+        tx.commit().await?;
+
         Ok(())
     }
 
-    async fn insert_multiple_cate1(&self, names: &[&str]) -> ERPResult<HashMap<String, i32>> {
+    async fn insert_multiple_cate1(
+        &self,
+        conn: &mut PgConnection,
+        names: &[&str],
+    ) -> ERPResult<HashMap<String, i32>> {
         let mut query_builder: QueryBuilder<Postgres> =
             QueryBuilder::new("insert into cates (index, name, cate_type, parent_id) ");
 
@@ -265,7 +329,7 @@ impl CateServiceTrait for CateService {
 
         let name_to_id = query_builder
             .build_query_as::<CateModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(conn)
             .await?
             .into_iter()
             .map(|item| (item.name, item.id))
@@ -274,7 +338,11 @@ impl CateServiceTrait for CateService {
         Ok(name_to_id)
     }
 
-    async fn insert_multiple_cate2(&self, items: &[CateModel]) -> ERPResult<Vec<CateModel>> {
+    async fn insert_multiple_cate2(
+        &self,
+        conn: &mut PgConnection,
+        items: &[CateModel],
+    ) -> ERPResult<Vec<CateModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
             QueryBuilder::new("insert into cates (index, name, cate_type, parent_id) ");
 
@@ -289,7 +357,7 @@ impl CateServiceTrait for CateService {
 
         let cates = query_builder
             .build_query_as::<CateModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(conn)
             .await?;
 
         Ok(cates)
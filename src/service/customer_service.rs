@@ -1,6 +1,8 @@
 use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE};
 use crate::dto::dto_customer::{CustomerDto, CustomerEditParam, CustomerSearchParam};
 use crate::model::customer::CustomerModel;
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
 use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
 use std::sync::Arc;
@@ -8,6 +10,7 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct CustomerService {
     pub db: Arc<Database>,
+    audit_service: AuditService,
 }
 
 #[async_trait]
@@ -17,14 +20,17 @@ pub trait CustomerServiceTrait {
     async fn get_customers(&self, param: &CustomerSearchParam) -> ERPResult<Vec<CustomerModel>>;
     async fn get_all_customers(&self) -> ERPResult<Vec<CustomerModel>>;
     async fn get_customers_count(&self, param: &CustomerSearchParam) -> ERPResult<i32>;
-    async fn edit_customer(&self, param: &CustomerEditParam) -> ERPResult<()>;
-    async fn delete_customer(&self, id: i32) -> ERPResult<()>;
+    async fn edit_customer(&self, param: &CustomerEditParam, account_id: i32) -> ERPResult<()>;
+    async fn delete_customer(&self, id: i32, account_id: i32) -> ERPResult<()>;
 }
 
 #[async_trait]
 impl CustomerServiceTrait for CustomerService {
     fn new(db: &Arc<Database>) -> Self {
-        Self { db: Arc::clone(db) }
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+        }
     }
 
     async fn get_customer_with_id(&self, customer_id: i32) -> ERPResult<CustomerDto> {
@@ -71,7 +77,7 @@ impl CustomerServiceTrait for CustomerService {
         Ok(count)
     }
 
-    async fn edit_customer(&self, param: &CustomerEditParam) -> ERPResult<()> {
+    async fn edit_customer(&self, param: &CustomerEditParam, account_id: i32) -> ERPResult<()> {
         let existing = sqlx::query_as!(
             CustomerModel,
             "select * from customers where name = $1",
@@ -95,11 +101,14 @@ impl CustomerServiceTrait for CustomerService {
                     )));
                 }
 
-                sqlx::query!(
+                let mut tx = self.db.get_pool().begin().await?;
+                let customer = sqlx::query_as!(
+                    CustomerModel,
                     r#"
                     insert into customers (ty_pe, name, head, address, 
                         email, birthday, phone, notes)
-                    values ($1, $2, $3, $4, $5, $6, $7, $8);
+                    values ($1, $2, $3, $4, $5, $6, $7, $8)
+                    returning *;
                     "#,
                     param.ty_pe,
                     param.name,
@@ -110,8 +119,21 @@ impl CustomerServiceTrait for CustomerService {
                     param.phone,
                     param.notes,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "customers",
+                        customer.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&customer),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 if !existing
@@ -125,12 +147,21 @@ impl CustomerServiceTrait for CustomerService {
                         param.name
                     )));
                 }
-                sqlx::query!(
+                let customer =
+                    sqlx::query_as!(CustomerModel, "select * from customers where id = $1", id)
+                        .fetch_optional(self.db.get_pool())
+                        .await?
+                        .ok_or(ERPError::NotFound("客户不存在".to_string()))?;
+
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    CustomerModel,
                     r#"
                     update customers set notes=$1, ty_pe=$2, name=$3, 
                         head=$4, address=$5, email=$6, birthday=$7, 
                         phone=$8
-                    where id=$9"#,
+                    where id=$9
+                    returning *"#,
                     param.notes,
                     param.ty_pe,
                     param.name,
@@ -141,18 +172,51 @@ impl CustomerServiceTrait for CustomerService {
                     param.phone,
                     param.id,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "customers",
+                        id,
+                        AUDIT_UPDATE,
+                        snapshot(&customer),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         };
 
         Ok(())
     }
 
-    async fn delete_customer(&self, id: i32) -> ERPResult<()> {
-        sqlx::query!("delete from customers where id = $1", id)
-            .execute(self.db.get_pool())
-            .await?;
+    async fn delete_customer(&self, id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let customer = sqlx::query_as!(
+            CustomerModel,
+            "delete from customers where id = $1 returning *",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(customer) = customer {
+            self.audit_service
+                .add_audit_log(
+                    &mut tx,
+                    account_id,
+                    "customers",
+                    id,
+                    AUDIT_DELETE,
+                    snapshot(&customer),
+                    None,
+                )
+                .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 }
@@ -1,5 +1,5 @@
 use crate::config::database::{Database, DatabaseTrait};
-use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::constants::{AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE, DEFAULT_PAGE_SIZE};
 use crate::dto::dto_embryo::{
     EditParams, EmbryoDto, EmbryoInOutBucketDto, EmbryoInOutDto, InoutBucketParams,
     InoutListOfBucketParams, InoutParams, QueryParams,
@@ -8,9 +8,11 @@ use crate::dto::dto_excel::EmbryoExcelDto;
 use crate::dto::GenericDeleteParams;
 use crate::model::embryo::{EmbryoInOutBucketModal, EmbryoInOutModel, EmbryoModel};
 use crate::repository::embryo_repository::{EmbryoRepository, EmbryoRepositoryTrait};
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
 use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
-use sqlx::{Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -18,6 +20,8 @@ use std::sync::Arc;
 pub struct EmbryoService {
     db: Arc<Database>,
     pub embryo_repo: EmbryoRepository,
+    audit_service: AuditService,
+    stock_service: StockService,
 }
 
 #[async_trait]
@@ -25,15 +29,24 @@ pub trait EmbryoServiceTrait {
     fn new(db: &Arc<Database>) -> Self;
     async fn get_item_list(&self, params: &QueryParams) -> ERPResult<Vec<EmbryoModel>>;
     async fn get_item_count(&self, params: &QueryParams) -> ERPResult<i32>;
-    async fn edit_item(&self, params: &EditParams) -> ERPResult<()>;
-    async fn delete_item(&self, params: &GenericDeleteParams) -> ERPResult<()>;
-    async fn insert_multiple_items(&self, rows: &[EmbryoExcelDto]) -> ERPResult<Vec<EmbryoModel>>;
-    async fn insert_multiple_items_inouts(&self, rows: &[EmbryoInOutModel]) -> ERPResult<()>;
+    async fn edit_item(&self, params: &EditParams, account_id: i32) -> ERPResult<()>;
+    async fn delete_item(&self, params: &GenericDeleteParams, account_id: i32) -> ERPResult<()>;
+    async fn insert_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[EmbryoExcelDto],
+    ) -> ERPResult<Vec<EmbryoModel>>;
+    async fn insert_multiple_items_inouts(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[EmbryoInOutModel],
+    ) -> ERPResult<()>;
     async fn add_item_inout(&self, params: &InoutParams, account_id: i32) -> ERPResult<()>;
     async fn embryos_to_embryo_dtos(&self, embryos: Vec<EmbryoModel>) -> ERPResult<Vec<EmbryoDto>>;
     // async fn get_embryo_dtos_with_numbers(&self, numbers: &[String]) -> ERPResult<Vec<EmbryoDto>>;
     async fn add_inout_bucket(
         &self,
+        conn: &mut PgConnection,
         bucket: EmbryoInOutBucketModal,
     ) -> ERPResult<EmbryoInOutBucketModal>;
 
@@ -55,6 +68,8 @@ impl EmbryoServiceTrait for EmbryoService {
         Self {
             db: Arc::clone(db),
             embryo_repo: EmbryoRepository::new(db),
+            audit_service: AuditService::new(db),
+            stock_service: StockService::new(db),
         }
     }
     async fn get_item_list(&self, params: &QueryParams) -> ERPResult<Vec<EmbryoModel>> {
@@ -122,14 +137,17 @@ impl EmbryoServiceTrait for EmbryoService {
         Ok(count)
     }
 
-    async fn edit_item(&self, params: &EditParams) -> ERPResult<()> {
+    async fn edit_item(&self, params: &EditParams, account_id: i32) -> ERPResult<()> {
         match params.id {
             0 => {
                 // 新增item
-                sqlx::query!(
+                let mut tx = self.db.get_pool().begin().await?;
+                let embryo = sqlx::query_as!(
+                    EmbryoModel,
                     r#"
                     insert into embryos (images, name, color, unit, number, notes)
-                    values ($1, $2, $3, $4, $5, $6);
+                    values ($1, $2, $3, $4, $5, $6)
+                    returning *;
                     "#,
                     &params.images,
                     params.name,
@@ -138,15 +156,32 @@ impl EmbryoServiceTrait for EmbryoService {
                     params.number,
                     params.notes,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "embryos",
+                        embryo.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&embryo),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 // 修改item
-                sqlx::query!(
+                let embryo = self.embryo_repo.get_embryo(params.id).await?;
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    EmbryoModel,
                     r#"
                     update embryos set images=$1, name=$2, color=$3, unit=$4, number=$5, notes=$6
                     where id=$7
+                    returning *
                     "#,
                     &params.images,
                     params.name,
@@ -156,23 +191,62 @@ impl EmbryoServiceTrait for EmbryoService {
                     params.notes,
                     params.id,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "embryos",
+                        embryo.id,
+                        AUDIT_UPDATE,
+                        snapshot(&embryo),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         };
 
         Ok(())
     }
 
-    async fn delete_item(&self, params: &GenericDeleteParams) -> ERPResult<()> {
-        sqlx::query!("delete from embryos where id = $1", params.id)
-            .execute(self.db.get_pool())
+    async fn delete_item(&self, params: &GenericDeleteParams, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let embryo = sqlx::query_as!(
+            EmbryoModel,
+            "delete from embryos where id = $1 returning *",
+            params.id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        sqlx::query!("delete from embryo_stock where embryo_id = $1", params.id)
+            .execute(&mut *tx)
             .await?;
+        if let Some(embryo) = embryo {
+            self.audit_service
+                .add_audit_log(
+                    &mut tx,
+                    account_id,
+                    "embryos",
+                    embryo.id,
+                    AUDIT_DELETE,
+                    snapshot(&embryo),
+                    None,
+                )
+                .await?;
+        }
+        tx.commit().await?;
 
         Ok(())
     }
 
-    async fn insert_multiple_items(&self, rows: &[EmbryoExcelDto]) -> ERPResult<Vec<EmbryoModel>> {
+    async fn insert_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[EmbryoExcelDto],
+    ) -> ERPResult<Vec<EmbryoModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
             QueryBuilder::new("insert into embryos (images, name,  color, unit, number, notes)");
 
@@ -189,13 +263,17 @@ impl EmbryoServiceTrait for EmbryoService {
 
         let embryos = query_builder
             .build_query_as::<EmbryoModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
             .await?;
 
         Ok(embryos)
     }
 
-    async fn insert_multiple_items_inouts(&self, rows: &[EmbryoInOutModel]) -> ERPResult<()> {
+    async fn insert_multiple_items_inouts(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[EmbryoInOutModel],
+    ) -> ERPResult<()> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
             "insert into embryo_inout (bucket_id, embryo_id, count, current_cost, current_total) ",
         );
@@ -210,7 +288,14 @@ impl EmbryoServiceTrait for EmbryoService {
 
         query_builder.push(" returning id;");
 
-        query_builder.build().execute(self.db.get_pool()).await?;
+        query_builder.build().execute(&mut *conn).await?;
+        let stock_rows = rows
+            .iter()
+            .map(|item| (item.bucket_id, item.embryo_id, item.count))
+            .collect::<Vec<_>>();
+        self.stock_service
+            .add_embryo_stock_by_bucket(&mut *conn, &stock_rows)
+            .await?;
         Ok(())
     }
 
@@ -229,24 +314,32 @@ impl EmbryoServiceTrait for EmbryoService {
             _ => -params.count,
         };
 
+        let mut tx = self.db.get_pool().begin().await?;
+        let warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, params.warehouse_id)
+            .await?;
         let bucket_id = sqlx::query!(
             r#"
-            insert into embryo_inout_bucket (account_id, in_true_out_false, via)
-            values ($1, $2, $3) 
+            insert into embryo_inout_bucket (account_id, in_true_out_false, via, warehouse_id)
+            values ($1, $2, $3, $4)
             returning id
             "#,
             account_id,
             params.in_out,
-            "form"
+            "form",
+            warehouse_id
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?
         .id;
 
-        sqlx::query!(
+        let inout = sqlx::query_as!(
+            EmbryoInOutModel,
             r#"
             insert into embryo_inout (bucket_id, embryo_id, count, current_cost, current_total) 
-            values ($1, $2, $3, $4, $5);
+            values ($1, $2, $3, $4, $5)
+            returning *;
             "#,
             bucket_id,
             params.id,
@@ -254,8 +347,23 @@ impl EmbryoServiceTrait for EmbryoService {
             embryo.cost,
             embryo.cost * count
         )
-        .execute(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?;
+        self.stock_service
+            .add_embryo_stock(&mut tx, warehouse_id, &[(params.id, count)])
+            .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "embryo_inout_bucket",
+                bucket_id,
+                AUDIT_CREATE,
+                None,
+                snapshot(&vec![inout]),
+            )
+            .await?;
+        tx.commit().await?;
 
         Ok(())
     }
@@ -263,27 +371,19 @@ impl EmbryoServiceTrait for EmbryoService {
     async fn embryos_to_embryo_dtos(&self, embryos: Vec<EmbryoModel>) -> ERPResult<Vec<EmbryoDto>> {
         let embryo_ids = embryos.iter().map(|item| item.id).collect::<Vec<_>>();
 
-        let embryo_id_to_count = sqlx::query!(
-            r#"
-            select embryo_id, sum(count) 
-            from embryo_inout 
-            where embryo_id = any($1)
-            group by embryo_id
-            "#,
-            &embryo_ids
-        )
-        .fetch_all(self.db.get_pool())
-        .await?
-        .into_iter()
-        .map(|r| (r.embryo_id, r.sum.unwrap_or(0) as i32))
-        .collect::<HashMap<_, _>>();
+        let embryo_id_to_count = self.stock_service.get_embryo_stock(&embryo_ids).await?;
+        let mut embryo_id_to_stocks = self
+            .stock_service
+            .get_embryo_warehouse_stock(&embryo_ids)
+            .await?;
 
         let embryo_dtos = embryos
             .into_iter()
             .map(|item| {
                 let count = embryo_id_to_count.get(&item.id).unwrap_or(&0);
+                let stocks = embryo_id_to_stocks.remove(&item.id).unwrap_or_default();
 
-                EmbryoDto::from(item, *count)
+                EmbryoDto::from(item, *count, stocks)
             })
             .collect::<Vec<_>>();
 
@@ -292,20 +392,22 @@ impl EmbryoServiceTrait for EmbryoService {
 
     async fn add_inout_bucket(
         &self,
+        conn: &mut PgConnection,
         bucket: EmbryoInOutBucketModal,
     ) -> ERPResult<EmbryoInOutBucketModal> {
         let bucket = sqlx::query_as!(
             EmbryoInOutBucketModal,
             r#"
-            insert into embryo_inout_bucket (account_id, in_true_out_false, via)
-            values ($1, $2, $3)
+            insert into embryo_inout_bucket (account_id, in_true_out_false, via, warehouse_id)
+            values ($1, $2, $3, $4)
             returning *
             "#,
             bucket.account_id,
             bucket.in_true_out_false,
             bucket.via,
+            bucket.warehouse_id
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(bucket)
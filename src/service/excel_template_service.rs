@@ -0,0 +1,379 @@
+use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE};
+use crate::dto::dto_excel::{CustomerExcelTemplateParams, ExcelTemplateEditParams};
+use crate::dto::GenericDeleteParams;
+use crate::excel::parse_template_orders::{FIELD_TO_NAME, REQUIRED_FIELDS};
+use crate::model::excel::{CustomerExcelTemplateModel, ExcelTemplateModel};
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::{ERPError, ERPResult};
+use async_trait::async_trait;
+use regex::Regex;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct ExcelTemplateService {
+    pub db: Arc<Database>,
+    audit_service: AuditService,
+}
+
+#[async_trait]
+pub trait ExcelTemplateServiceTrait {
+    fn new(db: &Arc<Database>) -> Self;
+    async fn get_excel_templates(&self) -> ERPResult<Vec<ExcelTemplateModel>>;
+    async fn edit_excel_template(
+        &self,
+        params: &ExcelTemplateEditParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
+    async fn delete_excel_template(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
+    async fn get_customer_excel_templates(&self) -> ERPResult<Vec<CustomerExcelTemplateModel>>;
+    async fn get_customer_excel_template(
+        &self,
+        customer_id: i32,
+    ) -> ERPResult<Option<ExcelTemplateModel>>;
+    async fn set_customer_excel_template(
+        &self,
+        params: &CustomerExcelTemplateParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
+}
+
+/// 保存前检查: 行号, 字段名, 必需的列, 日期正则
+fn check_excel_template(params: &ExcelTemplateEditParams) -> ERPResult<()> {
+    if params.name.trim().is_empty() {
+        return Err(ERPError::ParamNeeded("模版名称".to_string()));
+    }
+    if params.header_row < 1 || params.data_start_row <= params.header_row {
+        return Err(ERPError::ParamError(
+            "表头行要从1开始, 数据开始行要在表头行之后".to_string(),
+        ));
+    }
+
+    for (header, field) in params.columns.iter() {
+        if header.trim().is_empty() {
+            return Err(ERPError::ParamError("表头文字不能为空".to_string()));
+        }
+        if !FIELD_TO_NAME.iter().any(|(key, _)| key == field) {
+            return Err(ERPError::ParamError(format!(
+                "未知字段: {}, 可用: {}",
+                field,
+                FIELD_TO_NAME
+                    .iter()
+                    .map(|(key, _)| *key)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )));
+        }
+    }
+    for field in REQUIRED_FIELDS.iter() {
+        if !params.columns.values().any(|value| value == field) {
+            return Err(ERPError::ParamError(format!("缺少必需的字段: {}", field)));
+        }
+    }
+
+    for re in [&params.order_date_regex, &params.delivery_date_regex] {
+        if re.is_empty() {
+            continue;
+        }
+        match Regex::new(re) {
+            Ok(re) if re.captures_len() > 1 => {}
+            _ => {
+                return Err(ERPError::ParamError(format!(
+                    "日期正则有误(需要一个分组匹配日期): {}",
+                    re
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl ExcelTemplateServiceTrait for ExcelTemplateService {
+    fn new(db: &Arc<Database>) -> Self {
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+        }
+    }
+
+    async fn get_excel_templates(&self) -> ERPResult<Vec<ExcelTemplateModel>> {
+        let templates = sqlx::query_as!(
+            ExcelTemplateModel,
+            "select * from excel_templates order by id"
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+
+        Ok(templates)
+    }
+
+    async fn edit_excel_template(
+        &self,
+        params: &ExcelTemplateEditParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        check_excel_template(params)?;
+
+        let templates = self.get_excel_templates().await?;
+        if templates
+            .iter()
+            .any(|item| item.id != params.id && item.name == params.name)
+        {
+            return Err(ERPError::AlreadyExists(format!(
+                "导入模版: {} 已存在",
+                params.name
+            )));
+        }
+
+        let columns = serde_json::to_value(&params.columns)
+            .map_err(|e| ERPError::ConvertFailed(e.to_string()))?;
+        match params.id {
+            0 => {
+                let mut tx = self.db.get_pool().begin().await?;
+                let template = sqlx::query_as!(
+                    ExcelTemplateModel,
+                    r#"insert into excel_templates
+                    (name, header_row, data_start_row, columns, order_date_regex, delivery_date_regex)
+                    values ($1, $2, $3, $4, $5, $6) returning *"#,
+                    params.name,
+                    params.header_row,
+                    params.data_start_row,
+                    columns,
+                    params.order_date_regex,
+                    params.delivery_date_regex
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "excel_templates",
+                        template.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&template),
+                    )
+                    .await?;
+                tx.commit().await?;
+            }
+            _ => {
+                let before = templates.iter().find(|item| item.id == params.id);
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    ExcelTemplateModel,
+                    r#"update excel_templates
+                    set name=$1, header_row=$2, data_start_row=$3, columns=$4,
+                        order_date_regex=$5, delivery_date_regex=$6
+                    where id=$7 returning *"#,
+                    params.name,
+                    params.header_row,
+                    params.data_start_row,
+                    columns,
+                    params.order_date_regex,
+                    params.delivery_date_regex,
+                    params.id
+                )
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "excel_templates",
+                        params.id,
+                        AUDIT_UPDATE,
+                        before.and_then(snapshot),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_excel_template(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        let template = sqlx::query_as!(
+            ExcelTemplateModel,
+            "select * from excel_templates where id=$1",
+            params.id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?
+        .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+        if sqlx::query!(
+            "select count(1) from customer_excel_template where template_id = $1",
+            params.id
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0)
+            > 0
+        {
+            return Err(ERPError::Failed(
+                "删除不合法，有客户在用这个模版".to_string(),
+            ));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        sqlx::query!("delete from excel_templates where id = $1", params.id)
+            .execute(&mut *tx)
+            .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "excel_templates",
+                template.id,
+                AUDIT_DELETE,
+                snapshot(&template),
+                None,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_customer_excel_templates(&self) -> ERPResult<Vec<CustomerExcelTemplateModel>> {
+        let customer_templates = sqlx::query_as!(
+            CustomerExcelTemplateModel,
+            "select * from customer_excel_template order by customer_id"
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+
+        Ok(customer_templates)
+    }
+
+    async fn get_customer_excel_template(
+        &self,
+        customer_id: i32,
+    ) -> ERPResult<Option<ExcelTemplateModel>> {
+        let template = sqlx::query_as!(
+            ExcelTemplateModel,
+            r#"select t.* from excel_templates t, customer_excel_template c
+            where t.id = c.template_id and c.customer_id = $1"#,
+            customer_id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?;
+
+        Ok(template)
+    }
+
+    async fn set_customer_excel_template(
+        &self,
+        params: &CustomerExcelTemplateParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        if sqlx::query!(
+            "select count(1) from customers where id = $1",
+            params.customer_id
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0)
+            == 0
+        {
+            return Err(ERPError::NotFound("客户不存在".to_string()));
+        }
+
+        let before = sqlx::query_as!(
+            CustomerExcelTemplateModel,
+            "select * from customer_excel_template where customer_id = $1",
+            params.customer_id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?;
+
+        // 0: 不再用模版, 按默认格式导入
+        if params.template_id == 0 {
+            if let Some(before) = before {
+                let mut tx = self.db.get_pool().begin().await?;
+                sqlx::query!(
+                    "delete from customer_excel_template where id = $1",
+                    before.id
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "customer_excel_template",
+                        before.id,
+                        AUDIT_DELETE,
+                        snapshot(&before),
+                        None,
+                    )
+                    .await?;
+                tx.commit().await?;
+            }
+            return Ok(());
+        }
+
+        if sqlx::query!(
+            "select count(1) from excel_templates where id = $1",
+            params.template_id
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .count
+        .unwrap_or(0)
+            == 0
+        {
+            return Err(ERPError::NotFound("导入模版不存在".to_string()));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        let updated = sqlx::query_as!(
+            CustomerExcelTemplateModel,
+            r#"insert into customer_excel_template (customer_id, template_id) values ($1, $2)
+            on conflict (customer_id) do update set template_id = excluded.template_id
+            returning *"#,
+            params.customer_id,
+            params.template_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "customer_excel_template",
+                updated.id,
+                match before {
+                    Some(_) => AUDIT_UPDATE,
+                    None => AUDIT_CREATE,
+                },
+                before.as_ref().and_then(snapshot),
+                snapshot(&updated),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,568 @@
+use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{
+    DEFAULT_PAGE_SIZE, IMPORT_JOB_STATUS_FAILED, IMPORT_JOB_STATUS_ROLLED_BACK,
+    IMPORT_JOB_STATUS_RUNNING,
+};
+use crate::dto::dto_import_job::{ImportCounts, ImportJobDto, ImportRollbackDto, QueryParams};
+use crate::model::import_job::ImportJobModel;
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
+use crate::{ERPError, ERPResult};
+use async_trait::async_trait;
+use itertools::Itertools;
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct ImportJobService {
+    pub db: Arc<Database>,
+    audit_service: AuditService,
+    stock_service: StockService,
+}
+
+#[async_trait]
+pub trait ImportJobServiceTrait {
+    fn new(db: &Arc<Database>) -> Self;
+    async fn add_import_job(&self, job: &ImportJobModel) -> ERPResult<i32>;
+    async fn finish_import_job(
+        &self,
+        id: i32,
+        status: i32,
+        counts: &ImportCounts,
+        errors: Option<Value>,
+    ) -> ERPResult<()>;
+    async fn add_import_job_entities(
+        &self,
+        conn: &mut PgConnection,
+        job_id: i32,
+        entity: &str,
+        entity_ids: &[i32],
+    ) -> ERPResult<()>;
+    async fn fail_unfinished_import_jobs(&self) -> ERPResult<u64>;
+    async fn get_import_job(&self, id: i32) -> ERPResult<ImportJobModel>;
+    async fn get_import_job_list(&self, params: &QueryParams) -> ERPResult<Vec<ImportJobDto>>;
+    async fn get_import_job_count(&self, params: &QueryParams) -> ERPResult<i32>;
+    /// 导入时对已有商品的修改不会还原, 只在结果里给出修改的条数
+    async fn rollback_import_job(&self, id: i32, account_id: i32) -> ERPResult<ImportRollbackDto>;
+}
+
+/// 一次导入新增的数据
+#[derive(Debug, Default)]
+struct JobEntities {
+    items: Vec<i32>,
+    embryos: Vec<i32>,
+    orders: Vec<i32>,
+    item_buckets: Vec<i32>,
+    embryo_buckets: Vec<i32>,
+    cates: Vec<i32>,
+    colors: Vec<i32>,
+}
+
+impl JobEntities {
+    fn from(rows: Vec<(String, i32)>) -> Self {
+        let mut entities = JobEntities::default();
+        for (entity, id) in rows {
+            let ids = match entity.as_str() {
+                "items" => &mut entities.items,
+                "embryos" => &mut entities.embryos,
+                "orders" => &mut entities.orders,
+                "item_inout_bucket" => &mut entities.item_buckets,
+                "embryo_inout_bucket" => &mut entities.embryo_buckets,
+                "cates" => &mut entities.cates,
+                "color_settings" => &mut entities.colors,
+                _ => continue,
+            };
+            ids.push(id);
+        }
+        entities
+    }
+}
+
+/// 导入的数据被后面的数据用到了就不能撤销
+async fn check_rollback_dependencies(
+    conn: &mut PgConnection,
+    entities: &JobEntities,
+) -> ERPResult<()> {
+    let mut reasons = vec![];
+
+    let item_ids = sqlx::query!(
+        r#"
+        select distinct item_id as "id!" from item_inout
+        where item_id = any($1) and bucket_id <> all($2)
+        union
+        select distinct item_id as "id!" from order_items
+        where item_id = any($1) and order_id <> all($3)
+        "#,
+        &entities.items,
+        &entities.item_buckets,
+        &entities.orders
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if !item_ids.is_empty() {
+        reasons.push(format!(
+            "商品#{} 已有其他出入库或订单",
+            item_ids.iter().map(|row| row.id).join(",")
+        ));
+    }
+
+    // 导入新增的胚有其他出入库, 或者导入入库的已有胚在导入之后又有出入库(减回去可能变成负库存)
+    let embryo_ids = sqlx::query!(
+        r#"
+        select distinct o.embryo_id as "id!" from embryo_inout o
+        where o.bucket_id <> all($2)
+          and (o.embryo_id = any($1)
+               or exists(select 1 from embryo_inout j
+                         where j.bucket_id = any($2) and j.embryo_id = o.embryo_id and j.id < o.id))
+        "#,
+        &entities.embryos,
+        &entities.embryo_buckets
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if !embryo_ids.is_empty() {
+        reasons.push(format!(
+            "胚#{} 导入后已有其他出入库",
+            embryo_ids.iter().map(|row| row.id).join(",")
+        ));
+    }
+
+    let order_ids = sqlx::query!(
+        r#"
+        select distinct order_id as "id!" from item_inout_bucket
+        where order_id = any($1) and id <> all($2)
+        "#,
+        &entities.orders,
+        &entities.item_buckets
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if !order_ids.is_empty() {
+        reasons.push(format!(
+            "订单#{} 已有出入库",
+            order_ids.iter().map(|row| row.id).join(",")
+        ));
+    }
+
+    let cate_names = sqlx::query!(
+        r#"
+        select name from cates
+        where id = any($1)
+          and (exists(select 1 from items
+                      where (cate1_id = cates.id or cate2_id = cates.id) and id <> all($2))
+               or exists(select 1 from cates c where c.parent_id = cates.id and c.id <> all($1)))
+        "#,
+        &entities.cates,
+        &entities.items
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if !cate_names.is_empty() {
+        reasons.push(format!(
+            "类别 {} 已被其他商品使用",
+            cate_names.iter().map(|row| &row.name).join(",")
+        ));
+    }
+
+    let colors = sqlx::query!(
+        r#"
+        select color from color_settings
+        where id = any($1)
+          and (exists(select 1 from items where color = color_settings.color and id <> all($2))
+               or exists(select 1 from embryos where color = color_settings.color and id <> all($3))
+               or exists(select 1 from import_order_items
+                         where color = color_settings.color and order_id <> all($4)))
+        "#,
+        &entities.colors,
+        &entities.items,
+        &entities.embryos,
+        &entities.orders
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if !colors.is_empty() {
+        reasons.push(format!(
+            "颜色 {} 已被其他数据使用",
+            colors.iter().map(|row| &row.color).join(",")
+        ));
+    }
+
+    match reasons.is_empty() {
+        true => Ok(()),
+        false => Err(ERPError::Collision(format!(
+            "导入的数据已被使用, 不能撤销: {}",
+            reasons.join("; ")
+        ))),
+    }
+}
+
+fn push_filters(sql: &mut QueryBuilder<Postgres>, params: &QueryParams) {
+    sql.push(" where 1 = 1 ");
+    if let Some(account_id) = params.account_id.filter(|id| *id != 0) {
+        sql.push(" and account_id = ").push_bind(account_id);
+    }
+    if let Some(tp) = params.tp {
+        sql.push(" and tp = ").push_bind(tp);
+    }
+    if let Some(customer_id) = params.customer_id.filter(|id| *id != 0) {
+        sql.push(" and customer_id = ").push_bind(customer_id);
+    }
+    if let Some(status) = params.status {
+        sql.push(" and status = ").push_bind(status);
+    }
+    if let Some(st) = params.create_time_st.as_ref().filter(|st| !st.is_empty()) {
+        sql.push(" and create_time >= ")
+            .push_bind(st.to_string())
+            .push("::timestamptz");
+    }
+    if let Some(ed) = params.create_time_ed.as_ref().filter(|ed| !ed.is_empty()) {
+        sql.push(" and create_time <= ")
+            .push_bind(ed.to_string())
+            .push("::timestamptz");
+    }
+}
+
+#[async_trait]
+impl ImportJobServiceTrait for ImportJobService {
+    fn new(db: &Arc<Database>) -> Self {
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+            stock_service: StockService::new(db),
+        }
+    }
+
+    async fn add_import_job(&self, job: &ImportJobModel) -> ERPResult<i32> {
+        let id = sqlx::query!(
+            r#"
+            insert into import_jobs (account_id, tp, customer_id, filename, file_path, status)
+            values ($1, $2, $3, $4, $5, $6)
+            returning id
+            "#,
+            job.account_id,
+            job.tp,
+            job.customer_id,
+            job.filename,
+            job.file_path,
+            IMPORT_JOB_STATUS_RUNNING
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .id;
+
+        Ok(id)
+    }
+
+    async fn finish_import_job(
+        &self,
+        id: i32,
+        status: i32,
+        counts: &ImportCounts,
+        errors: Option<Value>,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            r#"
+            update import_jobs
+            set status = $1, row_count = $2, created_count = $3, updated_count = $4,
+                errors = $5, finish_time = now()
+            where id = $6
+            "#,
+            status,
+            counts.row_count,
+            counts.created_count,
+            counts.updated_count,
+            errors,
+            id
+        )
+        .execute(self.db.get_pool())
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_import_job_entities(
+        &self,
+        conn: &mut PgConnection,
+        job_id: i32,
+        entity: &str,
+        entity_ids: &[i32],
+    ) -> ERPResult<()> {
+        if entity_ids.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            insert into import_job_entities (job_id, entity, entity_id)
+            select $1, $2, * from unnest($3::int[])
+            "#,
+            job_id,
+            entity,
+            entity_ids
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// 重启前没导完的, 后台任务已经没了, 标记为失败
+    async fn fail_unfinished_import_jobs(&self) -> ERPResult<u64> {
+        let errors = serde_json::json!({"msg": "服务重启, 导入中断", "errors": []});
+        let count = sqlx::query!(
+            r#"
+            update import_jobs set status = $1, errors = $2, finish_time = now()
+            where status = $3
+            "#,
+            IMPORT_JOB_STATUS_FAILED,
+            errors,
+            IMPORT_JOB_STATUS_RUNNING
+        )
+        .execute(self.db.get_pool())
+        .await?
+        .rows_affected();
+
+        Ok(count)
+    }
+
+    async fn get_import_job(&self, id: i32) -> ERPResult<ImportJobModel> {
+        let job = sqlx::query_as!(
+            ImportJobModel,
+            "select * from import_jobs where id = $1",
+            id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?
+        .ok_or(ERPError::NotFound("导入记录".to_string()))?;
+
+        Ok(job)
+    }
+
+    async fn get_import_job_list(&self, params: &QueryParams) -> ERPResult<Vec<ImportJobDto>> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select * from import_jobs ");
+        push_filters(&mut sql, params);
+
+        let page = params.page.unwrap_or(1);
+        let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let offset = (page - 1) * page_size;
+        sql.push(format!(
+            " order by id desc limit {page_size} offset {offset}"
+        ));
+
+        let jobs = sql
+            .build_query_as::<ImportJobModel>()
+            .fetch_all(self.db.get_pool())
+            .await?;
+
+        let account_ids = jobs.iter().map(|job| job.account_id).collect::<Vec<_>>();
+        let id_to_name = sqlx::query!(
+            "select id, name from accounts where id = any($1)",
+            &account_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect::<HashMap<i32, String>>();
+
+        let empty = "".to_string();
+        let dtos = jobs
+            .into_iter()
+            .map(|job| {
+                let name = id_to_name.get(&job.account_id).unwrap_or(&empty).clone();
+                ImportJobDto::from(job, &name)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(dtos)
+    }
+
+    async fn get_import_job_count(&self, params: &QueryParams) -> ERPResult<i32> {
+        let mut sql: QueryBuilder<Postgres> =
+            QueryBuilder::new("select count(1) from import_jobs ");
+        push_filters(&mut sql, params);
+
+        let count = sql
+            .build_query_as::<(i64,)>()
+            .fetch_one(self.db.get_pool())
+            .await?
+            .0 as i32;
+
+        Ok(count)
+    }
+
+    async fn rollback_import_job(&self, id: i32, account_id: i32) -> ERPResult<ImportRollbackDto> {
+        let mut tx = self.db.get_pool().begin().await?;
+
+        // 锁住导入记录, 同一个导入不会被同时撤销两次
+        let job = sqlx::query!(
+            "select status, updated_count from import_jobs where id = $1 for update",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ERPError::NotFound("导入记录".to_string()))?;
+        match job.status {
+            IMPORT_JOB_STATUS_RUNNING => {
+                return Err(ERPError::Failed("导入还没结束, 不能撤销".to_string()))
+            }
+            IMPORT_JOB_STATUS_ROLLED_BACK => {
+                return Err(ERPError::Failed("该导入已经撤销过了".to_string()))
+            }
+            _ => {}
+        }
+
+        let rows = sqlx::query!(
+            "select entity, entity_id from import_job_entities where job_id = $1",
+            id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.entity, row.entity_id))
+        .collect::<Vec<_>>();
+        let entities = JobEntities::from(rows);
+
+        check_rollback_dependencies(&mut tx, &entities).await?;
+
+        // 先删明细再删主表, 库存跟着减回去
+        let item_stock_rows = sqlx::query!(
+            "delete from item_inout where bucket_id = any($1) returning bucket_id, item_id, count",
+            &entities.item_buckets
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.bucket_id, row.item_id, -row.count))
+        .collect::<Vec<_>>();
+        // 减回导入入库的库存, 已经出库了不够减的不让撤销, 不管负库存设置
+        let shortages = self
+            .stock_service
+            .check_item_stock_out_by_bucket(&mut tx, &item_stock_rows, account_id)
+            .await?;
+        if !shortages.is_empty() {
+            return Err(ERPError::InsufficientStock(shortages));
+        }
+        self.stock_service
+            .add_item_stock_by_bucket(&mut tx, &item_stock_rows)
+            .await?;
+        let item_buckets = sqlx::query!(
+            "delete from item_inout_bucket where id = any($1)",
+            &entities.item_buckets
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let embryo_stock_rows = sqlx::query!(
+            "delete from embryo_inout where bucket_id = any($1) returning bucket_id, embryo_id, count",
+            &entities.embryo_buckets
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.bucket_id, row.embryo_id, -row.count))
+        .collect::<Vec<_>>();
+        self.stock_service
+            .add_embryo_stock_by_bucket(&mut tx, &embryo_stock_rows)
+            .await?;
+        let embryo_buckets = sqlx::query!(
+            "delete from embryo_inout_bucket where id = any($1)",
+            &entities.embryo_buckets
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query!(
+            "delete from import_order_items where order_id = any($1)",
+            &entities.orders
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "delete from order_items where order_id = any($1)",
+            &entities.orders
+        )
+        .execute(&mut *tx)
+        .await?;
+        let orders = sqlx::query!("delete from orders where id = any($1)", &entities.orders)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query!(
+            "delete from item_stock where item_id = any($1)",
+            &entities.items
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "delete from embryo_stock where embryo_id = any($1)",
+            &entities.embryos
+        )
+        .execute(&mut *tx)
+        .await?;
+        let items = sqlx::query!("delete from items where id = any($1)", &entities.items)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let embryos = sqlx::query!("delete from embryos where id = any($1)", &entities.embryos)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let cates = sqlx::query!("delete from cates where id = any($1)", &entities.cates)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let colors = sqlx::query!(
+            "delete from color_settings where id = any($1)",
+            &entities.colors
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        sqlx::query!(
+            "update import_jobs set status = $1 where id = $2",
+            IMPORT_JOB_STATUS_ROLLED_BACK,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let deleted = vec![
+            ("items", items),
+            ("embryos", embryos),
+            ("orders", orders),
+            ("item_inout_bucket", item_buckets),
+            ("embryo_inout_bucket", embryo_buckets),
+            ("cates", cates),
+            ("color_settings", colors),
+        ]
+        .into_iter()
+        .map(|(entity, count)| (entity.to_string(), count as i32))
+        .collect::<HashMap<_, _>>();
+        let rollback = ImportRollbackDto {
+            deleted,
+            updated_count: job.updated_count,
+            msg: match job.updated_count {
+                0 => "".to_string(),
+                count => format!("导入时修改的{}个已有商品不会还原", count),
+            },
+        };
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "import_jobs",
+                id,
+                "rollback",
+                None,
+                snapshot(&rollback),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(rollback)
+    }
+}
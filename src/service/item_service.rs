@@ -1,19 +1,22 @@
 use crate::common::items::calculate_barcode;
 use crate::config::database::{Database, DatabaseTrait};
-use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::constants::{AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE, DEFAULT_PAGE_SIZE};
 use crate::dto::dto_items::{
     DeleteParams, EditParams, InoutBucketParams, InoutListOfBucketParams, InoutParams,
-    ItemInOutBucketDto, ItemInOutDto, ItemSearchParams, ItemStockOutMultiParams, ItemsDto,
-    QueryParams,
+    ItemInOutBucketDto, ItemInOutDto, ItemSearchParams, ItemStockOutMultiParams,
+    ItemTransferParams, ItemsDto, QueryParams,
 };
+use crate::dto::dto_stock::InsufficientStockDto;
 use crate::model::embryo::EmbryoModel;
 use crate::model::items::{ItemInOutBucketModal, ItemsInOutModel, ItemsModel};
 use crate::model::order::OrderItemModel;
 use crate::repository::embryo_repository::{EmbryoRepository, EmbryoRepositoryTrait};
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
 use crate::ERPError::Failed;
 use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
-use sqlx::{Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -21,6 +24,8 @@ use std::sync::Arc;
 pub struct ItemService {
     db: Arc<Database>,
     embryo_repo: EmbryoRepository,
+    audit_service: AuditService,
+    stock_service: StockService,
 }
 
 #[async_trait]
@@ -30,16 +35,33 @@ pub trait ItemServiceTrait {
     async fn get_item_count(&self, params: &QueryParams) -> ERPResult<i32>;
     async fn get_item_with_numbers(&self, numbers: Vec<String>) -> ERPResult<Vec<ItemsModel>>;
     async fn get_item_with_ids(&self, ids: Vec<i32>) -> ERPResult<Vec<ItemsModel>>;
-    async fn edit_item(&self, params: &EditParams) -> ERPResult<()>;
-    async fn delete_item(&self, params: &DeleteParams) -> ERPResult<()>;
-    async fn insert_multiple_items(&self, rows: &[ItemsModel]) -> ERPResult<Vec<ItemsModel>>;
+    async fn edit_item(&self, params: &EditParams, account_id: i32) -> ERPResult<()>;
+    async fn delete_item(&self, params: &DeleteParams, account_id: i32) -> ERPResult<()>;
+    async fn insert_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[ItemsModel],
+    ) -> ERPResult<Vec<ItemsModel>>;
+    /// 导入时修改已有商品, 每个有变化的商品记一条修改前后的操作日志
+    async fn update_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[ItemsModel],
+        account_id: i32,
+    ) -> ERPResult<()>;
     async fn insert_multiple_items_inouts(
         &self,
+        conn: &mut PgConnection,
         rows: &[ItemsInOutModel],
         bucket_id: i32,
     ) -> ERPResult<()>;
     async fn to_items_dto(&self, items: Vec<ItemsModel>) -> ERPResult<Vec<ItemsDto>>;
-    async fn add_item_inout(&self, params: &InoutParams, account_id: i32) -> ERPResult<()>;
+    /// 出库时返回库存不够但按设置允许出库的商品
+    async fn add_item_inout(
+        &self,
+        params: &InoutParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
     async fn inout_list_of_item(
         &self,
         item_id: i32,
@@ -57,15 +79,23 @@ pub trait ItemServiceTrait {
     async fn inout_bucket_count(&self, params: &InoutBucketParams) -> ERPResult<i32>;
     async fn add_inout_bucket(
         &self,
+        conn: &mut PgConnection,
         bucket: ItemInOutBucketModal,
     ) -> ERPResult<ItemInOutBucketModal>;
     async fn stock_out_multiple(
         &self,
         params: &ItemStockOutMultiParams,
         account_id: i32,
-    ) -> ERPResult<()>;
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
+    /// 调拨: 同一个事务里写调出仓库的出库和调入仓库的入库, via 都是 transfer
+    async fn transfer_items(
+        &self,
+        params: &ItemTransferParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
     async fn add_multiple_items_inouts(
         &self,
+        conn: &mut PgConnection,
         items: &[ItemsInOutModel],
     ) -> ERPResult<Vec<ItemsInOutModel>>;
     async fn inout_list_of_bucket(
@@ -81,6 +111,8 @@ impl ItemServiceTrait for ItemService {
         Self {
             db: Arc::clone(db),
             embryo_repo: EmbryoRepository::new(db),
+            audit_service: AuditService::new(db),
+            stock_service: StockService::new(db),
         }
     }
 
@@ -216,7 +248,7 @@ impl ItemServiceTrait for ItemService {
         )
     }
 
-    async fn edit_item(&self, params: &EditParams) -> ERPResult<()> {
+    async fn edit_item(&self, params: &EditParams, account_id: i32) -> ERPResult<()> {
         match params.id {
             0 => {
                 // 新增item
@@ -235,11 +267,14 @@ impl ItemServiceTrait for ItemService {
                     }
                     false => params.barcode.clone(),
                 };
-                sqlx::query!(
+                let mut tx = self.db.get_pool().begin().await?;
+                let item = sqlx::query_as!(
+                    ItemsModel,
                     r#"
                     insert into items (images, name, size, color, cate1_id, cate2_id, unit,
                      price, cost, notes, number, barcode)
-                    values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);
+                    values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                    returning *;
                     "#,
                     &params.images,
                     params.name,
@@ -254,16 +289,33 @@ impl ItemServiceTrait for ItemService {
                     params.number,
                     barcode,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "items",
+                        item.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&item),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 // 修改item
-                sqlx::query!(
+                let item = self.get_item(params.id).await?;
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    ItemsModel,
                     r#"
                     update items set images=$1, name=$2, size=$3, color=$4, cate1_id=$5, cate2_id=$6,
                      unit=$7, price=$8, cost=$9, notes=$10, number=$11, barcode=$12
-                    where id=$13"#,
+                    where id=$13
+                    returning *"#,
                     &params.images,
                     params.name,
                     params.size,
@@ -278,15 +330,30 @@ impl ItemServiceTrait for ItemService {
                     params.barcode,
                     params.id,
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "items",
+                        item.id,
+                        AUDIT_UPDATE,
+                        snapshot(&item),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         };
 
         Ok(())
     }
 
-    async fn delete_item(&self, params: &DeleteParams) -> ERPResult<()> {
+    async fn delete_item(&self, params: &DeleteParams, account_id: i32) -> ERPResult<()> {
+        let item = self.get_item(params.id).await?;
+
         let order_items = sqlx::query_as!(
             OrderItemModel,
             "select * from order_items where item_id = $1",
@@ -299,37 +366,63 @@ impl ItemServiceTrait for ItemService {
             return Err(Failed("产品已有订单数据，删除不合法".to_string()));
         }
 
+        let mut tx = self.db.get_pool().begin().await?;
         let mut bucket_ids = sqlx::query!(
             "select bucket_id from item_inout where item_id = $1",
             params.id
         )
-        .fetch_all(self.db.get_pool())
+        .fetch_all(&mut *tx)
         .await?
         .into_iter()
         .map(|item| item.bucket_id)
         .collect::<Vec<i32>>();
         if !bucket_ids.is_empty() {
+            bucket_ids.sort();
             bucket_ids.dedup();
             sqlx::query!("delete from item_inout where item_id=$1", params.id)
-                .execute(self.db.get_pool())
+                .execute(&mut *tx)
                 .await?;
+            // 只删除已经没有出入库记录的bucket
             sqlx::query!(
-                "delete from item_inout_bucket where id = any($1);",
+                r#"
+                delete from item_inout_bucket b
+                where b.id = any($1)
+                  and not exists (select 1 from item_inout where bucket_id = b.id);
+                "#,
                 &bucket_ids
             )
-            .execute(self.db.get_pool())
+            .execute(&mut *tx)
             .await?;
         }
 
+        sqlx::query!("delete from item_stock where item_id = $1", params.id)
+            .execute(&mut *tx)
+            .await?;
         sqlx::query!("delete from items where id = $1", params.id)
-            .execute(self.db.get_pool())
+            .execute(&mut *tx)
+            .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "items",
+                item.id,
+                AUDIT_DELETE,
+                snapshot(&item),
+                None,
+            )
             .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
     // todo
-    async fn insert_multiple_items(&self, rows: &[ItemsModel]) -> ERPResult<Vec<ItemsModel>> {
+    async fn insert_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[ItemsModel],
+    ) -> ERPResult<Vec<ItemsModel>> {
         let mut query_builder: QueryBuilder<Postgres> =
                     QueryBuilder::new("insert into items (images, name, size, color, cate1_id, cate2_id, unit, price, cost, notes, number, barcode) ");
 
@@ -352,14 +445,82 @@ impl ItemServiceTrait for ItemService {
 
         let items = query_builder
             .build_query_as::<ItemsModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
             .await?;
 
         Ok(items)
     }
 
+    async fn update_multiple_items(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[ItemsModel],
+        account_id: i32,
+    ) -> ERPResult<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let item_ids = rows.iter().map(|item| item.id).collect::<Vec<_>>();
+        let id_to_before = sqlx::query_as!(
+            ItemsModel,
+            "select * from items where id = any($1) order by id for update",
+            &item_ids
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item))
+        .collect::<HashMap<_, _>>();
+
+        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "update items set \
+            images = case when cardinality(v.images) = 0 then items.images else v.images end, \
+            name = v.name, size = v.size, color = v.color, \
+            cate1_id = v.cate1_id, cate2_id = v.cate2_id, unit = v.unit, price = v.price, \
+            cost = v.cost, notes = v.notes, number = v.number from (",
+        );
+
+        query_builder.push_values(rows, |mut b, item| {
+            b.push_bind(item.id)
+                .push_bind(item.images.clone())
+                .push_bind(item.name.clone())
+                .push_bind(item.size.clone())
+                .push_bind(item.color.clone())
+                .push_bind(item.cate1_id)
+                .push_bind(item.cate2_id)
+                .push_bind(item.unit.clone())
+                .push_bind(item.price)
+                .push_bind(item.cost)
+                .push_bind(item.notes.clone())
+                .push_bind(item.number.clone());
+        });
+
+        query_builder.push(
+            ") as v(id, images, name, size, color, cate1_id, cate2_id, unit, price, cost, notes, number) \
+            where items.id = v.id returning items.*",
+        );
+
+        let updated = query_builder
+            .build_query_as::<ItemsModel>()
+            .fetch_all(&mut *conn)
+            .await?;
+        let logs = updated
+            .iter()
+            .filter_map(|item| {
+                let before = snapshot(id_to_before.get(&item.id)?);
+                let after = snapshot(item);
+                (before != after).then_some((item.id, before, after))
+            })
+            .collect::<Vec<_>>();
+        self.audit_service
+            .add_audit_logs(conn, account_id, "items", AUDIT_UPDATE, &logs)
+            .await
+    }
+
     async fn insert_multiple_items_inouts(
         &self,
+        conn: &mut PgConnection,
         rows: &[ItemsInOutModel],
         bucket_id: i32,
     ) -> ERPResult<()> {
@@ -377,26 +538,25 @@ impl ItemServiceTrait for ItemService {
 
         query_builder.push(" returning id;");
 
-        query_builder.build().execute(self.db.get_pool()).await?;
+        query_builder.build().execute(&mut *conn).await?;
+        let stock_rows = rows
+            .iter()
+            .map(|item| (bucket_id, item.item_id, item.count))
+            .collect::<Vec<_>>();
+        self.stock_service
+            .add_item_stock_by_bucket(&mut *conn, &stock_rows)
+            .await?;
         Ok(())
     }
 
     async fn to_items_dto(&self, items: Vec<ItemsModel>) -> ERPResult<Vec<ItemsDto>> {
         let item_ids = items.iter().map(|item| item.id).collect::<Vec<_>>();
-        let item_id_to_count = sqlx::query!(
-            r#"
-            select item_id, sum(count) 
-            from item_inout 
-            where item_id = any($1)
-            group by item_id 
-            "#,
-            &item_ids
-        )
-        .fetch_all(self.db.get_pool())
-        .await?
-        .into_iter()
-        .map(|r| (r.item_id, r.sum.unwrap_or(0) as i32))
-        .collect::<HashMap<_, _>>();
+        let item_id_to_count = self.stock_service.get_item_stock(&item_ids).await?;
+        let item_id_to_reserved = self.stock_service.get_item_reserved(&item_ids).await?;
+        let mut item_id_to_stocks = self
+            .stock_service
+            .get_item_warehouse_stock(&item_ids)
+            .await?;
 
         let cate_id_to_name = sqlx::query!("select id, name from cates")
             .fetch_all(self.db.get_pool())
@@ -433,18 +593,24 @@ impl ItemServiceTrait for ItemService {
                 let cate1 = cate_id_to_name.get(&item.cate1_id).unwrap_or(&empty);
                 let cate2 = cate_id_to_name.get(&item.cate2_id).unwrap_or(&empty);
                 let count = item_id_to_count.get(&item.id).unwrap_or(&0);
+                let reserved = item_id_to_reserved.get(&item.id).unwrap_or(&0);
+                let stocks = item_id_to_stocks.remove(&item.id).unwrap_or_default();
                 let embryo = match number_to_embryo_dto.get(&item.number) {
                     Some(emb) => Some(emb.clone()),
                     None => None,
                 };
-                ItemsDto::from(item, *count, cate1, cate2, embryo)
+                ItemsDto::from(item, *count, *reserved, stocks, cate1, cate2, embryo)
             })
             .collect::<Vec<_>>();
 
         Ok(items_dto)
     }
 
-    async fn add_item_inout(&self, params: &InoutParams, account_id: i32) -> ERPResult<()> {
+    async fn add_item_inout(
+        &self,
+        params: &InoutParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
         let item = sqlx::query_as!(ItemsModel, "select * from items where id=$1", params.id)
             .fetch_one(self.db.get_pool())
             .await
@@ -455,24 +621,43 @@ impl ItemServiceTrait for ItemService {
             _ => -params.count,
         };
 
+        let mut tx = self.db.get_pool().begin().await?;
+        let warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, params.warehouse_id)
+            .await?;
+        let warnings = self
+            .stock_service
+            .check_item_stock_out(
+                &mut tx,
+                warehouse_id,
+                &[(params.id, count)],
+                0,
+                true,
+                account_id,
+            )
+            .await?;
         let bucket_id = sqlx::query!(
             r#"
-            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id)
-            values ($1, $2, $3, $4) 
+            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id, warehouse_id)
+            values ($1, $2, $3, $4, $5)
             returning id"#,
             account_id,
             params.in_out,
             "form",
-            0
+            0,
+            warehouse_id
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?
         .id;
 
-        sqlx::query!(
+        let inout = sqlx::query_as!(
+            ItemsInOutModel,
             r#"
             insert into item_inout (bucket_id, item_id, count, current_cost, current_total) 
-            values ($1, $2, $3, $4, $5);
+            values ($1, $2, $3, $4, $5)
+            returning *;
             "#,
             bucket_id,
             params.id,
@@ -480,10 +665,25 @@ impl ItemServiceTrait for ItemService {
             item.price,
             item.price * count
         )
-        .execute(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?;
+        self.stock_service
+            .add_item_stock(&mut tx, warehouse_id, &[(params.id, count)])
+            .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "item_inout_bucket",
+                bucket_id,
+                AUDIT_CREATE,
+                None,
+                snapshot(&vec![inout]),
+            )
+            .await?;
+        tx.commit().await?;
 
-        Ok(())
+        Ok(warnings)
     }
 
     async fn inout_list_of_item(
@@ -690,21 +890,23 @@ impl ItemServiceTrait for ItemService {
 
     async fn add_inout_bucket(
         &self,
+        conn: &mut PgConnection,
         bucket: ItemInOutBucketModal,
     ) -> ERPResult<ItemInOutBucketModal> {
         let bucket = sqlx::query_as!(
             ItemInOutBucketModal,
             r#"
-            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id) 
-            values ($1, $2, $3, $4) 
+            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id, warehouse_id)
+            values ($1, $2, $3, $4, $5)
             returning *
             "#,
             bucket.account_id,
             bucket.in_true_out_false,
             bucket.via,
-            bucket.order_id
+            bucket.order_id,
+            bucket.warehouse_id
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(bucket)
@@ -714,19 +916,34 @@ impl ItemServiceTrait for ItemService {
         &self,
         params: &ItemStockOutMultiParams,
         account_id: i32,
-    ) -> ERPResult<()> {
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, params.warehouse_id)
+            .await?;
+        let stock_rows = params
+            .items
+            .iter()
+            .map(|item| (item.item_id, -item.count))
+            .collect::<Vec<_>>();
+        let warnings = self
+            .stock_service
+            .check_item_stock_out(&mut tx, warehouse_id, &stock_rows, 0, true, account_id)
+            .await?;
         let bucket_id = sqlx::query!(
             r#"
-            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id) 
-            values ($1, $2, $3, $4) 
+            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id, warehouse_id)
+            values ($1, $2, $3, $4, $5)
             returning id
             "#,
             account_id,
             false,
             "form",
-            0
+            0,
+            warehouse_id
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?
         .id;
 
@@ -737,7 +954,7 @@ impl ItemServiceTrait for ItemService {
             .collect::<Vec<i32>>();
 
         let id_to_cost = sqlx::query!("select id, cost from items where id =any($1)", &item_ids)
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *tx)
             .await?
             .into_iter()
             .map(|item| (item.id, item.cost))
@@ -760,15 +977,148 @@ impl ItemServiceTrait for ItemService {
             })
             .collect::<Vec<_>>();
 
+        let mut inouts = vec![];
         if !item_inouts.is_empty() {
-            self.add_multiple_items_inouts(&item_inouts).await?;
+            inouts = self
+                .add_multiple_items_inouts(&mut tx, &item_inouts)
+                .await?;
         }
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "item_inout_bucket",
+                bucket_id,
+                AUDIT_CREATE,
+                None,
+                snapshot(&inouts),
+            )
+            .await?;
+        tx.commit().await?;
 
-        Ok(())
+        Ok(warnings)
+    }
+
+    async fn transfer_items(
+        &self,
+        params: &ItemTransferParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        if params.items.is_empty() {
+            return Err(ERPError::ParamNeeded("items".to_string()));
+        }
+        if params.from_warehouse_id == params.to_warehouse_id {
+            return Err(ERPError::ParamError(
+                "调出和调入不能是同一个仓库".to_string(),
+            ));
+        }
+        if params.items.iter().any(|item| item.count <= 0) {
+            return Err(ERPError::ParamError("调拨数量要大于0".to_string()));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        let from_warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, Some(params.from_warehouse_id))
+            .await?;
+        let to_warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, Some(params.to_warehouse_id))
+            .await?;
+        let stock_rows = params
+            .items
+            .iter()
+            .map(|item| (item.item_id, -item.count))
+            .collect::<Vec<_>>();
+        let warnings = self
+            .stock_service
+            .check_item_stock_out(
+                &mut tx,
+                from_warehouse_id,
+                &stock_rows,
+                0,
+                false,
+                account_id,
+            )
+            .await?;
+
+        let item_ids = params
+            .items
+            .iter()
+            .map(|item| item.item_id)
+            .collect::<Vec<i32>>();
+        let id_to_cost = sqlx::query!("select id, cost from items where id =any($1)", &item_ids)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|item| (item.id, item.cost))
+            .collect::<HashMap<_, _>>();
+
+        let mut bucket_ids = vec![];
+        let mut inouts = vec![];
+        for (warehouse_id, in_true_out_false) in
+            [(from_warehouse_id, false), (to_warehouse_id, true)]
+        {
+            let bucket = self
+                .add_inout_bucket(
+                    &mut tx,
+                    ItemInOutBucketModal {
+                        id: 0,
+                        account_id,
+                        in_true_out_false,
+                        via: "transfer".to_string(),
+                        order_id: 0,
+                        warehouse_id,
+                        create_time: Default::default(),
+                    },
+                )
+                .await?;
+            let sign = if in_true_out_false { 1 } else { -1 };
+            let item_inouts = params
+                .items
+                .iter()
+                .map(|item| {
+                    let current_cost = id_to_cost.get(&item.item_id).unwrap_or(&0);
+                    ItemsInOutModel {
+                        id: 0,
+                        bucket_id: bucket.id,
+                        item_id: item.item_id,
+                        count: sign * item.count,
+                        current_cost: *current_cost,
+                        current_total: sign * current_cost * item.count,
+                    }
+                })
+                .collect::<Vec<_>>();
+            inouts.extend(
+                self.add_multiple_items_inouts(&mut tx, &item_inouts)
+                    .await?,
+            );
+            bucket_ids.push(bucket.id);
+        }
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "item_inout_bucket",
+                bucket_ids[0],
+                AUDIT_CREATE,
+                None,
+                Some(serde_json::json!({
+                    "from_warehouse_id": from_warehouse_id,
+                    "to_warehouse_id": to_warehouse_id,
+                    "bucket_ids": bucket_ids,
+                    "inouts": inouts,
+                })),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(warnings)
     }
 
     async fn add_multiple_items_inouts(
         &self,
+        conn: &mut PgConnection,
         items: &[ItemsInOutModel],
     ) -> ERPResult<Vec<ItemsInOutModel>> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
@@ -787,9 +1137,15 @@ impl ItemServiceTrait for ItemService {
 
         let items = query_builder
             .build_query_as::<ItemsInOutModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
+            .await?;
+        let stock_rows = items
+            .iter()
+            .map(|item| (item.bucket_id, item.item_id, item.count))
+            .collect::<Vec<_>>();
+        self.stock_service
+            .add_item_stock_by_bucket(&mut *conn, &stock_rows)
             .await?;
-        // query_builder.build().execute(self.db.get_pool()).await?;
 
         Ok(items)
     }
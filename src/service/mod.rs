@@ -1,7 +1,12 @@
 pub mod account_service;
+pub mod audit_service;
 pub mod cates_service;
 pub mod customer_service;
 pub mod embryo_service;
+pub mod excel_template_service;
+pub mod import_job_service;
 pub mod item_service;
 pub mod order_service;
 pub mod settings_service;
+pub mod stock_service;
+pub mod stocktake_service;
@@ -1,37 +1,51 @@
 use crate::config::database::{Database, DatabaseTrait};
-use crate::constants::DEFAULT_PAGE_SIZE;
+use crate::config::parameter;
+use crate::constants::{
+    AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE, DEFAULT_PAGE_SIZE, DEFAULT_WAREHOUSE_ID,
+    ORDER_STATUS_CANCELLED, ORDER_STATUS_COMPLETED, ORDER_STATUS_CONFIRMED, ORDER_STATUS_DRAFT,
+    ORDER_STATUS_SHIPPED, ORDER_STATUS_TO_NAME,
+};
 use crate::dto::dto_orders::{
-    CreateOrderParams, OrderDto, OrderInListDto, OrderItemDto, OrderItemsParams, QueryParams,
+    CreateOrderParams, EditOrderParams, OrderDto, OrderInListDto, OrderItemDto, OrderItemsParams,
+    QueryParams,
 };
+use crate::dto::dto_stock::InsufficientStockDto;
 use crate::model::order::{ImportedOrderItemModel, OrderItemModel, OrderModel};
-use crate::ERPResult;
+use crate::service::audit_service::{AuditService, AuditServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
+use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDate, Utc};
-use sqlx::{query, FromRow, Postgres, QueryBuilder};
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
 use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct OrderService {
     pub db: Arc<Database>,
+    audit_service: AuditService,
+    stock_service: StockService,
 }
 
 #[async_trait]
 pub trait OrderServiceTrait {
     fn new(db: &Arc<Database>) -> Self;
     async fn create_order(&self, account_id: i32, params: &CreateOrderParams) -> ERPResult<i32>;
-    async fn add_order(&self, order: &OrderModel) -> ERPResult<i32>;
+    async fn add_order(&self, conn: &mut PgConnection, order: &OrderModel) -> ERPResult<i32>;
     async fn insert_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[OrderItemsParams],
         order_id: i32,
     ) -> ERPResult<Vec<OrderItemModel>>;
     async fn insert_just_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[OrderItemModel],
     ) -> ERPResult<Vec<OrderItemModel>>;
     async fn insert_just_imported_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[ImportedOrderItemModel],
     ) -> ERPResult<Vec<ImportedOrderItemModel>>;
     async fn get_order_list(&self, params: &QueryParams) -> ERPResult<Vec<OrderInListDto>>;
@@ -45,13 +59,50 @@ pub trait OrderServiceTrait {
         &self,
         order_id: i32,
     ) -> ERPResult<Vec<ImportedOrderItemModel>>;
-    async fn delete_order(&self, order_id: i32) -> ERPResult<()>;
-    async fn delete_import_order(&self, order_id: i32) -> ERPResult<()>;
+    /// 修改已发货的订单会重新出库, 返回库存不足但允许出库的商品
+    async fn edit_order(
+        &self,
+        params: &EditOrderParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
+    async fn delete_order(&self, order_id: i32, account_id: i32) -> ERPResult<()>;
+    async fn delete_import_order(&self, order_id: i32, account_id: i32) -> ERPResult<()>;
+    async fn confirm_order(&self, order_id: i32, account_id: i32) -> ERPResult<()>;
+    /// 返回库存不够但按设置允许出库的商品
+    /// 从 warehouse_id 仓库发货, 不传是默认仓库
+    async fn ship_order(
+        &self,
+        order_id: i32,
+        warehouse_id: Option<i32>,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
+    async fn complete_order(&self, order_id: i32, account_id: i32) -> ERPResult<()>;
+    async fn cancel_order(&self, order_id: i32, account_id: i32) -> ERPResult<()>;
+}
+
+/// 订单号: 前缀 + 日期 + 当天序号(至少4位)
+pub fn format_order_no(prefix: &str, day: NaiveDate, seq: i32) -> String {
+    format!("{}{}{:04}", prefix, day.format("%Y%m%d"), seq)
+}
+
+/// 订单状态是否允许从 from 变为 to
+pub fn order_status_can_change(from: i32, to: i32) -> bool {
+    matches!(
+        (from, to),
+        (ORDER_STATUS_DRAFT, ORDER_STATUS_CONFIRMED)
+            | (ORDER_STATUS_CONFIRMED, ORDER_STATUS_SHIPPED)
+            | (ORDER_STATUS_SHIPPED, ORDER_STATUS_COMPLETED)
+            | (ORDER_STATUS_DRAFT, ORDER_STATUS_CANCELLED)
+            | (ORDER_STATUS_CONFIRMED, ORDER_STATUS_CANCELLED)
+            | (ORDER_STATUS_SHIPPED, ORDER_STATUS_CANCELLED)
+    )
 }
 
 #[derive(Debug, Serialize, FromRow)]
 pub struct TmpOrderInListDto {
     pub id: i32,
+    pub order_no: String,
+    pub status: i32,
     pub account_id: i32,
     pub account: String,
     pub customer_id: i32,
@@ -61,40 +112,273 @@ pub struct TmpOrderInListDto {
     pub create_time: DateTime<Utc>,
 }
 
+impl OrderService {
+    /// 分配当天的下一个订单号, 行锁保证并发下不会重复, 需要和创建订单在同一个事务里
+    /// 前缀是 ORDER_NO_PREFIX, 已有订单的订单号是 20231025000000_order_no 迁移按 SO 补的
+    async fn generate_order_no(&self, conn: &mut PgConnection) -> ERPResult<String> {
+        let row = sqlx::query!(
+            r#"
+            insert into order_no_sequences (day, seq) values (current_date, 1)
+            on conflict (day) do update set seq = order_no_sequences.seq + 1
+            returning day, seq
+            "#
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(format_order_no(
+            &parameter::get_or("ORDER_NO_PREFIX", "SO"),
+            row.day,
+            row.seq,
+        ))
+    }
+
+    /// 锁住订单并修改状态, 返回修改前的订单
+    async fn change_order_status(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+        to_status: i32,
+    ) -> ERPResult<OrderModel> {
+        let order = sqlx::query_as!(
+            OrderModel,
+            "select * from orders where id = $1 for update",
+            order_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(ERPError::NotFound("订单".to_string()))?;
+
+        if order.tp != 0 {
+            return Err(ERPError::Failed("导入的订单不能修改状态".to_string()));
+        }
+        if !order_status_can_change(order.status, to_status) {
+            return Err(ERPError::Failed(format!(
+                "订单当前状态为{}, 不能改为{}",
+                ORDER_STATUS_TO_NAME.get(&order.status).unwrap_or(&""),
+                ORDER_STATUS_TO_NAME.get(&to_status).unwrap_or(&"")
+            )));
+        }
+
+        sqlx::query!(
+            "update orders set status = $1 where id = $2",
+            to_status,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(order)
+    }
+
+    /// 按订单商品生成出库(发货)或退回入库(取消已发货订单)记录, 返回bucket id
+    async fn add_order_inout_bucket(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+        account_id: i32,
+        warehouse_id: i32,
+        in_true_out_false: bool,
+    ) -> ERPResult<i32> {
+        let bucket_id = sqlx::query!(
+            r#"
+            insert into item_inout_bucket (account_id, in_true_out_false, via, order_id, warehouse_id)
+            values ($1, $2, $3, $4, $5)
+            returning id
+            "#,
+            account_id,
+            in_true_out_false,
+            "order",
+            order_id,
+            warehouse_id
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .id;
+
+        self.insert_order_inouts(conn, bucket_id, order_id, in_true_out_false)
+            .await?;
+
+        Ok(bucket_id)
+    }
+
+    /// 订单商品出库前检查库存, 按负库存设置拒绝或返回警告
+    async fn check_order_stock_out(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+        warehouse_id: i32,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        let stock_rows = sqlx::query!(
+            "select item_id, count from order_items where order_id = $1",
+            order_id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| (row.item_id, -row.count))
+        .collect::<Vec<_>>();
+
+        self.stock_service
+            .check_item_stock_out(conn, warehouse_id, &stock_rows, order_id, true, account_id)
+            .await
+    }
+
+    /// 把订单商品写入bucket的出入库明细: 出库数量为负, 入库为正
+    async fn insert_order_inouts(
+        &self,
+        conn: &mut PgConnection,
+        bucket_id: i32,
+        order_id: i32,
+        in_true_out_false: bool,
+    ) -> ERPResult<()> {
+        let sign = if in_true_out_false { 1 } else { -1 };
+        let stock_rows = sqlx::query!(
+            r#"
+            insert into item_inout (bucket_id, item_id, count, current_cost, current_total)
+            select $1, oi.item_id, $2 * oi.count, i.cost, $2 * oi.count * i.cost
+            from order_items oi, items i
+            where oi.item_id = i.id and oi.order_id = $3
+            order by oi.id
+            returning bucket_id, item_id, count
+            "#,
+            bucket_id,
+            sign,
+            order_id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| (row.bucket_id, row.item_id, row.count))
+        .collect::<Vec<_>>();
+        self.stock_service
+            .add_item_stock_by_bucket(&mut *conn, &stock_rows)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 删掉订单的出入库记录, 库存按明细加回去(出库的加回, 入库的减掉)
+    async fn delete_order_inout_buckets(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+    ) -> ERPResult<()> {
+        let bucket_ids = sqlx::query!(
+            "select id from item_inout_bucket where order_id=$1",
+            order_id
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|item| item.id)
+        .collect::<Vec<i32>>();
+        if !bucket_ids.is_empty() {
+            let stock_rows = sqlx::query!(
+                "delete from item_inout where bucket_id = any($1) returning bucket_id, item_id, count",
+                &bucket_ids
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.bucket_id, row.item_id, -row.count))
+            .collect::<Vec<_>>();
+            self.stock_service
+                .add_item_stock_by_bucket(&mut *conn, &stock_rows)
+                .await?;
+            sqlx::query!(
+                "delete from item_inout_bucket where id = any($1)",
+                &bucket_ids
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn add_order_status_audit_log(
+        &self,
+        conn: &mut PgConnection,
+        account_id: i32,
+        order: &OrderModel,
+        to_status: i32,
+        bucket_id: Option<i32>,
+    ) -> ERPResult<()> {
+        self.audit_service
+            .add_audit_log(
+                conn,
+                account_id,
+                "orders",
+                order.id,
+                AUDIT_UPDATE,
+                Some(serde_json::json!({"status": order.status})),
+                Some(serde_json::json!({"status": to_status, "bucket_id": bucket_id})),
+            )
+            .await
+    }
+}
+
 #[async_trait]
 impl OrderServiceTrait for OrderService {
     fn new(db: &Arc<Database>) -> Self {
-        Self { db: Arc::clone(db) }
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+            stock_service: StockService::new(db),
+        }
     }
 
     async fn create_order(&self, account_id: i32, params: &CreateOrderParams) -> ERPResult<i32> {
-        let order = sqlx::query!(
-            "insert into orders(account_id, customer_id) values ($1, $2) returning *",
+        let mut tx = self.db.get_pool().begin().await?;
+        let order_no = self.generate_order_no(&mut tx).await?;
+        let order = sqlx::query_as!(
+            OrderModel,
+            "insert into orders(account_id, customer_id, order_no) values ($1, $2, $3) returning *",
             account_id,
-            params.customer_id
+            params.customer_id,
+            order_no
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *tx)
         .await?;
 
-        let order_items = self.insert_order_items(&params.items, order.id).await?;
+        let order_items = self
+            .insert_order_items(&mut tx, &params.items, order.id)
+            .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "orders",
+                order.id,
+                AUDIT_CREATE,
+                None,
+                Some(serde_json::json!({"order": order, "items": order_items})),
+            )
+            .await?;
+        tx.commit().await?;
 
         Ok(order.id)
     }
 
-    async fn add_order(&self, order: &OrderModel) -> ERPResult<i32> {
+    async fn add_order(&self, conn: &mut PgConnection, order: &OrderModel) -> ERPResult<i32> {
+        let order_no = self.generate_order_no(conn).await?;
         let order = sqlx::query!(
             r#"
-            insert into orders(account_id, customer_id, order_date, tp, delivery_date)
-            values ($1, $2, $3, $4, $5) 
+            insert into orders(account_id, customer_id, order_no, order_date, tp, status, delivery_date)
+            values ($1, $2, $3, $4, $5, $6, $7) 
             returning *;
             "#,
             order.account_id,
             order.customer_id,
+            order_no,
             order.order_date,
             order.tp,
+            order.status,
             order.delivery_date
         )
-        .fetch_one(self.db.get_pool())
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(order.id)
@@ -102,13 +386,14 @@ impl OrderServiceTrait for OrderService {
 
     async fn insert_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[OrderItemsParams],
         order_id: i32,
     ) -> ERPResult<Vec<OrderItemModel>> {
         let item_ids = items.iter().map(|item| item.item_id).collect::<Vec<_>>();
         let item_id_to_origin_price =
             sqlx::query!("select id, price from items where id = any($1)", &item_ids)
-                .fetch_all(self.db.get_pool())
+                .fetch_all(&mut *conn)
                 .await?
                 .into_iter()
                 .map(|item| (item.id, item.price))
@@ -135,7 +420,7 @@ impl OrderServiceTrait for OrderService {
 
         let res = query_builder
             .build_query_as::<OrderItemModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
             .await?;
 
         Ok(res)
@@ -143,6 +428,7 @@ impl OrderServiceTrait for OrderService {
 
     async fn insert_just_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[OrderItemModel],
     ) -> ERPResult<Vec<OrderItemModel>> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
@@ -162,7 +448,7 @@ impl OrderServiceTrait for OrderService {
 
         let res = query_builder
             .build_query_as::<OrderItemModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
             .await?;
 
         Ok(res)
@@ -170,6 +456,7 @@ impl OrderServiceTrait for OrderService {
 
     async fn insert_just_imported_order_items(
         &self,
+        conn: &mut PgConnection,
         items: &[ImportedOrderItemModel],
     ) -> ERPResult<Vec<ImportedOrderItemModel>> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
@@ -193,7 +480,7 @@ impl OrderServiceTrait for OrderService {
 
         let res = query_builder
             .build_query_as::<ImportedOrderItemModel>()
-            .fetch_all(self.db.get_pool())
+            .fetch_all(&mut *conn)
             .await?;
 
         Ok(res)
@@ -217,6 +504,15 @@ impl OrderServiceTrait for OrderService {
                 .push_bind(params.customer_id);
         }
 
+        if let Some(status) = params.status {
+            sql.push(" and o.status = ").push_bind(status);
+        }
+
+        if let Some(order_no) = params.order_no.as_deref().filter(|s| !s.is_empty()) {
+            sql.push(" and o.order_no like ")
+                .push_bind(format!("%{}%", order_no));
+        }
+
         if !params.create_time_st.is_empty() && !params.create_time_ed.is_empty() {
             sql.push(" and o.create_time >= ")
                 .push_bind(&params.create_time_st)
@@ -295,6 +591,8 @@ impl OrderServiceTrait for OrderService {
                 let images = order_id_to_images.get(&order.id).unwrap_or(&empty_str_arr);
                 OrderInListDto {
                     id: order.id,
+                    status: order.status,
+                    order_no: order.order_no,
                     account_id: order.account_id,
                     account: order.account,
                     customer_id: order.customer_id,
@@ -329,6 +627,15 @@ impl OrderServiceTrait for OrderService {
                 .push_bind(params.customer_id);
         }
 
+        if let Some(status) = params.status {
+            sql.push(" and o.status = ").push_bind(status);
+        }
+
+        if let Some(order_no) = params.order_no.as_deref().filter(|s| !s.is_empty()) {
+            sql.push(" and o.order_no like ")
+                .push_bind(format!("%{}%", order_no));
+        }
+
         if !params.create_time_st.is_empty() && !params.create_time_ed.is_empty() {
             sql.push(" and o.create_time >= ")
                 .push_bind(&params.create_time_st)
@@ -366,6 +673,15 @@ impl OrderServiceTrait for OrderService {
                 .push_bind(params.customer_id);
         }
 
+        if let Some(status) = params.status {
+            sql.push(" and o.status = ").push_bind(status);
+        }
+
+        if let Some(order_no) = params.order_no.as_deref().filter(|s| !s.is_empty()) {
+            sql.push(" and o.order_no like ")
+                .push_bind(format!("%{}%", order_no));
+        }
+
         if !params.create_time_st.is_empty() && !params.create_time_ed.is_empty() {
             sql.push(" and o.create_time >= ")
                 .push_bind(&params.create_time_st)
@@ -444,6 +760,8 @@ impl OrderServiceTrait for OrderService {
                 let images = order_id_to_images.get(&order.id).unwrap_or(&empty_str_arr);
                 OrderInListDto {
                     id: order.id,
+                    status: order.status,
+                    order_no: order.order_no,
                     account_id: order.account_id,
                     account: order.account,
                     customer_id: order.customer_id,
@@ -478,6 +796,15 @@ impl OrderServiceTrait for OrderService {
                 .push_bind(params.customer_id);
         }
 
+        if let Some(status) = params.status {
+            sql.push(" and o.status = ").push_bind(status);
+        }
+
+        if let Some(order_no) = params.order_no.as_deref().filter(|s| !s.is_empty()) {
+            sql.push(" and o.order_no like ")
+                .push_bind(format!("%{}%", order_no));
+        }
+
         if !params.create_time_st.is_empty() && !params.create_time_ed.is_empty() {
             sql.push(" and o.create_time >= ")
                 .push_bind(&params.create_time_st)
@@ -518,7 +845,7 @@ impl OrderServiceTrait for OrderService {
             r#"
             select 
                 oi.*,
-                i.images, i.size, i.number, i.name, i.color
+                i.images, i.size, i.number, i.name, i.color, i.unit
             from order_items oi, items i
             where oi.item_id = i.id
                 and oi.order_id=$1
@@ -542,43 +869,353 @@ impl OrderServiceTrait for OrderService {
         .await?)
     }
 
-    async fn delete_order(&self, order_id: i32) -> ERPResult<()> {
-        sqlx::query!("delete from orders where id = $1", order_id)
-            .execute(self.db.get_pool())
+    async fn edit_order(
+        &self,
+        params: &EditOrderParams,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        if params.items.is_empty() {
+            return Err(ERPError::ParamNeeded("items".to_string()));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = sqlx::query_as!(
+            OrderModel,
+            "select * from orders where id = $1 for update",
+            params.id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ERPError::NotFound("订单".to_string()))?;
+
+        if order.tp != 0 {
+            return Err(ERPError::Failed("导入的订单不能修改".to_string()));
+        }
+        if order.status == ORDER_STATUS_COMPLETED || order.status == ORDER_STATUS_CANCELLED {
+            return Err(ERPError::Failed(format!(
+                "订单{}, 不能修改",
+                ORDER_STATUS_TO_NAME.get(&order.status).unwrap_or(&"")
+            )));
+        }
+
+        let new_order = sqlx::query_as!(
+            OrderModel,
+            r#"
+            update orders set customer_id = $1, order_date = $2, delivery_date = $3
+            where id = $4
+            returning *
+            "#,
+            params.customer_id,
+            params.order_date,
+            params.delivery_date,
+            params.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // 商品整体替换, 金额按新的数量和折后价重新计算
+        let order_items = sqlx::query_as!(
+            OrderItemModel,
+            "delete from order_items where order_id = $1 returning *",
+            params.id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let new_order_items = self
+            .insert_order_items(&mut tx, &params.items, params.id)
+            .await?;
+
+        // 已确认的订单, 占用的库存跟着订单商品一起调整
+        if order.status == ORDER_STATUS_CONFIRMED {
+            self.stock_service
+                .reserve_order_items(&mut tx, params.id)
+                .await?;
+        }
+
+        // 已发货的订单, 出库记录跟着订单商品一起调整
+        let mut warnings = vec![];
+        if order.status == ORDER_STATUS_SHIPPED {
+            let bucket = sqlx::query!(
+                r#"
+                select id, warehouse_id from item_inout_bucket 
+                where order_id = $1 and via = 'order' and in_true_out_false = false
+                order by id desc limit 1
+                "#,
+                params.id
+            )
+            .fetch_optional(&mut *tx)
             .await?;
-        sqlx::query!("delete from order_items where order_id = $1", order_id)
-            .execute(self.db.get_pool())
+
+            // 从原来发货的仓库出库
+            match bucket {
+                Some(bucket) => {
+                    let stock_rows = sqlx::query!(
+                        "delete from item_inout where bucket_id = $1 returning item_id, count",
+                        bucket.id
+                    )
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .into_iter()
+                    .map(|row| (row.item_id, -row.count))
+                    .collect::<Vec<_>>();
+                    self.stock_service
+                        .add_item_stock(&mut tx, bucket.warehouse_id, &stock_rows)
+                        .await?;
+                    warnings = self
+                        .check_order_stock_out(&mut tx, params.id, bucket.warehouse_id, account_id)
+                        .await?;
+                    self.insert_order_inouts(&mut tx, bucket.id, params.id, false)
+                        .await?;
+                }
+                None => {
+                    warnings = self
+                        .check_order_stock_out(&mut tx, params.id, DEFAULT_WAREHOUSE_ID, account_id)
+                        .await?;
+                    self.add_order_inout_bucket(
+                        &mut tx,
+                        params.id,
+                        account_id,
+                        DEFAULT_WAREHOUSE_ID,
+                        false,
+                    )
+                    .await?;
+                }
+            }
+        }
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "orders",
+                params.id,
+                AUDIT_UPDATE,
+                Some(serde_json::json!({"order": order, "items": order_items})),
+                Some(serde_json::json!({"order": new_order, "items": new_order_items})),
+            )
             .await?;
-        let res = sqlx::query!(
-            "select id from item_inout_bucket where order_id=$1",
+        tx.commit().await?;
+
+        Ok(warnings)
+    }
+
+    async fn delete_order(&self, order_id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = sqlx::query_as!(
+            OrderModel,
+            "delete from orders where id = $1 returning *",
             order_id
         )
-        .fetch_optional(self.db.get_pool())
+        .fetch_optional(&mut *tx)
         .await?;
-        if res.is_some() {
-            let bucket_id = res.unwrap().id;
+        let order_items = sqlx::query_as!(
+            OrderItemModel,
+            "delete from order_items where order_id = $1 returning *",
+            order_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        self.stock_service
+            .release_order_reservation(&mut tx, order_id)
+            .await?;
+        self.delete_order_inout_buckets(&mut tx, order_id).await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "orders",
+                order_id,
+                AUDIT_DELETE,
+                Some(serde_json::json!({"order": order, "items": order_items})),
+                None,
+            )
+            .await?;
+        tx.commit().await?;
 
-            sqlx::query!("delete from item_inout_bucket where id = $1", bucket_id)
-                .execute(self.db.get_pool())
-                .await?;
-            sqlx::query!("delete from item_inout where bucket_id = $1", bucket_id)
-                .execute(self.db.get_pool())
-                .await?;
-        }
+        Ok(())
+    }
+
+    async fn delete_import_order(&self, order_id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = sqlx::query_as!(
+            OrderModel,
+            "delete from orders where id = $1 returning *",
+            order_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ERPError::NotFound("订单".to_string()))?;
+        let imported_items = sqlx::query_as!(
+            ImportedOrderItemModel,
+            "delete from import_order_items where order_id = $1 returning *",
+            order_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        // 旧格式导入的订单有关联商品和导入时的出库记录
+        let order_items = sqlx::query_as!(
+            OrderItemModel,
+            "delete from order_items where order_id = $1 returning *",
+            order_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        self.delete_order_inout_buckets(&mut tx, order_id).await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "orders",
+                order_id,
+                AUDIT_DELETE,
+                Some(serde_json::json!({
+                    "order": order,
+                    "items": imported_items,
+                    "order_items": order_items,
+                })),
+                None,
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn confirm_order(&self, order_id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = self
+            .change_order_status(&mut tx, order_id, ORDER_STATUS_CONFIRMED)
+            .await?;
+        self.stock_service
+            .reserve_order_items(&mut tx, order_id)
+            .await?;
+        self.add_order_status_audit_log(&mut tx, account_id, &order, ORDER_STATUS_CONFIRMED, None)
+            .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    async fn delete_import_order(&self, order_id: i32) -> ERPResult<()> {
-        sqlx::query!("delete from orders where id = $1", order_id)
-            .execute(self.db.get_pool())
+    async fn ship_order(
+        &self,
+        order_id: i32,
+        warehouse_id: Option<i32>,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = self
+            .change_order_status(&mut tx, order_id, ORDER_STATUS_SHIPPED)
             .await?;
-        sqlx::query!(
-            "delete from import_order_items where order_id = $1",
-            order_id
+        let warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, warehouse_id)
+            .await?;
+        self.stock_service
+            .release_order_reservation(&mut tx, order_id)
+            .await?;
+        let warnings = self
+            .check_order_stock_out(&mut tx, order_id, warehouse_id, account_id)
+            .await?;
+        let bucket_id = self
+            .add_order_inout_bucket(&mut tx, order_id, account_id, warehouse_id, false)
+            .await?;
+        self.add_order_status_audit_log(
+            &mut tx,
+            account_id,
+            &order,
+            ORDER_STATUS_SHIPPED,
+            Some(bucket_id),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(warnings)
+    }
+
+    async fn complete_order(&self, order_id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = self
+            .change_order_status(&mut tx, order_id, ORDER_STATUS_COMPLETED)
+            .await?;
+        self.add_order_status_audit_log(&mut tx, account_id, &order, ORDER_STATUS_COMPLETED, None)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn cancel_order(&self, order_id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let order = self
+            .change_order_status(&mut tx, order_id, ORDER_STATUS_CANCELLED)
+            .await?;
+        self.stock_service
+            .release_order_reservation(&mut tx, order_id)
+            .await?;
+        // 已经发货的订单, 取消时把货退回库存
+        let mut bucket_id = None;
+        if order.status == ORDER_STATUS_SHIPPED {
+            // 退回发货的仓库
+            let warehouse_id = sqlx::query!(
+                r#"
+                select warehouse_id from item_inout_bucket
+                where order_id = $1 and via = 'order' and in_true_out_false = false
+                order by id desc limit 1
+                "#,
+                order_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .map_or(DEFAULT_WAREHOUSE_ID, |bucket| bucket.warehouse_id);
+            bucket_id = Some(
+                self.add_order_inout_bucket(&mut tx, order_id, account_id, warehouse_id, true)
+                    .await?,
+            );
+        }
+        self.add_order_status_audit_log(
+            &mut tx,
+            account_id,
+            &order,
+            ORDER_STATUS_CANCELLED,
+            bucket_id,
         )
-        .execute(self.db.get_pool())
         .await?;
+        tx.commit().await?;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_order_no() {
+        let day = NaiveDate::from_ymd_opt(2023, 10, 25).unwrap();
+        assert_eq!(format_order_no("SO", day, 1), "SO202310250001");
+        assert_eq!(format_order_no("", day, 12345), "2023102512345");
+    }
+
+    #[test]
+    fn test_order_status_can_change() {
+        assert!(order_status_can_change(
+            ORDER_STATUS_DRAFT,
+            ORDER_STATUS_CONFIRMED
+        ));
+        assert!(order_status_can_change(
+            ORDER_STATUS_SHIPPED,
+            ORDER_STATUS_CANCELLED
+        ));
+        assert!(!order_status_can_change(
+            ORDER_STATUS_DRAFT,
+            ORDER_STATUS_SHIPPED
+        ));
+        assert!(!order_status_can_change(
+            ORDER_STATUS_COMPLETED,
+            ORDER_STATUS_CANCELLED
+        ));
+        assert!(!order_status_can_change(
+            ORDER_STATUS_CANCELLED,
+            ORDER_STATUS_CONFIRMED
+        ));
+    }
+}
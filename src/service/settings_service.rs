@@ -1,18 +1,26 @@
 use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{
+    AUDIT_CREATE, AUDIT_DELETE, AUDIT_UPDATE, DEFAULT_WAREHOUSE_ID, NEGATIVE_STOCK_ACCOUNTS,
+    NEGATIVE_STOCK_REJECT, NEGATIVE_STOCK_WARN,
+};
 use crate::dto::dto_settings::{
-    ColorEditParams, CustomerTypeEditParams, GlobalSettingsUpdateParams,
+    ColorEditParams, CustomerTypeEditParams, GlobalSettingsUpdateParams, WarehouseEditParams,
 };
 use crate::dto::GenericDeleteParams;
-use crate::model::settings::{ColorSettingsModel, CustomerTypeModel, GlobalSettingsModel};
+use crate::model::settings::{
+    ColorSettingsModel, CustomerTypeModel, GlobalSettingsModel, WarehouseModel,
+};
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
 use crate::{ERPError, ERPResult};
 use async_trait::async_trait;
-use sqlx::{Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
 use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct SettingsService {
     pub db: Arc<Database>,
+    audit_service: AuditService,
 }
 
 #[async_trait]
@@ -20,22 +28,51 @@ pub trait SettingsServiceTrait {
     fn new(db: &Arc<Database>) -> Self;
     async fn get_all_color_sort_by_color(&self) -> ERPResult<Vec<ColorSettingsModel>>;
     async fn get_all_color_to_values(&self) -> ERPResult<Vec<ColorSettingsModel>>;
-    async fn edit_color_to_value(&self, params: &ColorEditParams) -> ERPResult<()>;
-    async fn delete_color_to_value(&self, params: &GenericDeleteParams) -> ERPResult<()>;
+    async fn edit_color_to_value(&self, params: &ColorEditParams, account_id: i32)
+        -> ERPResult<()>;
+    async fn delete_color_to_value(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
     async fn add_multiple_color_to_value(
         &self,
+        conn: &mut PgConnection,
         colors: Vec<String>,
     ) -> ERPResult<HashMap<String, i32>>;
     async fn get_global_settings(&self) -> ERPResult<GlobalSettingsModel>;
-    async fn update_global_settings(&self, params: &GlobalSettingsUpdateParams) -> ERPResult<()>;
+    async fn update_global_settings(
+        &self,
+        params: &GlobalSettingsUpdateParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
     async fn get_customer_types(&self) -> ERPResult<Vec<CustomerTypeModel>>;
-    async fn edit_customer_type(&self, params: &CustomerTypeEditParams) -> ERPResult<()>;
-    async fn delete_customer_type(&self, params: &GenericDeleteParams) -> ERPResult<()>;
+    async fn edit_customer_type(
+        &self,
+        params: &CustomerTypeEditParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
+    async fn delete_customer_type(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
+    async fn get_warehouses(&self) -> ERPResult<Vec<WarehouseModel>>;
+    async fn edit_warehouse(&self, params: &WarehouseEditParams, account_id: i32) -> ERPResult<()>;
+    /// 默认仓库和有出入库记录的仓库不能删
+    async fn delete_warehouse(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()>;
 }
 #[async_trait]
 impl SettingsServiceTrait for SettingsService {
     fn new(db: &Arc<Database>) -> Self {
-        Self { db: Arc::clone(db) }
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+        }
     }
     async fn get_all_color_sort_by_color(&self) -> ERPResult<Vec<ColorSettingsModel>> {
         let css = sqlx::query_as!(
@@ -59,7 +96,11 @@ impl SettingsServiceTrait for SettingsService {
         Ok(css)
     }
 
-    async fn edit_color_to_value(&self, params: &ColorEditParams) -> ERPResult<()> {
+    async fn edit_color_to_value(
+        &self,
+        params: &ColorEditParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
         let existing = self.get_all_color_to_values().await?;
         match params.id {
             0 => {
@@ -89,13 +130,28 @@ impl SettingsServiceTrait for SettingsService {
                 //     )));
                 // }
 
-                sqlx::query!(
-                    "insert into color_settings (color, value) values ($1, $2)",
+                let mut tx = self.db.get_pool().begin().await?;
+                let color = sqlx::query_as!(
+                    ColorSettingsModel,
+                    "insert into color_settings (color, value) values ($1, $2) returning *",
                     params.color.to_ascii_uppercase(),
                     params.value
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "color_settings",
+                        color.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&color),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 if !existing
@@ -123,34 +179,76 @@ impl SettingsServiceTrait for SettingsService {
                 //     )));
                 // }
 
-                sqlx::query!(
-                    "update color_settings set color=$1, value=$2 where id = $3",
+                let before = existing.iter().find(|&item| item.id == params.id);
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    ColorSettingsModel,
+                    "update color_settings set color=$1, value=$2 where id = $3 returning *",
                     params.color.to_ascii_uppercase(),
                     params.value,
                     params.id
                 )
-                .execute(self.db.get_pool())
-                .await?;
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "color_settings",
+                        params.id,
+                        AUDIT_UPDATE,
+                        before.and_then(snapshot),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         }
 
         Ok(())
     }
 
-    async fn delete_color_to_value(&self, params: &GenericDeleteParams) -> ERPResult<()> {
-        sqlx::query!("delete from color_settings where id = $1", params.id)
-            .execute(self.db.get_pool())
-            .await?;
+    async fn delete_color_to_value(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let color = sqlx::query_as!(
+            ColorSettingsModel,
+            "delete from color_settings where id = $1 returning *",
+            params.id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(color) = color {
+            self.audit_service
+                .add_audit_log(
+                    &mut tx,
+                    account_id,
+                    "color_settings",
+                    color.id,
+                    AUDIT_DELETE,
+                    snapshot(&color),
+                    None,
+                )
+                .await?;
+        }
+        tx.commit().await?;
 
         Ok(())
     }
 
     async fn add_multiple_color_to_value(
         &self,
+        conn: &mut PgConnection,
         colors: Vec<String>,
     ) -> ERPResult<HashMap<String, i32>> {
         let mut max = sqlx::query!("select max(value) from color_settings")
-            .fetch_one(self.db.get_pool())
+            .fetch_one(&mut *conn)
             .await?
             .max
             .unwrap_or(0) as i32;
@@ -178,7 +276,7 @@ impl SettingsServiceTrait for SettingsService {
 
             Ok(query_builder
                 .build_query_as::<ColorSettingsModel>()
-                .fetch_all(self.db.get_pool())
+                .fetch_all(conn)
                 .await?
                 .into_iter()
                 .map(|item| (item.color, item.value))
@@ -199,18 +297,66 @@ impl SettingsServiceTrait for SettingsService {
         Ok(global_settings)
     }
 
-    async fn update_global_settings(&self, params: &GlobalSettingsUpdateParams) -> ERPResult<()> {
+    async fn update_global_settings(
+        &self,
+        params: &GlobalSettingsUpdateParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        let before = self.get_global_settings().await?;
+
+        if let Some(policy) = params.negative_stock_policy {
+            if ![
+                NEGATIVE_STOCK_REJECT,
+                NEGATIVE_STOCK_WARN,
+                NEGATIVE_STOCK_ACCOUNTS,
+            ]
+            .contains(&policy)
+            {
+                return Err(ERPError::ParamError(format!(
+                    "库存不足策略不对: {}",
+                    policy
+                )));
+            }
+        }
+
         let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("update global_settings set ");
-        if params.units.is_some() {
-            sql.push("units=")
-                .push_bind(params.units.as_ref())
-                .push(",");
+        let mut fields = sql.separated(", ");
+        if let Some(units) = &params.units {
+            fields.push("units=").push_bind_unseparated(units);
         }
-        if params.accounts.is_some() {
-            sql.push("accounts=").push_bind(params.accounts.as_ref());
+        if let Some(accounts) = &params.accounts {
+            fields.push("accounts=").push_bind_unseparated(accounts);
         }
+        if let Some(policy) = params.negative_stock_policy {
+            fields
+                .push("negative_stock_policy=")
+                .push_bind_unseparated(policy);
+        }
+        if let Some(account_ids) = &params.negative_stock_account_ids {
+            fields
+                .push("negative_stock_account_ids=")
+                .push_bind_unseparated(account_ids);
+        }
+        sql.push(" where id=").push_bind(before.id);
+        sql.push(" returning *");
 
-        sql.build().execute(self.db.get_pool()).await?;
+        let mut tx = self.db.get_pool().begin().await?;
+        let after = sql
+            .build_query_as::<GlobalSettingsModel>()
+            .fetch_one(&mut *tx)
+            .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "global_settings",
+                before.id,
+                AUDIT_UPDATE,
+                snapshot(&before),
+                snapshot(&after),
+            )
+            .await?;
+        tx.commit().await?;
 
         Ok(())
     }
@@ -226,7 +372,11 @@ impl SettingsServiceTrait for SettingsService {
         Ok(customer_types)
     }
 
-    async fn edit_customer_type(&self, params: &CustomerTypeEditParams) -> ERPResult<()> {
+    async fn edit_customer_type(
+        &self,
+        params: &CustomerTypeEditParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
         let customer_types = self.get_customer_types().await?;
         if !customer_types
             .iter()
@@ -243,30 +393,66 @@ impl SettingsServiceTrait for SettingsService {
         match params.id {
             0 => {
                 // insert
-                sqlx::query!(
-                    "insert into customer_types (ty_pe) values ($1)",
+                let mut tx = self.db.get_pool().begin().await?;
+                let customer_type = sqlx::query_as!(
+                    CustomerTypeModel,
+                    "insert into customer_types (ty_pe) values ($1) returning *",
                     params.ty_pe
                 )
-                .execute(self.db.get_pool())
+                .fetch_one(&mut *tx)
                 .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "customer_types",
+                        customer_type.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&customer_type),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
             _ => {
                 // update
-                sqlx::query!(
-                    "update customer_types set ty_pe=$1 where id=$2",
+                let before = customer_types.iter().find(|item| item.id == params.id);
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    CustomerTypeModel,
+                    "update customer_types set ty_pe=$1 where id=$2 returning *",
                     params.ty_pe,
                     params.id
                 )
-                .execute(self.db.get_pool())
-                .await?;
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "customer_types",
+                        params.id,
+                        AUDIT_UPDATE,
+                        before.and_then(snapshot),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
             }
         }
 
         Ok(())
     }
 
-    async fn delete_customer_type(&self, params: &GenericDeleteParams) -> ERPResult<()> {
-        let _ = sqlx::query_as!(
+    async fn delete_customer_type(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        let customer_type = sqlx::query_as!(
             CustomerTypeModel,
             "select * from customer_types where id=$1",
             params.id
@@ -285,9 +471,161 @@ impl SettingsServiceTrait for SettingsService {
             return Err(ERPError::Failed("删除不合法，有对应的客户存在".to_string()));
         }
 
+        let mut tx = self.db.get_pool().begin().await?;
         sqlx::query!("delete from customer_types where id = $1", params.id)
-            .execute(self.db.get_pool())
+            .execute(&mut *tx)
+            .await?;
+
+        if let Ok(customer_type) = customer_type {
+            self.audit_service
+                .add_audit_log(
+                    &mut tx,
+                    account_id,
+                    "customer_types",
+                    customer_type.id,
+                    AUDIT_DELETE,
+                    snapshot(&customer_type),
+                    None,
+                )
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_warehouses(&self) -> ERPResult<Vec<WarehouseModel>> {
+        let warehouses = sqlx::query_as!(WarehouseModel, "select * from warehouses order by id")
+            .fetch_all(self.db.get_pool())
+            .await?;
+
+        Ok(warehouses)
+    }
+
+    async fn edit_warehouse(&self, params: &WarehouseEditParams, account_id: i32) -> ERPResult<()> {
+        if params.name.trim().is_empty() {
+            return Err(ERPError::ParamNeeded("仓库名".to_string()));
+        }
+        let warehouses = self.get_warehouses().await?;
+        if warehouses
+            .iter()
+            .any(|item| item.id != params.id && item.name == params.name)
+        {
+            return Err(ERPError::AlreadyExists(format!(
+                "仓库: {} 已存在",
+                params.name
+            )));
+        }
+
+        match params.id {
+            0 => {
+                let mut tx = self.db.get_pool().begin().await?;
+                let warehouse = sqlx::query_as!(
+                    WarehouseModel,
+                    "insert into warehouses (name, notes) values ($1, $2) returning *",
+                    params.name,
+                    params.notes
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "warehouses",
+                        warehouse.id,
+                        AUDIT_CREATE,
+                        None,
+                        snapshot(&warehouse),
+                    )
+                    .await?;
+                tx.commit().await?;
+            }
+            _ => {
+                let before = warehouses.iter().find(|item| item.id == params.id);
+                let mut tx = self.db.get_pool().begin().await?;
+                let updated = sqlx::query_as!(
+                    WarehouseModel,
+                    "update warehouses set name=$1, notes=$2 where id=$3 returning *",
+                    params.name,
+                    params.notes,
+                    params.id
+                )
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+                self.audit_service
+                    .add_audit_log(
+                        &mut tx,
+                        account_id,
+                        "warehouses",
+                        params.id,
+                        AUDIT_UPDATE,
+                        before.and_then(snapshot),
+                        snapshot(&updated),
+                    )
+                    .await?;
+                tx.commit().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_warehouse(
+        &self,
+        params: &GenericDeleteParams,
+        account_id: i32,
+    ) -> ERPResult<()> {
+        if params.id == DEFAULT_WAREHOUSE_ID {
+            return Err(ERPError::Failed("默认仓库不能删除".to_string()));
+        }
+        let warehouse = sqlx::query_as!(
+            WarehouseModel,
+            "select * from warehouses where id=$1",
+            params.id
+        )
+        .fetch_optional(self.db.get_pool())
+        .await?
+        .ok_or(ERPError::NotFound("数据不存在，请刷新".to_string()))?;
+
+        let used = sqlx::query!(
+            r#"
+            select exists(select 1 from item_inout_bucket where warehouse_id = $1)
+                or exists(select 1 from embryo_inout_bucket where warehouse_id = $1)
+                or exists(select 1 from stocktakes where warehouse_id = $1)
+                as "used!"
+            "#,
+            params.id
+        )
+        .fetch_one(self.db.get_pool())
+        .await?
+        .used;
+        if used {
+            return Err(ERPError::Failed(
+                "删除不合法，这个仓库有出入库或盘点记录".to_string(),
+            ));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        sqlx::query!("delete from warehouses where id = $1", params.id)
+            .execute(&mut *tx)
+            .await?;
+
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "warehouses",
+                warehouse.id,
+                AUDIT_DELETE,
+                snapshot(&warehouse),
+                None,
+            )
             .await?;
+        tx.commit().await?;
 
         Ok(())
     }
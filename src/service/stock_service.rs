@@ -0,0 +1,671 @@
+use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{DEFAULT_WAREHOUSE_ID, NEGATIVE_STOCK_ACCOUNTS, NEGATIVE_STOCK_WARN};
+use crate::dto::dto_stock::{InsufficientStockDto, StockDriftDto, WarehouseStockDto};
+use crate::{ERPError, ERPResult};
+use async_trait::async_trait;
+use sqlx::PgConnection;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct StockService {
+    db: Arc<Database>,
+}
+
+#[async_trait]
+pub trait StockServiceTrait {
+    fn new(db: &Arc<Database>) -> Self;
+    /// 没传仓库的用默认仓库, 传了的检查仓库存在
+    async fn get_warehouse_id(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: Option<i32>,
+    ) -> ERPResult<i32>;
+    /// 写完出入库记录后在同一个事务里调用, rows: (商品id, 数量), 出库为负
+    async fn add_item_stock(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+    ) -> ERPResult<()>;
+    /// rows: (库存胚id, 数量), 出库为负
+    async fn add_embryo_stock(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+    ) -> ERPResult<()>;
+    /// rows: (bucket id, 商品id, 数量), 按bucket所在的仓库更新, 要在删除bucket之前调用
+    async fn add_item_stock_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+    ) -> ERPResult<()>;
+    /// rows: (bucket id, 库存胚id, 数量)
+    async fn add_embryo_stock_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+    ) -> ERPResult<()>;
+    /// 出库前在同一个事务里调用, rows 同 add_item_stock: 锁住库存行, 不够的按设置拒绝或者返回提示
+    /// 可用的是仓库库存减去订单占用的, order_id 是发货的订单, 它自己的占用不算; 不是订单出库传0
+    /// 调拨传 reserved false 只看仓库实际库存: 占用不分仓库, 调拨不改变总的可用数
+    async fn check_item_stock_out(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+        order_id: i32,
+        reserved: bool,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
+    /// rows 同 add_item_stock_by_bucket, 按bucket所在的仓库检查, 要在删除bucket之前调用
+    async fn check_item_stock_out_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>>;
+    /// 订单确认时按订单商品占用库存, 已有的占用整体替换
+    async fn reserve_order_items(&self, conn: &mut PgConnection, order_id: i32) -> ERPResult<()>;
+    /// 订单发货/取消/删除时释放占用的库存
+    async fn release_order_reservation(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+    ) -> ERPResult<()>;
+    /// 所有仓库加起来的库存
+    async fn get_item_stock(&self, item_ids: &[i32]) -> ERPResult<HashMap<i32, i32>>;
+    async fn get_item_warehouse_stock(
+        &self,
+        item_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, Vec<WarehouseStockDto>>>;
+    /// 已确认未发货的订单占用的数量
+    async fn get_item_reserved(&self, item_ids: &[i32]) -> ERPResult<HashMap<i32, i32>>;
+    async fn get_embryo_stock(&self, embryo_ids: &[i32]) -> ERPResult<HashMap<i32, i32>>;
+    async fn get_embryo_warehouse_stock(
+        &self,
+        embryo_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, Vec<WarehouseStockDto>>>;
+    /// 按出入库记录重新算库存, 返回对不上的; fix 为true时改成算出来的
+    async fn reconcile_item_stock(&self, fix: bool) -> ERPResult<Vec<StockDriftDto>>;
+    async fn reconcile_embryo_stock(&self, fix: bool) -> ERPResult<Vec<StockDriftDto>>;
+}
+
+/// 同一个id的数量合并, 一条upsert不能改同一行两次
+fn merge_stock_rows(rows: &[(i32, i32)]) -> (Vec<i32>, Vec<i32>) {
+    let mut id_to_count: HashMap<i32, i32> = HashMap::new();
+    for (id, count) in rows.iter() {
+        *id_to_count.entry(*id).or_insert(0) += count;
+    }
+    let mut merged = id_to_count.into_iter().collect::<Vec<_>>();
+    // 固定加锁顺序, 并发更新时不会死锁
+    merged.sort();
+    merged.into_iter().unzip()
+}
+
+/// 按bucket所在的仓库分组, 找不到bucket的算默认仓库; 仓库按id排序, 加锁顺序固定
+fn group_stock_rows_by_warehouse(
+    bucket_id_to_warehouse_id: &HashMap<i32, i32>,
+    rows: &[(i32, i32, i32)],
+) -> BTreeMap<i32, Vec<(i32, i32)>> {
+    let mut warehouse_to_rows: BTreeMap<i32, Vec<(i32, i32)>> = BTreeMap::new();
+    for (bucket_id, id, count) in rows.iter() {
+        let warehouse_id = bucket_id_to_warehouse_id
+            .get(bucket_id)
+            .unwrap_or(&DEFAULT_WAREHOUSE_ID);
+        warehouse_to_rows
+            .entry(*warehouse_id)
+            .or_default()
+            .push((*id, *count));
+    }
+    warehouse_to_rows
+}
+
+/// 修正对不上的库存: 按仓库分组, (id, 算出来的 - 库存表里的)
+fn drift_rows_by_warehouse(drifts: &[StockDriftDto]) -> BTreeMap<i32, Vec<(i32, i32)>> {
+    let mut warehouse_to_rows: BTreeMap<i32, Vec<(i32, i32)>> = BTreeMap::new();
+    for drift in drifts.iter() {
+        warehouse_to_rows
+            .entry(drift.warehouse_id)
+            .or_default()
+            .push((drift.id, drift.expected - drift.actual));
+    }
+    warehouse_to_rows
+}
+
+/// 库存不够时能不能继续出库
+fn allow_negative_stock(policy: i32, account_ids: &[i32], account_id: i32) -> bool {
+    match policy {
+        NEGATIVE_STOCK_WARN => true,
+        NEGATIVE_STOCK_ACCOUNTS => account_ids.contains(&account_id),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl StockServiceTrait for StockService {
+    fn new(db: &Arc<Database>) -> Self {
+        Self { db: Arc::clone(db) }
+    }
+
+    async fn get_warehouse_id(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: Option<i32>,
+    ) -> ERPResult<i32> {
+        let Some(warehouse_id) = warehouse_id else {
+            return Ok(DEFAULT_WAREHOUSE_ID);
+        };
+        sqlx::query!("select id from warehouses where id = $1", warehouse_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(ERPError::NotFound("仓库".to_string()))?;
+
+        Ok(warehouse_id)
+    }
+
+    async fn add_item_stock(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+    ) -> ERPResult<()> {
+        let (item_ids, counts) = merge_stock_rows(rows);
+        if item_ids.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            insert into item_stock (item_id, count, warehouse_id)
+            select *, $3 from unnest($1::int[], $2::int[])
+            on conflict (item_id, warehouse_id) do update
+            set count = item_stock.count + excluded.count, update_time = now()
+            "#,
+            &item_ids,
+            &counts,
+            warehouse_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_embryo_stock(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+    ) -> ERPResult<()> {
+        let (embryo_ids, counts) = merge_stock_rows(rows);
+        if embryo_ids.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            insert into embryo_stock (embryo_id, count, warehouse_id)
+            select *, $3 from unnest($1::int[], $2::int[])
+            on conflict (embryo_id, warehouse_id) do update
+            set count = embryo_stock.count + excluded.count, update_time = now()
+            "#,
+            &embryo_ids,
+            &counts,
+            warehouse_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn add_item_stock_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+    ) -> ERPResult<()> {
+        let bucket_ids = rows.iter().map(|row| row.0).collect::<Vec<_>>();
+        let bucket_id_to_warehouse_id = sqlx::query!(
+            "select id, warehouse_id from item_inout_bucket where id = any($1)",
+            &bucket_ids
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| (r.id, r.warehouse_id))
+        .collect::<HashMap<_, _>>();
+
+        for (warehouse_id, rows) in group_stock_rows_by_warehouse(&bucket_id_to_warehouse_id, rows)
+        {
+            self.add_item_stock(conn, warehouse_id, &rows).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn add_embryo_stock_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+    ) -> ERPResult<()> {
+        let bucket_ids = rows.iter().map(|row| row.0).collect::<Vec<_>>();
+        let bucket_id_to_warehouse_id = sqlx::query!(
+            "select id, warehouse_id from embryo_inout_bucket where id = any($1)",
+            &bucket_ids
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| (r.id, r.warehouse_id))
+        .collect::<HashMap<_, _>>();
+
+        for (warehouse_id, rows) in group_stock_rows_by_warehouse(&bucket_id_to_warehouse_id, rows)
+        {
+            self.add_embryo_stock(conn, warehouse_id, &rows).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_item_stock_out(
+        &self,
+        conn: &mut PgConnection,
+        warehouse_id: i32,
+        rows: &[(i32, i32)],
+        order_id: i32,
+        reserved: bool,
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        let (item_ids, counts) = merge_stock_rows(rows);
+        let item_id_to_requested = item_ids
+            .into_iter()
+            .zip(counts)
+            .filter(|(_, count)| *count < 0)
+            .map(|(item_id, count)| (item_id, -count))
+            .collect::<HashMap<i32, i32>>();
+        if item_id_to_requested.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut item_ids = item_id_to_requested.keys().copied().collect::<Vec<i32>>();
+        item_ids.sort();
+
+        // 先补上没有的库存行再按id顺序锁住, 并发出库时后来的要等前面的提交
+        sqlx::query!(
+            r#"
+            insert into item_stock (item_id, warehouse_id) select unnest($1::int[]), $2
+            on conflict (item_id, warehouse_id) do nothing
+            "#,
+            &item_ids,
+            warehouse_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        // 占用不分仓库, 整个减掉
+        let stocks = sqlx::query!(
+            r#"
+            select s.item_id, i.number, i.color,
+                s.count - coalesce((
+                    select sum(r.count) from item_reservations r
+                    where r.item_id = s.item_id and r.order_id <> $3 and $4
+                ), 0)::int as "available!"
+            from item_stock s, items i
+            where s.item_id = i.id and s.item_id = any($1) and s.warehouse_id = $2
+            order by s.item_id
+            for update of s
+            "#,
+            &item_ids,
+            warehouse_id,
+            order_id,
+            reserved
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let shortages = stocks
+            .into_iter()
+            .filter_map(|stock| {
+                let requested = *item_id_to_requested.get(&stock.item_id)?;
+                (stock.available < requested).then_some(InsufficientStockDto {
+                    item_id: stock.item_id,
+                    number: stock.number,
+                    color: stock.color,
+                    requested,
+                    available: stock.available,
+                })
+            })
+            .collect::<Vec<_>>();
+        if shortages.is_empty() {
+            return Ok(shortages);
+        }
+
+        let settings = sqlx::query!(
+            r#"
+            select negative_stock_policy, negative_stock_account_ids
+            from global_settings order by id limit 1
+            "#
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        match allow_negative_stock(
+            settings.negative_stock_policy,
+            &settings.negative_stock_account_ids,
+            account_id,
+        ) {
+            true => Ok(shortages),
+            false => Err(ERPError::InsufficientStock(shortages)),
+        }
+    }
+
+    async fn check_item_stock_out_by_bucket(
+        &self,
+        conn: &mut PgConnection,
+        rows: &[(i32, i32, i32)],
+        account_id: i32,
+    ) -> ERPResult<Vec<InsufficientStockDto>> {
+        let bucket_ids = rows.iter().map(|row| row.0).collect::<Vec<_>>();
+        let bucket_id_to_warehouse_id = sqlx::query!(
+            "select id, warehouse_id from item_inout_bucket where id = any($1)",
+            &bucket_ids
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|r| (r.id, r.warehouse_id))
+        .collect::<HashMap<_, _>>();
+
+        let mut shortages = vec![];
+        for (warehouse_id, rows) in group_stock_rows_by_warehouse(&bucket_id_to_warehouse_id, rows)
+        {
+            shortages.extend(
+                self.check_item_stock_out(conn, warehouse_id, &rows, 0, true, account_id)
+                    .await?,
+            );
+        }
+
+        Ok(shortages)
+    }
+
+    async fn reserve_order_items(&self, conn: &mut PgConnection, order_id: i32) -> ERPResult<()> {
+        self.release_order_reservation(conn, order_id).await?;
+        sqlx::query!(
+            r#"
+            insert into item_reservations (order_id, item_id, count)
+            select order_id, item_id, sum(count)
+            from order_items
+            where order_id = $1
+            group by order_id, item_id
+            "#,
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn release_order_reservation(
+        &self,
+        conn: &mut PgConnection,
+        order_id: i32,
+    ) -> ERPResult<()> {
+        sqlx::query!(
+            "delete from item_reservations where order_id = $1",
+            order_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_item_stock(&self, item_ids: &[i32]) -> ERPResult<HashMap<i32, i32>> {
+        let item_id_to_count = sqlx::query!(
+            r#"
+            select item_id, sum(count)::int as "count!"
+            from item_stock
+            where item_id = any($1)
+            group by item_id
+            "#,
+            item_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|r| (r.item_id, r.count))
+        .collect::<HashMap<_, _>>();
+
+        Ok(item_id_to_count)
+    }
+
+    async fn get_item_warehouse_stock(
+        &self,
+        item_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, Vec<WarehouseStockDto>>> {
+        let mut item_id_to_stocks: HashMap<i32, Vec<WarehouseStockDto>> = HashMap::new();
+        sqlx::query!(
+            r#"
+            select s.item_id, s.warehouse_id, w.name, s.count
+            from item_stock s, warehouses w
+            where s.warehouse_id = w.id and s.item_id = any($1)
+            order by s.warehouse_id
+            "#,
+            item_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .for_each(|r| {
+            item_id_to_stocks
+                .entry(r.item_id)
+                .or_default()
+                .push(WarehouseStockDto {
+                    warehouse_id: r.warehouse_id,
+                    warehouse: r.name,
+                    count: r.count,
+                })
+        });
+
+        Ok(item_id_to_stocks)
+    }
+
+    async fn get_item_reserved(&self, item_ids: &[i32]) -> ERPResult<HashMap<i32, i32>> {
+        let item_id_to_reserved = sqlx::query!(
+            r#"
+            select item_id, sum(count)::int as "count!"
+            from item_reservations
+            where item_id = any($1)
+            group by item_id
+            "#,
+            item_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|r| (r.item_id, r.count))
+        .collect::<HashMap<_, _>>();
+
+        Ok(item_id_to_reserved)
+    }
+
+    async fn get_embryo_stock(&self, embryo_ids: &[i32]) -> ERPResult<HashMap<i32, i32>> {
+        let embryo_id_to_count = sqlx::query!(
+            r#"
+            select embryo_id, sum(count)::int as "count!"
+            from embryo_stock
+            where embryo_id = any($1)
+            group by embryo_id
+            "#,
+            embryo_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|r| (r.embryo_id, r.count))
+        .collect::<HashMap<_, _>>();
+
+        Ok(embryo_id_to_count)
+    }
+
+    async fn get_embryo_warehouse_stock(
+        &self,
+        embryo_ids: &[i32],
+    ) -> ERPResult<HashMap<i32, Vec<WarehouseStockDto>>> {
+        let mut embryo_id_to_stocks: HashMap<i32, Vec<WarehouseStockDto>> = HashMap::new();
+        sqlx::query!(
+            r#"
+            select s.embryo_id, s.warehouse_id, w.name, s.count
+            from embryo_stock s, warehouses w
+            where s.warehouse_id = w.id and s.embryo_id = any($1)
+            order by s.warehouse_id
+            "#,
+            embryo_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .for_each(|r| {
+            embryo_id_to_stocks
+                .entry(r.embryo_id)
+                .or_default()
+                .push(WarehouseStockDto {
+                    warehouse_id: r.warehouse_id,
+                    warehouse: r.name,
+                    count: r.count,
+                })
+        });
+
+        Ok(embryo_id_to_stocks)
+    }
+
+    async fn reconcile_item_stock(&self, fix: bool) -> ERPResult<Vec<StockDriftDto>> {
+        let mut tx = self.db.get_pool().begin().await?;
+        // 挡住并发的出入库, 算的过程中库存不会变
+        sqlx::query!("lock table item_stock in share row exclusive mode")
+            .execute(&mut *tx)
+            .await?;
+
+        let drifts = sqlx::query_as!(
+            StockDriftDto,
+            r#"
+            select i.id, i.number, i.color,
+                coalesce(h.warehouse_id, s.warehouse_id) as "warehouse_id!",
+                coalesce(h.count, 0)::int as "expected!", coalesce(s.count, 0) as "actual!"
+            from (
+                select ii.item_id, coalesce(b.warehouse_id, $1) as warehouse_id, sum(ii.count) as count
+                from item_inout ii
+                left join item_inout_bucket b on ii.bucket_id = b.id
+                group by 1, 2
+            ) h
+            full join item_stock s on s.item_id = h.item_id and s.warehouse_id = h.warehouse_id
+            join items i on i.id = coalesce(h.item_id, s.item_id)
+            where coalesce(h.count, 0) <> coalesce(s.count, 0)
+            order by i.id, 4
+            "#,
+            DEFAULT_WAREHOUSE_ID
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if fix {
+            for (warehouse_id, rows) in drift_rows_by_warehouse(&drifts) {
+                self.add_item_stock(&mut tx, warehouse_id, &rows).await?;
+            }
+            sqlx::query!("delete from item_stock where item_id not in (select id from items)")
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(drifts)
+    }
+
+    async fn reconcile_embryo_stock(&self, fix: bool) -> ERPResult<Vec<StockDriftDto>> {
+        let mut tx = self.db.get_pool().begin().await?;
+        sqlx::query!("lock table embryo_stock in share row exclusive mode")
+            .execute(&mut *tx)
+            .await?;
+
+        let drifts = sqlx::query_as!(
+            StockDriftDto,
+            r#"
+            select e.id, e.number, e.color,
+                coalesce(h.warehouse_id, s.warehouse_id) as "warehouse_id!",
+                coalesce(h.count, 0)::int as "expected!", coalesce(s.count, 0) as "actual!"
+            from (
+                select ei.embryo_id, coalesce(b.warehouse_id, $1) as warehouse_id, sum(ei.count) as count
+                from embryo_inout ei
+                left join embryo_inout_bucket b on ei.bucket_id = b.id
+                group by 1, 2
+            ) h
+            full join embryo_stock s
+                on s.embryo_id = h.embryo_id and s.warehouse_id = h.warehouse_id
+            join embryos e on e.id = coalesce(h.embryo_id, s.embryo_id)
+            where coalesce(h.count, 0) <> coalesce(s.count, 0)
+            order by e.id, 4
+            "#,
+            DEFAULT_WAREHOUSE_ID
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if fix {
+            for (warehouse_id, rows) in drift_rows_by_warehouse(&drifts) {
+                self.add_embryo_stock(&mut tx, warehouse_id, &rows).await?;
+            }
+            sqlx::query!(
+                "delete from embryo_stock where embryo_id not in (select id from embryos)"
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(drifts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::{
+        DEFAULT_WAREHOUSE_ID, NEGATIVE_STOCK_ACCOUNTS, NEGATIVE_STOCK_REJECT, NEGATIVE_STOCK_WARN,
+    };
+    use crate::service::stock_service::{
+        allow_negative_stock, group_stock_rows_by_warehouse, merge_stock_rows,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_merge_stock_rows() {
+        let (ids, counts) = merge_stock_rows(&[(3, 10), (1, -5), (3, -2), (2, 0)]);
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(counts, vec![-5, 0, 8]);
+        assert_eq!(merge_stock_rows(&[]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_group_stock_rows_by_warehouse() {
+        let bucket_id_to_warehouse_id = HashMap::from([(1, 3), (2, 2), (3, 3)]);
+        let grouped = group_stock_rows_by_warehouse(
+            &bucket_id_to_warehouse_id,
+            &[(1, 10, 5), (2, 10, -1), (3, 11, 2), (9, 12, 4)],
+        );
+        assert_eq!(
+            grouped.into_iter().collect::<Vec<_>>(),
+            vec![
+                (DEFAULT_WAREHOUSE_ID, vec![(12, 4)]),
+                (2, vec![(10, -1)]),
+                (3, vec![(10, 5), (11, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allow_negative_stock() {
+        assert!(!allow_negative_stock(NEGATIVE_STOCK_REJECT, &[1], 1));
+        assert!(allow_negative_stock(NEGATIVE_STOCK_WARN, &[], 1));
+        assert!(allow_negative_stock(NEGATIVE_STOCK_ACCOUNTS, &[1, 3], 3));
+        assert!(!allow_negative_stock(NEGATIVE_STOCK_ACCOUNTS, &[1, 3], 2));
+    }
+}
@@ -0,0 +1,583 @@
+use crate::config::database::{Database, DatabaseTrait};
+use crate::constants::{
+    AUDIT_CREATE, AUDIT_UPDATE, DEFAULT_PAGE_SIZE, STOCKTAKE_STATUS_CANCELLED,
+    STOCKTAKE_STATUS_COUNTING, STOCKTAKE_STATUS_POSTED,
+};
+use crate::dto::dto_excel::StocktakeExcelDto;
+use crate::dto::dto_stocktake::{
+    QueryParams, StocktakeCountParams, StocktakeDetailDto, StocktakeDto, StocktakeItemDto,
+    StocktakeSheetRowDto, StocktakeStartParams,
+};
+use crate::excel::common::RowErrors;
+use crate::excel::parse_stocktake::J_TO_NAME;
+use crate::model::items::{ItemInOutBucketModal, ItemsInOutModel};
+use crate::model::stocktake::StocktakeModel;
+use crate::service::audit_service::{snapshot, AuditService, AuditServiceTrait};
+use crate::service::item_service::{ItemService, ItemServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
+use crate::{ERPError, ERPResult};
+use async_trait::async_trait;
+use itertools::Itertools;
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct StocktakeService {
+    pub db: Arc<Database>,
+    audit_service: AuditService,
+    stock_service: StockService,
+    item_service: ItemService,
+}
+
+#[async_trait]
+pub trait StocktakeServiceTrait {
+    fn new(db: &Arc<Database>) -> Self;
+    /// 开始盘点, 同一个仓库同时只能有一个盘点中的
+    async fn start_stocktake(
+        &self,
+        params: &StocktakeStartParams,
+        account_id: i32,
+    ) -> ERPResult<StocktakeDto>;
+    async fn get_stocktake_list(&self, params: &QueryParams) -> ERPResult<Vec<StocktakeDto>>;
+    async fn get_stocktake_count(&self, params: &QueryParams) -> ERPResult<i32>;
+    /// 盘点详情和每个商品的差异
+    async fn get_stocktake_detail(&self, id: i32) -> ERPResult<StocktakeDetailDto>;
+    /// 盘点表: 仓库里有库存的和已经盘过的商品
+    async fn get_stocktake_sheet(&self, id: i32) -> ERPResult<Vec<StocktakeSheetRowDto>>;
+    /// 录入盘点数量, 按商品id或条码
+    async fn count_items(&self, params: &StocktakeCountParams, account_id: i32) -> ERPResult<()>;
+    /// 上传的盘点表, 按条码或编号+颜色找商品, 找不到的行一起返回
+    async fn count_items_from_excel(
+        &self,
+        id: i32,
+        sheet: &str,
+        rows: &[StocktakeExcelDto],
+        account_id: i32,
+    ) -> ERPResult<i32>;
+    /// 过账: 按差异生成一次 via 为 stocktake 的出入库记录, 盘点关闭
+    async fn post_stocktake(&self, id: i32, account_id: i32) -> ERPResult<StocktakeDto>;
+    async fn cancel_stocktake(&self, id: i32, account_id: i32) -> ERPResult<()>;
+}
+
+/// 同一个商品录了多次的, 以最后一次为准; 按商品id排序
+fn merge_counted_rows(rows: &[(i32, i32)]) -> (Vec<i32>, Vec<i32>) {
+    rows.iter()
+        .copied()
+        .collect::<BTreeMap<i32, i32>>()
+        .into_iter()
+        .unzip()
+}
+
+/// 锁住盘点中的记录, 已过账/取消的不能再改
+async fn lock_counting_stocktake(conn: &mut PgConnection, id: i32) -> ERPResult<StocktakeModel> {
+    let stocktake = sqlx::query_as!(
+        StocktakeModel,
+        "select * from stocktakes where id = $1 for update",
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ERPError::NotFound("盘点".to_string()))?;
+    if stocktake.status != STOCKTAKE_STATUS_COUNTING {
+        return Err(ERPError::Failed("盘点已结束, 不能再修改".to_string()));
+    }
+
+    Ok(stocktake)
+}
+
+/// 录入时记下这个仓库当时的库存, 过账按 盘点数量 - 录入时的库存 调整, 录入后的出入库不受影响
+async fn save_counted_rows(
+    conn: &mut PgConnection,
+    stocktake: &StocktakeModel,
+    rows: &[(i32, i32)],
+    account_id: i32,
+) -> ERPResult<()> {
+    let (item_ids, counts) = merge_counted_rows(rows);
+    sqlx::query!(
+        r#"
+        insert into stocktake_items (stocktake_id, item_id, counted, system_count, account_id)
+        select $1, data.item_id, data.counted, coalesce(s.count, 0), $5
+        from unnest($2::int[], $3::int[]) as data(item_id, counted)
+        left join item_stock s on s.item_id = data.item_id and s.warehouse_id = $4
+        on conflict (stocktake_id, item_id)
+        do update set counted = excluded.counted, system_count = excluded.system_count,
+            account_id = excluded.account_id, update_time = now()
+        "#,
+        stocktake.id,
+        &item_ids,
+        &counts,
+        stocktake.warehouse_id,
+        account_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+fn push_filters(sql: &mut QueryBuilder<Postgres>, params: &QueryParams) {
+    sql.push(" where 1 = 1 ");
+    if let Some(warehouse_id) = params.warehouse_id.filter(|id| *id != 0) {
+        sql.push(" and warehouse_id = ").push_bind(warehouse_id);
+    }
+    if let Some(status) = params.status {
+        sql.push(" and status = ").push_bind(status);
+    }
+}
+
+impl StocktakeService {
+    async fn to_stocktake_dtos(
+        &self,
+        stocktakes: Vec<StocktakeModel>,
+    ) -> ERPResult<Vec<StocktakeDto>> {
+        let ids = stocktakes.iter().map(|item| item.id).collect::<Vec<_>>();
+        let account_ids = stocktakes
+            .iter()
+            .map(|item| item.account_id)
+            .collect::<Vec<_>>();
+
+        let id_to_account = sqlx::query!(
+            "select id, name from accounts where id = any($1)",
+            &account_ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect::<HashMap<i32, String>>();
+        let id_to_warehouse = sqlx::query!("select id, name from warehouses")
+            .fetch_all(self.db.get_pool())
+            .await?
+            .into_iter()
+            .map(|item| (item.id, item.name))
+            .collect::<HashMap<i32, String>>();
+        let id_to_item_count = sqlx::query!(
+            r#"
+            select stocktake_id, count(1) as "count!" from stocktake_items
+            where stocktake_id = any($1)
+            group by stocktake_id
+            "#,
+            &ids
+        )
+        .fetch_all(self.db.get_pool())
+        .await?
+        .into_iter()
+        .map(|item| (item.stocktake_id, item.count as i32))
+        .collect::<HashMap<i32, i32>>();
+
+        let empty = "".to_string();
+        let dtos = stocktakes
+            .into_iter()
+            .map(|item| {
+                let warehouse = id_to_warehouse.get(&item.warehouse_id).unwrap_or(&empty);
+                let account = id_to_account.get(&item.account_id).unwrap_or(&empty);
+                let item_count = id_to_item_count.get(&item.id).unwrap_or(&0);
+                StocktakeDto::from(item, warehouse, account, *item_count)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(dtos)
+    }
+
+    async fn get_stocktake_dto(&self, id: i32) -> ERPResult<StocktakeDto> {
+        let stocktake =
+            sqlx::query_as!(StocktakeModel, "select * from stocktakes where id = $1", id)
+                .fetch_optional(self.db.get_pool())
+                .await?
+                .ok_or(ERPError::NotFound("盘点".to_string()))?;
+
+        self.to_stocktake_dtos(vec![stocktake])
+            .await?
+            .pop()
+            .ok_or(ERPError::NotFound("盘点".to_string()))
+    }
+}
+
+#[async_trait]
+impl StocktakeServiceTrait for StocktakeService {
+    fn new(db: &Arc<Database>) -> Self {
+        Self {
+            db: Arc::clone(db),
+            audit_service: AuditService::new(db),
+            stock_service: StockService::new(db),
+            item_service: ItemService::new(db),
+        }
+    }
+
+    async fn start_stocktake(
+        &self,
+        params: &StocktakeStartParams,
+        account_id: i32,
+    ) -> ERPResult<StocktakeDto> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let warehouse_id = self
+            .stock_service
+            .get_warehouse_id(&mut tx, params.warehouse_id)
+            .await?;
+        let counting = sqlx::query!(
+            "select id from stocktakes where warehouse_id = $1 and status = $2",
+            warehouse_id,
+            STOCKTAKE_STATUS_COUNTING
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(counting) = counting {
+            return Err(ERPError::AlreadyExists(format!(
+                "这个仓库已经有盘点中的记录#{}",
+                counting.id
+            )));
+        }
+
+        let stocktake = sqlx::query_as!(
+            StocktakeModel,
+            r#"
+            insert into stocktakes (warehouse_id, account_id, status, notes)
+            values ($1, $2, $3, $4)
+            returning *
+            "#,
+            warehouse_id,
+            account_id,
+            STOCKTAKE_STATUS_COUNTING,
+            params.notes.clone().unwrap_or_default()
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "stocktakes",
+                stocktake.id,
+                AUDIT_CREATE,
+                None,
+                snapshot(&stocktake),
+            )
+            .await?;
+        tx.commit().await?;
+
+        self.get_stocktake_dto(stocktake.id).await
+    }
+
+    async fn get_stocktake_list(&self, params: &QueryParams) -> ERPResult<Vec<StocktakeDto>> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select * from stocktakes ");
+        push_filters(&mut sql, params);
+
+        let page = params.page.unwrap_or(1);
+        let page_size = params.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        let offset = (page - 1) * page_size;
+        sql.push(format!(
+            " order by id desc limit {page_size} offset {offset}"
+        ));
+
+        let stocktakes = sql
+            .build_query_as::<StocktakeModel>()
+            .fetch_all(self.db.get_pool())
+            .await?;
+
+        self.to_stocktake_dtos(stocktakes).await
+    }
+
+    async fn get_stocktake_count(&self, params: &QueryParams) -> ERPResult<i32> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("select count(1) from stocktakes ");
+        push_filters(&mut sql, params);
+
+        let count = sql
+            .build_query_as::<(i64,)>()
+            .fetch_one(self.db.get_pool())
+            .await?
+            .0 as i32;
+
+        Ok(count)
+    }
+
+    async fn get_stocktake_detail(&self, id: i32) -> ERPResult<StocktakeDetailDto> {
+        let stocktake = self.get_stocktake_dto(id).await?;
+        let items = sqlx::query_as!(
+            StocktakeItemDto,
+            r#"
+            select si.item_id, i.number, i.name, i.color, i.barcode, si.counted,
+                   si.system_count, si.counted - si.system_count as "variance!"
+            from stocktake_items si, items i
+            where si.item_id = i.id and si.stocktake_id = $1
+            order by i.number, i.color
+            "#,
+            id
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+
+        Ok(StocktakeDetailDto { stocktake, items })
+    }
+
+    async fn get_stocktake_sheet(&self, id: i32) -> ERPResult<Vec<StocktakeSheetRowDto>> {
+        let stocktake = self.get_stocktake_dto(id).await?;
+        let rows = sqlx::query_as!(
+            StocktakeSheetRowDto,
+            r#"
+            select i.number, i.name, i.color, i.barcode,
+                   coalesce(s.count, 0) as "system_count!", si.counted as "counted?"
+            from items i
+            left join item_stock s on s.item_id = i.id and s.warehouse_id = $2
+            left join stocktake_items si on si.item_id = i.id and si.stocktake_id = $1
+            where s.count <> 0 or si.id is not null
+            order by i.number, i.color
+            "#,
+            id,
+            stocktake.warehouse_id
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+
+        Ok(rows)
+    }
+
+    async fn count_items(&self, params: &StocktakeCountParams, account_id: i32) -> ERPResult<()> {
+        if params.items.is_empty() {
+            return Err(ERPError::ParamNeeded("items".to_string()));
+        }
+        if params.items.iter().any(|item| item.counted < 0) {
+            return Err(ERPError::ParamError("盘点数量不能小于0".to_string()));
+        }
+
+        let barcodes = params
+            .items
+            .iter()
+            .filter_map(|item| item.barcode.clone())
+            .filter(|barcode| !barcode.is_empty())
+            .collect::<Vec<_>>();
+        let item_ids = params
+            .items
+            .iter()
+            .filter_map(|item| item.item_id)
+            .collect::<Vec<_>>();
+        let items = sqlx::query!(
+            "select id, barcode from items where id = any($1) or barcode = any($2)",
+            &item_ids,
+            &barcodes
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+        let barcode_to_id = items
+            .iter()
+            .map(|item| (item.barcode.as_str(), item.id))
+            .collect::<HashMap<_, _>>();
+
+        let mut rows = vec![];
+        for item in params.items.iter() {
+            let item_id = match (item.item_id, item.barcode.as_deref()) {
+                (Some(item_id), _) => items
+                    .iter()
+                    .find(|row| row.id == item_id)
+                    .map(|row| row.id)
+                    .ok_or(ERPError::NotFound(format!("商品#{}", item_id)))?,
+                (None, Some(barcode)) if !barcode.is_empty() => *barcode_to_id
+                    .get(barcode)
+                    .ok_or(ERPError::NotFound(format!("条码 {}", barcode)))?,
+                _ => return Err(ERPError::ParamNeeded("item_id/barcode".to_string())),
+            };
+            rows.push((item_id, item.counted));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        let stocktake = lock_counting_stocktake(&mut tx, params.id).await?;
+        save_counted_rows(&mut tx, &stocktake, &rows, account_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn count_items_from_excel(
+        &self,
+        id: i32,
+        sheet: &str,
+        rows: &[StocktakeExcelDto],
+        account_id: i32,
+    ) -> ERPResult<i32> {
+        let barcodes = rows
+            .iter()
+            .map(|row| row.barcode.clone())
+            .filter(|barcode| !barcode.is_empty())
+            .collect::<Vec<_>>();
+        let numbers = rows
+            .iter()
+            .map(|row| row.number.clone())
+            .unique()
+            .collect::<Vec<_>>();
+        let items = sqlx::query!(
+            "select id, number, color, barcode from items where barcode = any($1) or number = any($2)",
+            &barcodes,
+            &numbers
+        )
+        .fetch_all(self.db.get_pool())
+        .await?;
+        let barcode_to_id = items
+            .iter()
+            .map(|item| (item.barcode.as_str(), item.id))
+            .collect::<HashMap<_, _>>();
+        let number_color_to_id = items
+            .iter()
+            .map(|item| ((item.number.as_str(), item.color.as_str()), item.id))
+            .collect::<HashMap<_, _>>();
+
+        let mut errors = RowErrors::new(sheet, &J_TO_NAME);
+        let mut counted_rows = vec![];
+        for row in rows.iter() {
+            let item_id = match row.barcode.is_empty() {
+                false => barcode_to_id.get(row.barcode.as_str()),
+                true => number_color_to_id.get(&(row.number.as_str(), row.color.as_str())),
+            };
+            match item_id {
+                Some(item_id) => counted_rows.push((*item_id, row.counted)),
+                None if !row.barcode.is_empty() => {
+                    errors.push(row.row, 4, &row.barcode, "条码对应的商品不存在")
+                }
+                None => errors.push(row.row, 1, &row.number, "编号+颜色对应的商品不存在"),
+            }
+        }
+        errors.into_result()?;
+        if counted_rows.is_empty() {
+            return Err(ERPError::ExcelError("没有填盘点数量的商品".to_string()));
+        }
+
+        let mut tx = self.db.get_pool().begin().await?;
+        let stocktake = lock_counting_stocktake(&mut tx, id).await?;
+        save_counted_rows(&mut tx, &stocktake, &counted_rows, account_id).await?;
+        tx.commit().await?;
+
+        Ok(counted_rows.len() as i32)
+    }
+
+    async fn post_stocktake(&self, id: i32, account_id: i32) -> ERPResult<StocktakeDto> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let stocktake = lock_counting_stocktake(&mut tx, id).await?;
+
+        // 差异按录入时的库存算, 录入后的出入库已经记在库存里, 过账只补上差异
+        let adjustments = sqlx::query!(
+            r#"
+            select si.item_id, si.counted - si.system_count as "variance!", i.cost
+            from stocktake_items si, items i
+            where si.item_id = i.id and si.stocktake_id = $1 and si.counted <> si.system_count
+            order by si.item_id
+            "#,
+            id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| (row.item_id, row.variance, row.cost))
+        .collect::<Vec<_>>();
+
+        let mut bucket_id = 0;
+        let mut inouts = vec![];
+        if !adjustments.is_empty() {
+            // 一次盘点一条出入库记录, 盘盈为正盘亏为负, 合计不为负算入库
+            let total = adjustments.iter().map(|row| row.1).sum::<i32>();
+            let bucket = self
+                .item_service
+                .add_inout_bucket(
+                    &mut tx,
+                    ItemInOutBucketModal {
+                        id: 0,
+                        account_id,
+                        in_true_out_false: total >= 0,
+                        via: "stocktake".to_string(),
+                        order_id: 0,
+                        warehouse_id: stocktake.warehouse_id,
+                        create_time: Default::default(),
+                    },
+                )
+                .await?;
+            bucket_id = bucket.id;
+            let item_inouts = adjustments
+                .iter()
+                .map(|(item_id, count, cost)| ItemsInOutModel {
+                    id: 0,
+                    bucket_id,
+                    item_id: *item_id,
+                    count: *count,
+                    current_cost: *cost,
+                    current_total: count * cost,
+                })
+                .collect::<Vec<_>>();
+            inouts = self
+                .item_service
+                .add_multiple_items_inouts(&mut tx, &item_inouts)
+                .await?;
+        }
+
+        let posted = sqlx::query_as!(
+            StocktakeModel,
+            r#"
+            update stocktakes set status = $1, bucket_id = $2, close_time = now()
+            where id = $3
+            returning *
+            "#,
+            STOCKTAKE_STATUS_POSTED,
+            bucket_id,
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "stocktakes",
+                id,
+                AUDIT_UPDATE,
+                snapshot(&stocktake),
+                Some(serde_json::json!({
+                    "stocktake": posted,
+                    "inouts": inouts,
+                })),
+            )
+            .await?;
+        tx.commit().await?;
+
+        self.get_stocktake_dto(id).await
+    }
+
+    async fn cancel_stocktake(&self, id: i32, account_id: i32) -> ERPResult<()> {
+        let mut tx = self.db.get_pool().begin().await?;
+        let stocktake = lock_counting_stocktake(&mut tx, id).await?;
+        let cancelled = sqlx::query_as!(
+            StocktakeModel,
+            r#"
+            update stocktakes set status = $1, close_time = now()
+            where id = $2
+            returning *
+            "#,
+            STOCKTAKE_STATUS_CANCELLED,
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        self.audit_service
+            .add_audit_log(
+                &mut tx,
+                account_id,
+                "stocktakes",
+                id,
+                AUDIT_UPDATE,
+                snapshot(&stocktake),
+                snapshot(&cancelled),
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::service::stocktake_service::merge_counted_rows;
+
+    #[test]
+    fn test_merge_counted_rows() {
+        let (item_ids, counts) = merge_counted_rows(&[(3, 5), (1, 2), (3, 7)]);
+        assert_eq!(item_ids, vec![1, 3]);
+        assert_eq!(counts, vec![2, 7]);
+    }
+}
@@ -0,0 +1,16 @@
+use crate::config::database::Database;
+use crate::service::audit_service::{AuditService, AuditServiceTrait};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AuditState {
+    pub audit_service: AuditService,
+}
+
+impl AuditState {
+    pub fn new(db: &Arc<Database>) -> Self {
+        Self {
+            audit_service: AuditService::new(db),
+        }
+    }
+}
@@ -1,10 +1,16 @@
 use crate::config::database::Database;
+use crate::excel::progress::ImportProgressMap;
+use crate::service::audit_service::{AuditService, AuditServiceTrait};
 use crate::service::cates_service::{CateService, CateServiceTrait};
 use crate::service::embryo_service::{EmbryoService, EmbryoServiceTrait};
+use crate::service::excel_template_service::{ExcelTemplateService, ExcelTemplateServiceTrait};
+use crate::service::import_job_service::{ImportJobService, ImportJobServiceTrait};
 use crate::service::item_service::{ItemService, ItemServiceTrait};
 use crate::service::order_service::{OrderService, OrderServiceTrait};
 use crate::service::settings_service::{SettingsService, SettingsServiceTrait};
+use crate::service::stock_service::{StockService, StockServiceTrait};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 #[derive(Clone)]
 pub struct ExcelState {
@@ -13,7 +19,13 @@ pub struct ExcelState {
     pub embryo_service: EmbryoService,
     pub settings_service: SettingsService,
     pub order_service: OrderService,
+    pub audit_service: AuditService,
+    pub import_job_service: ImportJobService,
+    pub excel_template_service: ExcelTemplateService,
+    pub stock_service: StockService,
     pub db: Arc<Database>,
+    pub import_queue: Arc<Semaphore>, // 后台导入一次只跑一个, 其他的排队
+    pub import_progress: ImportProgressMap,
 }
 
 impl ExcelState {
@@ -24,7 +36,13 @@ impl ExcelState {
             embryo_service: EmbryoService::new(db),
             settings_service: SettingsService::new(db),
             order_service: OrderService::new(db),
+            audit_service: AuditService::new(db),
+            import_job_service: ImportJobService::new(db),
+            excel_template_service: ExcelTemplateService::new(db),
+            stock_service: StockService::new(db),
             db: Arc::clone(db),
+            import_queue: Arc::new(Semaphore::new(1)),
+            import_progress: ImportProgressMap::default(),
         }
     }
 }
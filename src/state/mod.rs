@@ -1,4 +1,5 @@
 pub mod account_state;
+pub mod audit_state;
 pub mod cate_state;
 pub mod customer_state;
 pub mod embryo_state;
@@ -6,3 +7,4 @@ pub mod excel_state;
 pub mod item_state;
 pub mod order_state;
 pub mod settings_state;
+pub mod stocktake_state;
@@ -0,0 +1,16 @@
+use crate::config::database::Database;
+use crate::service::stocktake_service::{StocktakeService, StocktakeServiceTrait};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct StocktakeState {
+    pub stocktake_service: StocktakeService,
+}
+
+impl StocktakeState {
+    pub fn new(db: &Arc<Database>) -> Self {
+        Self {
+            stocktake_service: StocktakeService::new(db),
+        }
+    }
+}